@@ -0,0 +1,780 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serenity::client::Context;
+use serenity::prelude::TypeMapKey;
+use tokio_postgres::config::SslMode;
+
+const DEFAULT_CONFIG_PATH: &str = "yukino.toml";
+const DEFAULT_RSS_URL: &str = "https://subsplease.org/rss/?r=1080";
+const DEFAULT_SCHEDULE_URL: &str = "https://subsplease.org/api/?f=schedule";
+const DEFAULT_REDIRECT_BASE_URL: &str = "https://yukino.onrender.com/";
+const DEFAULT_TIMEZONE: &str = "Europe/Berlin";
+const DEFAULT_HTTP_BIND: &str = "0.0.0.0:8080";
+const DEFAULT_MAL_API_URL: &str = "https://api.myanimelist.net/v2";
+const DEFAULT_ANILIST_API_URL: &str = "https://graphql.anilist.co";
+const DEFAULT_KITSU_API_URL: &str = "https://kitsu.io/api/edge";
+const DEFAULT_PREMIUMIZE_API_URL: &str = "https://www.premiumize.me/api";
+const DEFAULT_TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// Validated settings of the bot. Built once in `main` and handed to everything that needs it.
+pub struct Config {
+    pub discord_token: String,
+    pub database: DatabaseConfig,
+    pub feeds: FeedConfig,
+    pub http: HttpConfig,
+    pub trackers: TrackerConfig,
+    pub premiumize: PremiumizeConfig,
+    pub channels: ChannelConfig,
+    /// Key that secrets of users, like api keys and passwords, are encrypted with in the db.
+    /// Without one nobody can hand the bot such a secret.
+    pub encryption_key: Option<[u8; 32]>,
+    /// Whether users may point webhooks, download clients and push servers at private or
+    /// loopback addresses. Only for instances that serve nobody but the owner's network.
+    pub allow_private_urls: bool,
+    /// Static page that redirects to the link given as `?r=<link>`. Download links go through
+    /// it when there's no `http.public_url` to serve the `/r/<token>` redirects from.
+    pub redirect_base_url: String,
+    /// How long a download redirect keeps working after the last release that used it.
+    pub redirect_expiry: Duration,
+    /// How long the watchlist of someone who unregistered is kept, so registering again brings
+    /// it back. Zero deletes it right away.
+    pub unregister_grace: Duration,
+    /// Discord user ids that may change data shared by everyone, like the list site ids of
+    /// shows.
+    pub admins: Vec<u64>,
+    pub schedule_timezone: Tz,
+    /// How long running jobs get to finish after a shutdown signal.
+    pub shutdown_timeout: Duration,
+}
+
+pub struct DatabaseConfig {
+    pub connection: tokio_postgres::Config,
+    pub tls: TlsMode,
+    /// PEM encoded certificates trusted in addition to the system roots.
+    pub ca_bundle: Option<Vec<u8>>,
+}
+
+pub struct FeedConfig {
+    pub rss_url: String,
+    pub rss_refresh_secs: u32,
+    pub schedule_url: String,
+}
+
+pub struct HttpConfig {
+    pub bind: SocketAddr,
+    /// Address the http server is reachable at from the outside. Links to it, like
+    /// calendar feeds, are only handed out when this is set.
+    pub public_url: Option<String>,
+}
+
+/// Premiumize api used to start transfers of new releases.
+pub struct PremiumizeConfig {
+    pub api_url: String,
+}
+
+/// Accounts the bot sends releases from on other services than Discord. Users can only
+/// link a kind of channel when its account is set up. ntfy and gotify need nothing here.
+pub struct ChannelConfig {
+    pub telegram: Option<TelegramConfig>,
+    pub matrix: Option<MatrixConfig>,
+    pub smtp: Option<SmtpConfig>,
+}
+
+pub struct TelegramConfig {
+    pub api_url: String,
+    pub bot_token: String,
+}
+
+/// A Matrix account of the bot, which joins the rooms users invite it to.
+pub struct MatrixConfig {
+    pub homeserver_url: String,
+    pub access_token: String,
+}
+
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender of the mails, like `Yukino <yukino@example.com>`.
+    pub from: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmtpTls {
+    None,
+    StartTls,
+    Tls,
+}
+
+/// Endpoints of the anime list sites. MyAnimeList needs a client id of a registered app.
+pub struct TrackerConfig {
+    pub mal_api_url: String,
+    pub mal_client_id: Option<String>,
+    pub anilist_api_url: String,
+    pub kitsu_api_url: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TlsMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyFull,
+}
+
+impl TlsMode {
+    fn parse(mode: &str) -> Option<TlsMode> {
+        match mode.to_ascii_lowercase().as_str() {
+            "disable" => Some(TlsMode::Disable),
+            "prefer" => Some(TlsMode::Prefer),
+            "require" => Some(TlsMode::Require),
+            "verify-full" => Some(TlsMode::VerifyFull),
+            _ => None
+        }
+    }
+
+    fn ssl_mode(&self) -> SslMode {
+        match self {
+            TlsMode::Disable => SslMode::Disable,
+            TlsMode::Prefer => SslMode::Prefer,
+            TlsMode::Require | TlsMode::VerifyFull => SslMode::Require,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    Read(String, String),
+    Parse(String),
+    Missing(&'static str),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "couldn't read config file {}: {}", path, e),
+            ConfigError::Parse(e) => write!(f, "invalid config file: {}", e),
+            ConfigError::Missing(key) => write!(f, "missing setting `{}`", key),
+            ConfigError::Invalid(key, reason) => write!(f, "invalid value for `{}`: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl TypeMapKey for Config {
+    type Value = Arc<Config>;
+}
+
+impl Config {
+    /// Reads the file named by `YUKINO_CONFIG` (or `yukino.toml` if present), applies
+    /// environment overrides and validates the result.
+    pub fn load() -> Result<Config, ConfigError> {
+        let mut raw = match env::var("YUKINO_CONFIG") {
+            Ok(path) => RawConfig::from_file(&path)?,
+            Err(_) if fs::metadata(DEFAULT_CONFIG_PATH).is_ok() => RawConfig::from_file(DEFAULT_CONFIG_PATH)?,
+            Err(_) => RawConfig::default(),
+        };
+        raw.apply_env(|key| env::var(key).ok())?;
+        raw.validate()
+    }
+
+    pub async fn from_context(ctx: &Context) -> Arc<Config> {
+        ctx.data.read().await.get::<Config>().cloned().expect("config is inserted at startup")
+    }
+}
+
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    discord_token: Option<String>,
+    redirect_base_url: Option<String>,
+    redirect_expiry_days: Option<u64>,
+    unregister_grace_days: Option<u64>,
+    encryption_key: Option<String>,
+    allow_private_urls: Option<bool>,
+    shutdown_timeout_secs: Option<u64>,
+    admins: Option<Vec<u64>>,
+    #[serde(default)]
+    rss: RawRss,
+    #[serde(default)]
+    subsplease: RawSubsPlease,
+    #[serde(default)]
+    database: RawDatabase,
+    #[serde(default)]
+    http: RawHttp,
+    #[serde(default)]
+    trackers: RawTrackers,
+    #[serde(default)]
+    premiumize: RawPremiumize,
+    #[serde(default)]
+    telegram: RawTelegram,
+    #[serde(default)]
+    matrix: RawMatrix,
+    #[serde(default)]
+    smtp: RawSmtp,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRss {
+    url: Option<String>,
+    refresh_secs: Option<u32>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSubsPlease {
+    schedule_url: Option<String>,
+    timezone: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDatabase {
+    url: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    user: Option<String>,
+    name: Option<String>,
+    password: Option<String>,
+    tls: Option<String>,
+    ca_file: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHttp {
+    bind: Option<String>,
+    public_url: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTrackers {
+    mal_api_url: Option<String>,
+    mal_client_id: Option<String>,
+    anilist_api_url: Option<String>,
+    kitsu_api_url: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPremiumize {
+    api_url: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTelegram {
+    api_url: Option<String>,
+    bot_token: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMatrix {
+    homeserver_url: Option<String>,
+    access_token: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSmtp {
+    host: Option<String>,
+    port: Option<u16>,
+    tls: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    from: Option<String>,
+}
+
+impl RawConfig {
+    fn from_file(path: &str) -> Result<RawConfig, ConfigError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Read(path.to_string(), e.to_string()))?;
+        RawConfig::from_toml(&content)
+    }
+
+    fn from_toml(content: &str) -> Result<RawConfig, ConfigError> {
+        toml::from_str(content).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    /// Environment variables win over the file. The names are the ones the bot used
+    /// before it had a config file.
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, lookup: F) -> Result<(), ConfigError> {
+        override_with(&mut self.discord_token, lookup("DISCORD_TOKEN"));
+        override_with(&mut self.redirect_base_url, lookup("REDIRECT_BASE_URL"));
+        override_with(&mut self.redirect_expiry_days,
+                      parse_env("REDIRECT_EXPIRY_DAYS", lookup("REDIRECT_EXPIRY_DAYS"))?);
+        override_with(&mut self.unregister_grace_days,
+                      parse_env("UNREGISTER_GRACE_DAYS", lookup("UNREGISTER_GRACE_DAYS"))?);
+        override_with(&mut self.encryption_key, lookup("ENCRYPTION_KEY"));
+        override_with(&mut self.allow_private_urls,
+                      parse_flag("ALLOW_PRIVATE_URLS", lookup("ALLOW_PRIVATE_URLS"))?);
+        override_with(&mut self.shutdown_timeout_secs, parse_env("SHUTDOWN_TIMEOUT", lookup("SHUTDOWN_TIMEOUT"))?);
+        override_with(&mut self.admins, parse_ids("ADMINS", lookup("ADMINS"))?);
+        override_with(&mut self.rss.url, lookup("RSS_LINK"));
+        override_with(&mut self.rss.refresh_secs, parse_env("RSS_REFRESH", lookup("RSS_REFRESH"))?);
+        override_with(&mut self.subsplease.schedule_url, lookup("SCHEDULE_URL"));
+        override_with(&mut self.subsplease.timezone, lookup("SCHEDULE_TZ"));
+        override_with(&mut self.database.url, lookup("DATABASE_URL"));
+        override_with(&mut self.database.host, lookup("DB_IP"));
+        override_with(&mut self.database.port, parse_env("DB_PORT", lookup("DB_PORT"))?);
+        override_with(&mut self.database.user, lookup("DB_USER"));
+        override_with(&mut self.database.name, lookup("DB_NAME"));
+        override_with(&mut self.database.password, lookup("DB_PW"));
+        override_with(&mut self.database.tls, lookup("DB_TLS"));
+        override_with(&mut self.database.ca_file, lookup("DB_CA_FILE"));
+        override_with(&mut self.http.bind, lookup("HTTP_BIND"));
+        override_with(&mut self.http.public_url, lookup("PUBLIC_URL"));
+        override_with(&mut self.trackers.mal_api_url, lookup("MAL_API_URL"));
+        override_with(&mut self.trackers.mal_client_id, lookup("MAL_CLIENT_ID"));
+        override_with(&mut self.trackers.anilist_api_url, lookup("ANILIST_API_URL"));
+        override_with(&mut self.trackers.kitsu_api_url, lookup("KITSU_API_URL"));
+        override_with(&mut self.premiumize.api_url, lookup("PREMIUMIZE_API_URL"));
+        override_with(&mut self.telegram.api_url, lookup("TELEGRAM_API_URL"));
+        override_with(&mut self.telegram.bot_token, lookup("TELEGRAM_BOT_TOKEN"));
+        override_with(&mut self.matrix.homeserver_url, lookup("MATRIX_HOMESERVER_URL"));
+        override_with(&mut self.matrix.access_token, lookup("MATRIX_ACCESS_TOKEN"));
+        override_with(&mut self.smtp.host, lookup("SMTP_HOST"));
+        override_with(&mut self.smtp.port, parse_env("SMTP_PORT", lookup("SMTP_PORT"))?);
+        override_with(&mut self.smtp.tls, lookup("SMTP_TLS"));
+        override_with(&mut self.smtp.username, lookup("SMTP_USER"));
+        override_with(&mut self.smtp.password, lookup("SMTP_PASSWORD"));
+        override_with(&mut self.smtp.from, lookup("SMTP_FROM"));
+        Ok(())
+    }
+
+    fn validate(self) -> Result<Config, ConfigError> {
+        let discord_token = required(self.discord_token, "discord_token")?;
+        let redirect_expiry_days = self.redirect_expiry_days.unwrap_or(30);
+        if redirect_expiry_days == 0 {
+            return Err(ConfigError::Invalid("redirect_expiry_days", "must be greater than 0".to_string()));
+        }
+
+        let rss_url = self.rss.url.unwrap_or_else(|| DEFAULT_RSS_URL.to_string());
+        check_url(&rss_url, "rss.url")?;
+        let rss_refresh_secs = required(self.rss.refresh_secs, "rss.refresh_secs")?;
+        if rss_refresh_secs == 0 {
+            return Err(ConfigError::Invalid("rss.refresh_secs", "must be greater than 0".to_string()));
+        }
+        let schedule_url = self.subsplease.schedule_url
+            .unwrap_or_else(|| DEFAULT_SCHEDULE_URL.to_string());
+        check_url(&schedule_url, "subsplease.schedule_url")?;
+        let timezone = self.subsplease.timezone.unwrap_or_else(|| DEFAULT_TIMEZONE.to_string());
+        let schedule_timezone: Tz = timezone.parse()
+            .map_err(|_| ConfigError::Invalid("subsplease.timezone", format!("unknown timezone {}", timezone)))?;
+
+        let database = self.database.validate()?;
+        let bind = self.http.bind.unwrap_or_else(|| DEFAULT_HTTP_BIND.to_string());
+        let bind = bind.parse()
+            .map_err(|_| ConfigError::Invalid("http.bind", format!("{} is not a socket address", bind)))?;
+        let public_url = self.http.public_url
+            .filter(|url| !url.is_empty())
+            .map(|url| url.trim_end_matches('/').to_string());
+        if let Some(url) = &public_url {
+            check_url(url, "http.public_url")?;
+        }
+        let redirect_base_url = self.redirect_base_url
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| DEFAULT_REDIRECT_BASE_URL.to_string());
+        check_url(&redirect_base_url, "redirect_base_url")?;
+        let mal_api_url = self.trackers.mal_api_url.unwrap_or_else(|| DEFAULT_MAL_API_URL.to_string());
+        check_url(&mal_api_url, "trackers.mal_api_url")?;
+        let anilist_api_url = self.trackers.anilist_api_url
+            .unwrap_or_else(|| DEFAULT_ANILIST_API_URL.to_string());
+        check_url(&anilist_api_url, "trackers.anilist_api_url")?;
+        let kitsu_api_url = self.trackers.kitsu_api_url.unwrap_or_else(|| DEFAULT_KITSU_API_URL.to_string());
+        check_url(&kitsu_api_url, "trackers.kitsu_api_url")?;
+        let trackers = TrackerConfig {
+            mal_api_url,
+            mal_client_id: self.trackers.mal_client_id.filter(|id| !id.is_empty()),
+            anilist_api_url,
+            kitsu_api_url,
+        };
+
+        let premiumize_api_url = self.premiumize.api_url
+            .unwrap_or_else(|| DEFAULT_PREMIUMIZE_API_URL.to_string());
+        check_url(&premiumize_api_url, "premiumize.api_url")?;
+        let premiumize = PremiumizeConfig { api_url: premiumize_api_url };
+        let channels = ChannelConfig {
+            telegram: self.telegram.validate()?,
+            matrix: self.matrix.validate()?,
+            smtp: self.smtp.validate()?,
+        };
+        let encryption_key = match self.encryption_key.filter(|k| !k.is_empty()) {
+            Some(key) => Some(parse_hex_key(&key).ok_or_else(|| ConfigError::Invalid("encryption_key",
+                "must be 32 bytes written as 64 hex digits".to_string()))?),
+            None => None,
+        };
+
+        Ok(Config {
+            discord_token,
+            database,
+            feeds: FeedConfig { rss_url, rss_refresh_secs, schedule_url },
+            http: HttpConfig { bind, public_url },
+            trackers,
+            premiumize,
+            channels,
+            encryption_key,
+            allow_private_urls: self.allow_private_urls.unwrap_or(false),
+            redirect_base_url,
+            redirect_expiry: Duration::from_secs(redirect_expiry_days * 24 * 60 * 60),
+            unregister_grace: Duration::from_secs(self.unregister_grace_days.unwrap_or(7) * 24 * 60 * 60),
+            admins: self.admins.unwrap_or_default(),
+            schedule_timezone,
+            shutdown_timeout: Duration::from_secs(self.shutdown_timeout_secs.unwrap_or(25)),
+        })
+    }
+}
+
+impl RawDatabase {
+    fn validate(self) -> Result<DatabaseConfig, ConfigError> {
+        let explicit_tls = match self.tls {
+            Some(mode) => Some(TlsMode::parse(&mode).ok_or_else(|| ConfigError::Invalid("database.tls",
+                format!("{} is not one of disable, prefer, require, verify-full", mode)))?),
+            None => None
+        };
+        let (mut connection, url_tls, url_ca_file) = match self.url {
+            Some(url) => parse_database_url(&url).map_err(|e| ConfigError::Invalid("database.url", e))?,
+            None => {
+                let port = self.port.unwrap_or(5432);
+                if port == 0 {
+                    return Err(ConfigError::Invalid("database.port", "must not be 0".to_string()));
+                }
+                let mut connection = tokio_postgres::Config::new();
+                connection.host(&required(self.host, "database.host")?)
+                    .port(port)
+                    .user(&required(self.user, "database.user")?)
+                    .dbname(&required(self.name, "database.name")?)
+                    .password(required(self.password, "database.password")?);
+                (connection, None, None)
+            }
+        };
+        let tls = explicit_tls.or(url_tls).unwrap_or(TlsMode::Disable);
+        connection.ssl_mode(tls.ssl_mode());
+
+        let ca_bundle = match self.ca_file.or(url_ca_file) {
+            Some(_) if tls == TlsMode::Disable =>
+                return Err(ConfigError::Invalid("database.ca_file", "requires database.tls to be enabled".to_string())),
+            Some(path) => Some(fs::read(&path).map_err(|e| ConfigError::Read(path, e.to_string()))?),
+            None => None
+        };
+        Ok(DatabaseConfig { connection, tls, ca_bundle })
+    }
+}
+
+impl RawTelegram {
+    /// None without a bot token.
+    fn validate(self) -> Result<Option<TelegramConfig>, ConfigError> {
+        let bot_token = match self.bot_token.filter(|t| !t.is_empty()) {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        let api_url = self.api_url.unwrap_or_else(|| DEFAULT_TELEGRAM_API_URL.to_string())
+            .trim_end_matches('/').to_string();
+        check_url(&api_url, "telegram.api_url")?;
+        Ok(Some(TelegramConfig { api_url, bot_token }))
+    }
+}
+
+impl RawMatrix {
+    /// None without a homeserver.
+    fn validate(self) -> Result<Option<MatrixConfig>, ConfigError> {
+        let homeserver_url = match self.homeserver_url.filter(|url| !url.is_empty()) {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => return Ok(None),
+        };
+        check_url(&homeserver_url, "matrix.homeserver_url")?;
+        let access_token = required(self.access_token.filter(|t| !t.is_empty()), "matrix.access_token")?;
+        Ok(Some(MatrixConfig { homeserver_url, access_token }))
+    }
+}
+
+impl RawSmtp {
+    /// None without a host.
+    fn validate(self) -> Result<Option<SmtpConfig>, ConfigError> {
+        let host = match self.host.filter(|host| !host.is_empty()) {
+            Some(host) => host,
+            None => return Ok(None),
+        };
+        let tls = match self.tls.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("starttls") => SmtpTls::StartTls,
+            Some("tls") => SmtpTls::Tls,
+            Some("none") => SmtpTls::None,
+            Some(mode) => return Err(ConfigError::Invalid("smtp.tls",
+                format!("{} is not one of starttls, tls, none", mode))),
+        };
+        let port = self.port.unwrap_or(match tls {
+            SmtpTls::Tls => 465,
+            SmtpTls::StartTls => 587,
+            SmtpTls::None => 25,
+        });
+        let from = required(self.from, "smtp.from")?;
+        if from.parse::<lettre::message::Mailbox>().is_err() {
+            return Err(ConfigError::Invalid("smtp.from", format!("{} is not a mail address", from)));
+        }
+        Ok(Some(SmtpConfig {
+            host,
+            port,
+            tls,
+            username: self.username.filter(|u| !u.is_empty()),
+            password: self.password,
+            from,
+        }))
+    }
+}
+
+/// Parses a libpq style connection string. `sslmode` and `sslrootcert` are taken out of
+/// url style strings, because tokio-postgres doesn't know `verify-full` and certificates
+/// are handled by our TLS connector.
+fn parse_database_url(url: &str) -> Result<(tokio_postgres::Config, Option<TlsMode>, Option<String>), String> {
+    if !(url.starts_with("postgres://") || url.starts_with("postgresql://")) {
+        let connection = url.parse().map_err(|e: tokio_postgres::Error| e.to_string())?;
+        return Ok((connection, None, None));
+    }
+    let mut parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    let (mut tls, mut ca_file) = (None, None);
+    let mut remaining = Vec::new();
+    for (key, value) in parsed.query_pairs() {
+        match key.as_ref() {
+            "sslmode" => tls = Some(TlsMode::parse(&value)
+                .ok_or_else(|| format!("unsupported sslmode {}", value))?),
+            "sslrootcert" => ca_file = Some(value.to_string()),
+            _ => remaining.push((key.to_string(), value.to_string())),
+        }
+    }
+    if remaining.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(remaining);
+    }
+    let connection = parsed.as_str().parse().map_err(|e: tokio_postgres::Error| e.to_string())?;
+    Ok((connection, tls, ca_file))
+}
+
+fn override_with<T>(setting: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *setting = value;
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(key: &'static str, value: Option<String>) -> Result<Option<T>, ConfigError> {
+    match value {
+        Some(v) => v.trim().parse().map(Some)
+            .map_err(|_| ConfigError::Invalid(key, format!("{} is not a valid number", v))),
+        None => Ok(None)
+    }
+}
+
+fn parse_flag(key: &'static str, value: Option<String>) -> Result<Option<bool>, ConfigError> {
+    match value.as_deref().map(|v| v.trim().to_lowercase()).as_deref() {
+        Some("true") | Some("1") => Ok(Some(true)),
+        Some("false") | Some("0") => Ok(Some(false)),
+        Some(_) => Err(ConfigError::Invalid(key, format!("{} is neither true nor false", value.unwrap_or_default()))),
+        None => Ok(None),
+    }
+}
+
+/// A comma separated list of Discord user ids.
+fn parse_ids(key: &'static str, value: Option<String>) -> Result<Option<Vec<u64>>, ConfigError> {
+    match value {
+        Some(v) => v.split(',').map(str::trim).filter(|id| !id.is_empty())
+            .map(|id| id.parse().map_err(|_| ConfigError::Invalid(key, format!("{} is not a user id", id))))
+            .collect::<Result<_, _>>().map(Some),
+        None => Ok(None)
+    }
+}
+
+fn required<T>(value: Option<T>, key: &'static str) -> Result<T, ConfigError> {
+    value.ok_or(ConfigError::Missing(key))
+}
+
+fn parse_hex_key(key: &str) -> Option<[u8; 32]> {
+    let key = key.trim();
+    if key.len() != 64 || !key.is_ascii() { return None; }
+    let mut bytes = [0; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&key[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn check_url(url: &str, key: &'static str) -> Result<(), ConfigError> {
+    match reqwest::Url::parse(url) {
+        Ok(u) if u.scheme() == "http" || u.scheme() == "https" => Ok(()),
+        Ok(_) => Err(ConfigError::Invalid(key, format!("{} is not a http(s) url", url))),
+        Err(e) => Err(ConfigError::Invalid(key, format!("{}: {}", url, e)))
+    }
+}
+
+
+#[cfg(test)]
+const TEST_CONFIG: &str = r##"
+    discord_token = "token"
+
+    [rss]
+    refresh_secs = 60
+
+    [database]
+    host = "localhost"
+    user = "yukino"
+    name = "yukino"
+    password = "secret"
+"##;
+
+#[cfg(test)]
+pub fn test_config() -> Config {
+    RawConfig::from_toml(TEST_CONFIG).unwrap().validate().unwrap()
+}
+
+#[test]
+fn test_config_from_toml() {
+    let config = test_config();
+    assert_eq!(config.discord_token, "token");
+    assert_eq!(config.feeds.rss_refresh_secs, 60);
+    assert_eq!(config.feeds.rss_url, DEFAULT_RSS_URL);
+    assert_eq!(config.database.connection.get_ports(), &[5432]);
+    assert_eq!(config.database.connection.get_ssl_mode(), SslMode::Disable);
+    assert_eq!(config.database.tls, TlsMode::Disable);
+    assert_eq!(config.schedule_timezone, chrono_tz::Europe::Berlin);
+    assert_eq!(config.http.bind, "0.0.0.0:8080".parse().unwrap());
+    assert_eq!(config.http.public_url, None);
+    assert_eq!(config.redirect_base_url, "https://yukino.onrender.com/");
+    assert_eq!(config.redirect_expiry, Duration::from_secs(30 * 24 * 60 * 60));
+    assert_eq!(config.unregister_grace, Duration::from_secs(7 * 24 * 60 * 60));
+    assert_eq!(config.premiumize.api_url, DEFAULT_PREMIUMIZE_API_URL);
+    assert_eq!(config.encryption_key, None);
+    assert!(!config.allow_private_urls);
+    assert!(config.admins.is_empty());
+    assert!(config.channels.telegram.is_none() && config.channels.matrix.is_none() && config.channels.smtp.is_none());
+}
+
+#[test]
+fn test_channel_config() {
+    let config = RawConfig::from_toml(&format!("{}{}", TEST_CONFIG, r##"
+        [telegram]
+        bot_token = "123:abc"
+
+        [matrix]
+        homeserver_url = "https://matrix.example.org/"
+        access_token = "syt_token"
+
+        [smtp]
+        host = "mail.example.org"
+        tls = "tls"
+        username = "yukino"
+        password = "secret"
+        from = "Yukino <yukino@example.org>"
+    "##)).unwrap().validate().unwrap();
+    let telegram = config.channels.telegram.unwrap();
+    assert_eq!((telegram.api_url.as_str(), telegram.bot_token.as_str()), (DEFAULT_TELEGRAM_API_URL, "123:abc"));
+    assert_eq!(config.channels.matrix.unwrap().homeserver_url, "https://matrix.example.org");
+    let smtp = config.channels.smtp.unwrap();
+    assert_eq!((smtp.port, smtp.tls, smtp.username.as_deref()), (465, SmtpTls::Tls, Some("yukino")));
+
+    let mut raw = RawConfig::from_toml(TEST_CONFIG).unwrap();
+    raw.apply_env(|key| match key {
+        "SMTP_HOST" => Some("localhost".to_string()),
+        "SMTP_FROM" => Some("not an address".to_string()),
+        _ => None
+    }).unwrap();
+    assert!(matches!(raw.validate().err(), Some(ConfigError::Invalid("smtp.from", _))));
+    let no_token = RawConfig::from_toml(&format!("{}\n[matrix]\nhomeserver_url = \"https://matrix.org\"", TEST_CONFIG))
+        .unwrap().validate();
+    assert_eq!(no_token.err(), Some(ConfigError::Missing("matrix.access_token")));
+}
+
+#[test]
+fn test_config_env_overrides() {
+    let mut raw = RawConfig::from_toml(TEST_CONFIG).unwrap();
+    raw.apply_env(|key| match key {
+        "DB_IP" => Some("db.example.com".to_string()),
+        "DB_PORT" => Some("6543".to_string()),
+        "DB_TLS" => Some("verify-full".to_string()),
+        "SCHEDULE_TZ" => Some("Asia/Tokyo".to_string()),
+        "PUBLIC_URL" => Some("https://yukino.example.com/".to_string()),
+        "REDIRECT_EXPIRY_DAYS" => Some("7".to_string()),
+        "UNREGISTER_GRACE_DAYS" => Some("0".to_string()),
+        "ALLOW_PRIVATE_URLS" => Some("1".to_string()),
+        "ENCRYPTION_KEY" => Some(format!("00ff{}", "a".repeat(60))),
+        "ADMINS" => Some("123, 456".to_string()),
+        _ => None
+    }).unwrap();
+    let config = raw.validate().unwrap();
+    assert_eq!(config.database.connection.get_hosts(),
+               &[tokio_postgres::config::Host::Tcp("db.example.com".to_string())]);
+    assert_eq!(config.database.connection.get_ports(), &[6543]);
+    assert_eq!(config.database.tls, TlsMode::VerifyFull);
+    assert_eq!(config.database.connection.get_ssl_mode(), SslMode::Require);
+    assert_eq!(config.schedule_timezone, chrono_tz::Asia::Tokyo);
+    assert_eq!(config.http.public_url.as_deref(), Some("https://yukino.example.com"));
+    assert_eq!(config.redirect_base_url, DEFAULT_REDIRECT_BASE_URL);
+    assert_eq!(config.redirect_expiry, Duration::from_secs(7 * 24 * 60 * 60));
+    assert_eq!(config.unregister_grace, Duration::ZERO);
+    assert!(config.allow_private_urls);
+    assert_eq!(config.admins, vec![123, 456]);
+    let key = config.encryption_key.unwrap();
+    assert_eq!((key[0], key[1], key[31]), (0x00, 0xff, 0xaa));
+
+    let mut raw = RawConfig::from_toml(TEST_CONFIG).unwrap();
+    let res = raw.apply_env(|key| if key == "RSS_REFRESH" { Some("soon".to_string()) } else { None });
+    assert_eq!(res, Err(ConfigError::Invalid("RSS_REFRESH", "soon is not a valid number".to_string())));
+    let res = raw.apply_env(|key| if key == "ADMINS" { Some("123,me".to_string()) } else { None });
+    assert_eq!(res, Err(ConfigError::Invalid("ADMINS", "me is not a user id".to_string())));
+}
+
+#[test]
+fn test_database_url() {
+    let mut raw = RawConfig::from_toml(TEST_CONFIG).unwrap();
+    raw.apply_env(|key| if key == "DATABASE_URL" {
+        Some("postgres://bot:pw@pg.example.com:25060/yukino?sslmode=require&connect_timeout=10".to_string())
+    } else { None }).unwrap();
+    let config = raw.validate().unwrap();
+    let connection = &config.database.connection;
+    assert_eq!(connection.get_hosts(), &[tokio_postgres::config::Host::Tcp("pg.example.com".to_string())]);
+    assert_eq!(connection.get_ports(), &[25060]);
+    assert_eq!(connection.get_user(), Some("bot"));
+    assert_eq!(connection.get_dbname(), Some("yukino"));
+    assert_eq!(connection.get_connect_timeout(), Some(&std::time::Duration::from_secs(10)));
+    assert_eq!(config.database.tls, TlsMode::Require);
+
+    let (_, tls, ca_file) = parse_database_url(
+        "postgresql://bot@localhost/yukino?sslmode=verify-full&sslrootcert=/etc/ssl/pg.pem").unwrap();
+    assert_eq!(tls, Some(TlsMode::VerifyFull));
+    assert_eq!(ca_file.as_deref(), Some("/etc/ssl/pg.pem"));
+    assert!(parse_database_url("postgres://localhost/yukino?sslmode=allow").is_err());
+    assert!(parse_database_url("host=localhost user=bot sslmode=require").unwrap().0.get_ssl_mode() == SslMode::Require);
+}
+
+#[test]
+fn test_config_validation() {
+    let missing_token = RawConfig::from_toml(&TEST_CONFIG.replace("discord_token = \"token\"", ""))
+        .unwrap().validate();
+    assert_eq!(missing_token.err(), Some(ConfigError::Missing("discord_token")));
+    let bad_tz = RawConfig::from_toml(&format!("{}\n[subsplease]\ntimezone = \"Mars/Olympus\"", TEST_CONFIG))
+        .unwrap().validate();
+    assert!(matches!(bad_tz.err(), Some(ConfigError::Invalid("subsplease.timezone", _))));
+    let bad_url = RawConfig::from_toml(&format!("redirect_base_url = \"magnet:?xt\"\n{}", TEST_CONFIG))
+        .unwrap().validate();
+    assert!(matches!(bad_url.err(), Some(ConfigError::Invalid("redirect_base_url", _))));
+    let short_key = RawConfig::from_toml(&format!("encryption_key = \"abcd\"\n{}", TEST_CONFIG))
+        .unwrap().validate();
+    assert!(matches!(short_key.err(), Some(ConfigError::Invalid("encryption_key", _))));
+    assert!(matches!(RawConfig::from_toml("[rss]\nrefresh = 1"), Err(ConfigError::Parse(_))));
+    let ca_without_tls = RawConfig::from_toml(&TEST_CONFIG.replace("[database]", "[database]\nca_file = \"ca.pem\""))
+        .unwrap().validate();
+    assert!(matches!(ca_without_tls.err(), Some(ConfigError::Invalid("database.ca_file", _))));
+}
@@ -0,0 +1,123 @@
+use reqwest::header::COOKIE;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serenity::async_trait;
+
+use super::{http_client, request_error, session_cookie, ClientSettings, DownloadError, DownloadSink};
+
+/// The JSON-RPC api of the Deluge web ui. It only has a password, the username is ignored.
+pub struct Deluge(pub ClientSettings);
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Value,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+impl Deluge {
+    async fn call(&self, client: &reqwest::Client, cookie: Option<&str>, method: &str,
+                  params: Value) -> Result<(Value, Option<String>), DownloadError> {
+        let mut req = client.post(format!("{}/json", self.0.url))
+            .json(&json!({ "method": method, "params": params, "id": 1 }));
+        if let Some(cookie) = cookie {
+            req = req.header(COOKIE, cookie);
+        }
+        let res = req.send().await.map_err(request_error)?;
+        match res.status().as_u16() {
+            200 => {}
+            code => return Err(DownloadError::Request(format!("Deluge answered with {}", code))),
+        }
+        let cookie = session_cookie(&res, "_session_id");
+        let res: RpcResponse = res.json().await.map_err(|e| DownloadError::InvalidResponse(e.to_string()))?;
+        match res.error {
+            Some(error) => Err(DownloadError::Rejected(format!("Deluge says: {}", error.message))),
+            None => Ok((res.result, cookie)),
+        }
+    }
+
+    /// Logs in and makes sure the web ui is connected to a daemon. Returns the session cookie.
+    async fn login(&self, client: &reqwest::Client) -> Result<String, DownloadError> {
+        let password = self.0.password.as_deref().unwrap_or_default();
+        let (logged_in, cookie) = self.call(client, None, "auth.login", json!([password])).await?;
+        let cookie = match (logged_in.as_bool(), cookie) {
+            (Some(true), Some(cookie)) => cookie,
+            (Some(true), None) => return Err(DownloadError::InvalidResponse("no session cookie".to_string())),
+            _ => return Err(DownloadError::Unauthorized),
+        };
+        let (connected, _) = self.call(client, Some(&cookie), "web.connected", json!([])).await?;
+        if connected.as_bool() != Some(true) {
+            return Err(DownloadError::Rejected("the Deluge web ui isn't connected to a daemon".to_string()));
+        }
+        Ok(cookie)
+    }
+}
+
+#[async_trait]
+impl DownloadSink for Deluge {
+    async fn check(&self) -> Result<(), DownloadError> {
+        self.login(&http_client(&self.0.url).await?).await.map(|_| ())
+    }
+
+    async fn add(&self, link: &str) -> Result<(), DownloadError> {
+        let client = http_client(&self.0.url).await?;
+        let cookie = self.login(&client).await?;
+        let options = &self.0.options;
+        let mut torrent_options = json!({});
+        if let Some(path) = &options.save_path {
+            torrent_options["download_location"] = json!(path);
+        }
+        let method = if link.starts_with("magnet:") { "core.add_torrent_magnet" } else { "core.add_torrent_url" };
+        let (torrent_id, _) = self.call(&client, Some(&cookie), method, json!([link, torrent_options])).await?;
+        let torrent_id = match torrent_id.as_str() {
+            Some(id) => id.to_string(),
+            None => return Err(DownloadError::Rejected("Deluge didn't add it, maybe it's there already".to_string())),
+        };
+        if let Some(category) = &options.category {
+            // labels come from a plugin, which might be turned off
+            self.call(&client, Some(&cookie), "label.set_torrent", json!([torrent_id, category])).await
+                .map_err(|e| DownloadError::Rejected(format!("added, but the label couldn't be set: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[tokio::test]
+async fn test_deluge() {
+    use hyper::{Body, Response};
+    use crate::test_server::{reply, StubServer};
+    use super::{sink, test_settings, ClientKind};
+    let server = StubServer::start(|req| {
+        let body: Value = serde_json::from_slice(&req.body).unwrap_or_default();
+        let logged_in = req.headers.get("Cookie").map(|c| c.as_bytes()) == Some(b"_session_id=d1");
+        match body["method"].as_str().unwrap_or_default() {
+            "auth.login" if body["params"][0] == "secret" => Response::builder()
+                .header("Set-Cookie", "_session_id=d1; Expires=Tue, 01 Jan 2030 00:00:00 GMT; Path=/json")
+                .body(Body::from(r#"{"result": true, "error": null, "id": 1}"#)).unwrap(),
+            "auth.login" => reply(200, r#"{"result": false, "error": null, "id": 1}"#),
+            "web.connected" if logged_in => reply(200, r#"{"result": true, "error": null, "id": 1}"#),
+            "core.add_torrent_magnet" if logged_in => reply(200, r#"{"result": "hash1", "error": null, "id": 1}"#),
+            "label.set_torrent" if logged_in =>
+                reply(200, r#"{"result": null, "error": {"message": "Unknown method", "code": 2}, "id": 1}"#),
+            _ => reply(200, r#"{"result": null, "error": {"message": "Not authenticated", "code": 1}, "id": 1}"#),
+        }
+    }).await;
+
+    let deluge = sink(test_settings(ClientKind::Deluge, &server.url, None));
+    assert_eq!(deluge.check().await, Ok(()));
+    assert_eq!(deluge.add("magnet:?xt=urn:btih:abc").await, Ok(()));
+    let add: Value = serde_json::from_slice(&server.requests()[4].body).unwrap();
+    assert_eq!(add["params"], json!(["magnet:?xt=urn:btih:abc", { "download_location": "/data/anime" }]));
+
+    let labeled = sink(test_settings(ClientKind::Deluge, &server.url, Some("anime")));
+    assert_eq!(labeled.add("magnet:?").await, Err(DownloadError::Rejected(
+        "added, but the label couldn't be set: Deluge says: Unknown method".to_string())));
+    let mut wrong = test_settings(ClientKind::Deluge, &server.url, None);
+    wrong.password = Some("wrong".to_string());
+    assert_eq!(sink(wrong).check().await, Err(DownloadError::Unauthorized));
+}
@@ -0,0 +1,152 @@
+//! Download clients users can have new releases sent to.
+
+use std::fmt;
+use std::time::Duration;
+
+use serenity::async_trait;
+
+use crate::outbound::{self, OutboundError};
+
+pub mod deluge;
+pub mod qbittorrent;
+pub mod transmission;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, PartialEq)]
+pub enum DownloadError {
+    /// The client didn't take the username or password.
+    Unauthorized,
+    /// The client answered, but didn't add the link, with its reason.
+    Rejected(String),
+    Request(String),
+    InvalidResponse(String),
+    /// The url leads somewhere the bot doesn't send requests to.
+    NotAllowed(OutboundError),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::Unauthorized => write!(f, "wrong username or password"),
+            DownloadError::Rejected(reason) => write!(f, "{}", reason),
+            DownloadError::Request(e) => write!(f, "couldn't reach it ({})", e),
+            DownloadError::InvalidResponse(e) => write!(f, "didn't understand the answer ({})", e),
+            DownloadError::NotAllowed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientKind {
+    QBittorrent,
+    Transmission,
+    Deluge,
+}
+
+impl ClientKind {
+    pub fn parse(name: &str) -> Option<ClientKind> {
+        match name.to_lowercase().as_str() {
+            "qbittorrent" => Some(ClientKind::QBittorrent),
+            "transmission" => Some(ClientKind::Transmission),
+            "deluge" => Some(ClientKind::Deluge),
+            _ => None,
+        }
+    }
+
+    /// Lowercase name, as stored in the db.
+    pub fn id(&self) -> &'static str {
+        match self {
+            ClientKind::QBittorrent => "qbittorrent",
+            ClientKind::Transmission => "transmission",
+            ClientKind::Deluge => "deluge",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientKind::QBittorrent => "qBittorrent",
+            ClientKind::Transmission => "Transmission",
+            ClientKind::Deluge => "Deluge",
+        }
+    }
+}
+
+/// Where releases end up in the client. Clients without categories use labels instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DownloadOptions {
+    pub category: Option<String>,
+    pub save_path: Option<String>,
+}
+
+/// The download client of a user. `url` is the address of its web ui, like
+/// `https://seedbox.example.com:8080`. It has to be public, see `outbound`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientSettings {
+    pub kind: ClientKind,
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub options: DownloadOptions,
+}
+
+#[async_trait]
+pub trait DownloadSink: Send + Sync {
+    /// Logs in without adding anything, to find out about a wrong url or password early.
+    async fn check(&self) -> Result<(), DownloadError>;
+
+    /// Hands a magnet or torrent link to the client.
+    async fn add(&self, link: &str) -> Result<(), DownloadError>;
+}
+
+pub fn sink(settings: ClientSettings) -> Box<dyn DownloadSink> {
+    match settings.kind {
+        ClientKind::QBittorrent => Box::new(qbittorrent::QBittorrent(settings)),
+        ClientKind::Transmission => Box::new(transmission::Transmission(settings)),
+        ClientKind::Deluge => Box::new(deluge::Deluge(settings)),
+    }
+}
+
+/// Clients at home can be slow or gone, which shouldn't hold up the notifications for long.
+/// Checks the url of the client first, as users choose it.
+async fn http_client(url: &str) -> Result<reqwest::Client, DownloadError> {
+    outbound::check_url(url).await.map_err(DownloadError::NotAllowed)?;
+    Ok(outbound::client(REQUEST_TIMEOUT))
+}
+
+fn request_error(e: reqwest::Error) -> DownloadError {
+    DownloadError::Request(e.to_string())
+}
+
+/// The `name=value` part of the first `Set-Cookie` header for `name`.
+fn session_cookie(res: &reqwest::Response, name: &str) -> Option<String> {
+    res.headers().get_all(reqwest::header::SET_COOKIE).iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|cookie| cookie.split(';').next())
+        .find(|cookie| cookie.trim().starts_with(&format!("{}=", name)))
+        .map(|cookie| cookie.trim().to_string())
+}
+
+
+#[cfg(test)]
+fn test_settings(kind: ClientKind, url: &str, category: Option<&str>) -> ClientSettings {
+    ClientSettings {
+        kind,
+        url: url.to_string(),
+        username: Some("admin".to_string()),
+        password: Some("secret".to_string()),
+        options: DownloadOptions {
+            category: category.map(str::to_string),
+            save_path: Some("/data/anime".to_string()),
+        },
+    }
+}
+
+#[test]
+fn test_client_kind() {
+    for kind in [ClientKind::QBittorrent, ClientKind::Transmission, ClientKind::Deluge] {
+        assert_eq!(ClientKind::parse(kind.id()), Some(kind));
+        assert_eq!(ClientKind::parse(kind.name()), Some(kind));
+    }
+    assert_eq!(ClientKind::parse("utorrent"), None);
+}
@@ -0,0 +1,91 @@
+use reqwest::header::COOKIE;
+use serenity::async_trait;
+
+use super::{http_client, request_error, session_cookie, ClientSettings, DownloadError, DownloadSink};
+
+/// The WebUI api of qBittorrent 4.1 and newer.
+pub struct QBittorrent(pub ClientSettings);
+
+impl QBittorrent {
+    /// Logs in and returns the session cookie.
+    async fn login(&self, client: &reqwest::Client) -> Result<String, DownloadError> {
+        let settings = &self.0;
+        let res = client.post(format!("{}/api/v2/auth/login", settings.url))
+            .form(&[("username", settings.username.as_deref().unwrap_or_default()),
+                ("password", settings.password.as_deref().unwrap_or_default())])
+            .send().await
+            .map_err(request_error)?;
+        match res.status().as_u16() {
+            200 => {}
+            // too many failed logins get the ip banned for a while
+            403 => return Err(DownloadError::Unauthorized),
+            code => return Err(DownloadError::Request(format!("qBittorrent answered with {}", code))),
+        }
+        let cookie = session_cookie(&res, "SID");
+        match res.text().await.map_err(request_error)?.as_str() {
+            "Ok." => cookie.ok_or_else(|| DownloadError::InvalidResponse("no session cookie".to_string())),
+            _ => Err(DownloadError::Unauthorized),
+        }
+    }
+}
+
+#[async_trait]
+impl DownloadSink for QBittorrent {
+    async fn check(&self) -> Result<(), DownloadError> {
+        self.login(&http_client(&self.0.url).await?).await.map(|_| ())
+    }
+
+    async fn add(&self, link: &str) -> Result<(), DownloadError> {
+        let client = http_client(&self.0.url).await?;
+        let cookie = self.login(&client).await?;
+        let options = &self.0.options;
+        let mut form = vec![("urls", link)];
+        if let Some(category) = &options.category {
+            form.push(("category", category));
+        }
+        if let Some(path) = &options.save_path {
+            form.push(("savepath", path));
+        }
+        let res = client.post(format!("{}/api/v2/torrents/add", self.0.url))
+            .header(COOKIE, cookie)
+            .form(&form)
+            .send().await
+            .map_err(request_error)?;
+        match res.status().as_u16() {
+            200 => {}
+            415 => return Err(DownloadError::Rejected("qBittorrent says the torrent is invalid".to_string())),
+            code => return Err(DownloadError::Request(format!("qBittorrent answered with {}", code))),
+        }
+        match res.text().await.map_err(request_error)?.as_str() {
+            "Ok." => Ok(()),
+            _ => Err(DownloadError::Rejected("qBittorrent didn't add it, maybe it's there already".to_string())),
+        }
+    }
+}
+
+
+#[tokio::test]
+async fn test_qbittorrent() {
+    use hyper::{Body, Response};
+    use crate::test_server::{reply, StubServer};
+    use super::{sink, test_settings, ClientKind};
+    let server = StubServer::start(|req| match req.path.as_str() {
+        "/api/v2/auth/login" if String::from_utf8_lossy(&req.body).contains("password=secret") =>
+            Response::builder().header("Set-Cookie", "SID=abc123; HttpOnly; path=/").body(Body::from("Ok.")).unwrap(),
+        "/api/v2/auth/login" => reply(200, "Fails."),
+        "/api/v2/torrents/add" if req.headers["Cookie"] == "SID=abc123" => reply(200, "Ok."),
+        _ => reply(403, "Forbidden"),
+    }).await;
+
+    let qbittorrent = sink(test_settings(ClientKind::QBittorrent, &server.url, Some("anime")));
+    assert_eq!(qbittorrent.check().await, Ok(()));
+    assert_eq!(qbittorrent.add("magnet:?xt=urn:btih:abc").await, Ok(()));
+    let requests = server.requests();
+    assert_eq!(String::from_utf8_lossy(&requests[0].body), "username=admin&password=secret");
+    assert_eq!(String::from_utf8_lossy(&requests[2].body),
+               "urls=magnet%3A%3Fxt%3Durn%3Abtih%3Aabc&category=anime&savepath=%2Fdata%2Fanime");
+
+    let mut wrong = test_settings(ClientKind::QBittorrent, &server.url, None);
+    wrong.password = Some("wrong".to_string());
+    assert_eq!(sink(wrong).add("magnet:?").await, Err(DownloadError::Unauthorized));
+}
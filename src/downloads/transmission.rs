@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serenity::async_trait;
+
+use super::{http_client, request_error, ClientSettings, DownloadError, DownloadSink};
+
+const SESSION_HEADER: &str = "X-Transmission-Session-Id";
+
+/// The RPC interface of Transmission, reachable under `/transmission/rpc` of the web ui.
+pub struct Transmission(pub ClientSettings);
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: String,
+}
+
+impl Transmission {
+    /// Transmission answers the first request with 409 and the session id to send along
+    /// from then on.
+    async fn call(&self, body: Value) -> Result<(), DownloadError> {
+        let client = http_client(&self.0.url).await?;
+        let mut session_id = String::new();
+        for _ in 0..2 {
+            let mut req = client.post(format!("{}/transmission/rpc", self.0.url))
+                .header(SESSION_HEADER, &session_id)
+                .json(&body);
+            if let Some(username) = &self.0.username {
+                req = req.basic_auth(username, self.0.password.as_ref());
+            }
+            let res = req.send().await.map_err(request_error)?;
+            match res.status().as_u16() {
+                200 => {}
+                409 => {
+                    session_id = res.headers().get(SESSION_HEADER)
+                        .and_then(|id| id.to_str().ok())
+                        .ok_or_else(|| DownloadError::InvalidResponse("no session id".to_string()))?
+                        .to_string();
+                    continue;
+                }
+                401 | 403 => return Err(DownloadError::Unauthorized),
+                code => return Err(DownloadError::Request(format!("Transmission answered with {}", code))),
+            }
+            let res: RpcResponse = res.json().await.map_err(|e| DownloadError::InvalidResponse(e.to_string()))?;
+            return match res.result.as_str() {
+                "success" => Ok(()),
+                reason => Err(DownloadError::Rejected(format!("Transmission says: {}", reason))),
+            };
+        }
+        Err(DownloadError::InvalidResponse("session id wasn't accepted".to_string()))
+    }
+}
+
+#[async_trait]
+impl DownloadSink for Transmission {
+    async fn check(&self) -> Result<(), DownloadError> {
+        self.call(json!({ "method": "session-get", "arguments": { "fields": ["version"] } })).await
+    }
+
+    async fn add(&self, link: &str) -> Result<(), DownloadError> {
+        let options = &self.0.options;
+        let mut arguments = json!({ "filename": link });
+        if let Some(path) = &options.save_path {
+            arguments["download-dir"] = json!(path);
+        }
+        if let Some(category) = &options.category {
+            arguments["labels"] = json!([category]);
+        }
+        self.call(json!({ "method": "torrent-add", "arguments": arguments })).await
+    }
+}
+
+
+#[tokio::test]
+async fn test_transmission() {
+    use hyper::{Body, Response};
+    use crate::test_server::{reply, StubServer};
+    use super::{sink, test_settings, ClientKind};
+    let server = StubServer::start(|req| {
+        // admin:secret
+        if req.headers.get("Authorization").map(|a| a.as_bytes()) != Some(b"Basic YWRtaW46c2VjcmV0") {
+            return reply(401, "Unauthorized");
+        }
+        if req.headers[SESSION_HEADER] != "s1" {
+            return Response::builder().status(409).header(SESSION_HEADER, "s1").body(Body::empty()).unwrap();
+        }
+        if String::from_utf8_lossy(&req.body).contains("duplicate") {
+            return reply(200, r#"{"result": "duplicate torrent", "arguments": {}}"#);
+        }
+        reply(200, r#"{"result": "success", "arguments": {}}"#)
+    }).await;
+
+    let transmission = sink(test_settings(ClientKind::Transmission, &server.url, Some("anime")));
+    assert_eq!(transmission.add("magnet:?xt=urn:btih:abc").await, Ok(()));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].path, "/transmission/rpc");
+    let body: Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(body, json!({ "method": "torrent-add", "arguments": {
+        "filename": "magnet:?xt=urn:btih:abc", "download-dir": "/data/anime", "labels": ["anime"] } }));
+
+    assert_eq!(transmission.add("magnet:?dn=duplicate").await,
+               Err(DownloadError::Rejected("Transmission says: duplicate torrent".to_string())));
+    let mut wrong = test_settings(ClientKind::Transmission, &server.url, None);
+    wrong.password = Some("wrong".to_string());
+    assert_eq!(sink(wrong).check().await, Err(DownloadError::Unauthorized));
+}
@@ -0,0 +1,177 @@
+use chrono::{DateTime, Datelike, Duration, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, Tz};
+
+use crate::subs_pls::page_parser::Show;
+use crate::watchlist_file::show_url;
+
+const BY_DAY: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+/// Content lines longer than this many bytes have to be folded.
+const MAX_LINE_BYTES: usize = 75;
+
+/// Builds an iCalendar file with a weekly event for every airing show. Air times are in
+/// the schedule timezone, so the events keep their local time over daylight saving changes.
+pub fn calendar(shows: &[Show], tz: Tz, now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//yukino//watchlist//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Yukino watchlist".to_string(),
+        format!("X-WR-TIMEZONE:{}", tz.name()),
+        "REFRESH-INTERVAL;VALUE=DURATION:PT12H".to_string(),
+    ];
+    lines.extend(vtimezone(tz, now));
+    let today = now.with_timezone(&tz).date_naive();
+    for show in shows.iter().filter(|s| s.air_time.is_airing) {
+        let air_time = &show.air_time;
+        let by_day = match BY_DAY.get(air_time.est_week_day as usize) {
+            Some(by_day) if air_time.est_week_day >= 0 => by_day,
+            _ => continue,
+        };
+        // the latest airing up to today, so this week's episode shows up as well
+        let days_back = (today.weekday().num_days_from_monday() as i64 + 7 - air_time.est_week_day as i64) % 7;
+        let first_airing = today - Duration::days(days_back);
+        lines.extend(vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@yukino", show.id),
+            format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART;TZID={}:{}T{:02}{:02}00", tz.name(), first_airing.format("%Y%m%d"),
+                    air_time.est_h, air_time.est_m),
+            "DURATION:PT30M".to_string(),
+            format!("RRULE:FREQ=WEEKLY;BYDAY={}", by_day),
+            format!("SUMMARY:{}", escape(&show.name)),
+            format!("URL:{}", show_url(&show.id)),
+            format!("DESCRIPTION:{}", escape(&show.synopsis)),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|l| fold(l)).collect::<Vec<String>>().join("")
+}
+
+/// The definition of the TZID the events use. Clients only need the offsets around the events,
+/// so it lists the changes from a year before `now` to two years after, as the feed is
+/// fetched again long before that runs out.
+fn vtimezone(tz: Tz, now: DateTime<Utc>) -> Vec<String> {
+    const DAY: i64 = 24 * 60 * 60;
+    let offset_at = |timestamp: i64| {
+        let offset = tz.offset_from_utc_datetime(&Utc.timestamp_opt(timestamp, 0).unwrap().naive_utc());
+        (offset.fix().local_minus_utc(), !offset.dst_offset().is_zero())
+    };
+    let mut transitions = Vec::new();
+    let end = (now + Duration::days(2 * 366)).timestamp();
+    let mut day = (now - Duration::days(366)).timestamp();
+    while day < end {
+        let (from, _) = offset_at(day);
+        if offset_at(day + DAY).0 != from {
+            let (mut before, mut after) = (day, day + DAY);
+            while after - before > 1 {
+                let middle = (before + after) / 2;
+                if offset_at(middle).0 == from { before = middle } else { after = middle }
+            }
+            transitions.push((after, from));
+        }
+        day += DAY;
+    }
+
+    let mut lines = vec!["BEGIN:VTIMEZONE".to_string(), format!("TZID:{}", tz.name())];
+    if transitions.is_empty() {
+        let (offset, _) = offset_at(now.timestamp());
+        lines.extend(observance("STANDARD", "19700101T000000".to_string(), offset, offset));
+    }
+    for (at, from) in transitions {
+        let (to, is_dst) = offset_at(at);
+        // the start of an observance is in the local time before it
+        let start = Utc.timestamp_opt(at + from as i64, 0).unwrap().format("%Y%m%dT%H%M%S").to_string();
+        lines.extend(observance(if is_dst { "DAYLIGHT" } else { "STANDARD" }, start, from, to));
+    }
+    lines.push("END:VTIMEZONE".to_string());
+    lines
+}
+
+fn observance(kind: &str, start: String, from: i32, to: i32) -> Vec<String> {
+    vec![
+        format!("BEGIN:{}", kind),
+        format!("DTSTART:{}", start),
+        format!("TZOFFSETFROM:{}", utc_offset(from)),
+        format!("TZOFFSETTO:{}", utc_offset(to)),
+        format!("END:{}", kind),
+    ]
+}
+
+/// `+0130` for 5400 seconds.
+fn utc_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    format!("{}{:02}{:02}", sign, seconds / 3600, seconds % 3600 / 60)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Ends a content line with CRLF, breaking it into continuation lines that start with a
+/// space where it gets too long. Never breaks inside a char.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut line_bytes = 0;
+    for c in line.chars() {
+        if line_bytes + c.len_utf8() > MAX_LINE_BYTES {
+            folded.push_str("\r\n ");
+            line_bytes = 1;
+        }
+        folded.push(c);
+        line_bytes += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+
+#[test]
+fn test_calendar() {
+    use chrono::TimeZone;
+    use crate::subs_pls::page_parser::{test_show, AirTime};
+    let show = |id: &str, name: &str, is_airing: bool| Show {
+        synopsis: "Luffy, a boy; and\nhis crew".to_string(),
+        ..test_show(id, name, AirTime { is_airing, est_week_day: if is_airing { 6 } else { -1 }, est_h: 2, est_m: 30 })
+    };
+    // a Wednesday
+    let now = Utc.with_ymd_and_hms(2021, 7, 21, 12, 0, 0).unwrap();
+    let mut unknown_day = show("gintama", "Gintama", true);
+    unknown_day.air_time.est_week_day = 7;
+    let ics = calendar(&[show("one-piece", "One Piece", true), show("aria", "Aria", false), unknown_day],
+                       chrono_tz::Europe::Berlin, now);
+    let lines: Vec<&str> = ics.split("\r\n").collect();
+    assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+    assert_eq!(lines.iter().filter(|&&l| l == "BEGIN:VEVENT").count(), 1);
+    assert!(lines.contains(&"UID:one-piece@yukino"));
+    assert!(lines.contains(&"DTSTAMP:20210721T120000Z"));
+    assert!(lines.contains(&"DTSTART;TZID=Europe/Berlin:20210718T023000"));
+    assert!(lines.contains(&"RRULE:FREQ=WEEKLY;BYDAY=SU"));
+    assert!(lines.contains(&"DESCRIPTION:Luffy\\, a boy\\; and\\nhis crew"));
+    assert!(ics.ends_with("END:VCALENDAR\r\n"));
+
+    assert!(ics.contains("BEGIN:VTIMEZONE\r\nTZID:Europe/Berlin\r\n"));
+    assert!(ics.contains("BEGIN:DAYLIGHT\r\nDTSTART:20210328T020000\r\nTZOFFSETFROM:+0100\r\n\
+        TZOFFSETTO:+0200\r\nEND:DAYLIGHT\r\n"));
+    assert!(ics.contains("BEGIN:STANDARD\r\nDTSTART:20211031T030000\r\nTZOFFSETFROM:+0200\r\n\
+        TZOFFSETTO:+0100\r\nEND:STANDARD\r\n"));
+    assert_eq!(lines.iter().filter(|&&l| l == "BEGIN:DAYLIGHT").count(), 3);
+    let tokyo = calendar(&[], chrono_tz::Asia::Tokyo, now);
+    assert!(tokyo.contains("BEGIN:STANDARD\r\nDTSTART:19700101T000000\r\nTZOFFSETFROM:+0900\r\n\
+        TZOFFSETTO:+0900\r\nEND:STANDARD\r\n"));
+}
+
+#[test]
+fn test_fold() {
+    assert_eq!(fold("SUMMARY:short"), "SUMMARY:short\r\n");
+    let long = format!("SUMMARY:{}", "ä".repeat(40));
+    let folded = fold(&long);
+    assert!(folded.split("\r\n").all(|l| l.len() <= MAX_LINE_BYTES));
+    assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", long));
+}
@@ -0,0 +1,70 @@
+use chrono::Utc;
+use rand::Rng;
+
+use crate::config::Config;
+use crate::subs_pls::db;
+
+const TOKEN_LENGTH: usize = 10;
+
+/// A short `/r/<token>` link that redirects to `url`. Discord doesn't render magnet links,
+/// so they are handed out like this. The same url always gets the same token, every new
+/// link to it pushes the expiry back. Without a public url of the http server, the link
+/// goes through the static redirect page instead.
+pub async fn redirect_link(config: &Config, url: &str) -> Result<String, tokio_postgres::Error> {
+    let public_url = match &config.http.public_url {
+        Some(public_url) => public_url,
+        None => return Ok(format!("{}?r={}", config.redirect_base_url, query_value(url))),
+    };
+    let token: String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    let expiry = chrono::Duration::from_std(config.redirect_expiry).unwrap_or_else(|_| chrono::Duration::days(30));
+    let expires_at = Utc::now() + expiry;
+    let token = db::upsert_link(&token, url, expires_at).await?;
+    Ok(format!("{}/r/{}", public_url, token))
+}
+
+/// Percent encodes everything but unreserved characters, so a magnet's own `&` and `=` stay
+/// part of the value.
+fn query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Percent encodes everything that may not appear in a `Location` header.
+pub fn location(url: &str) -> String {
+    let mut encoded = String::with_capacity(url.len());
+    for b in url.bytes() {
+        if (0x21..0x7f).contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+
+#[test]
+fn test_location() {
+    assert_eq!(location("magnet:?xt=urn:btih:abc&dn=%5BSubsPlease%5D"), "magnet:?xt=urn:btih:abc&dn=%5BSubsPlease%5D");
+    assert_eq!(location("magnet:?dn=One Piece – 1000"), "magnet:?dn=One%20Piece%20%E2%80%93%201000");
+}
+
+#[tokio::test]
+async fn test_redirect_link_without_public_url() {
+    let config = crate::config::test_config();
+    assert_eq!(redirect_link(&config, "magnet:?xt=urn:btih:abc").await.unwrap(),
+               "https://yukino.onrender.com/?r=magnet%3A%3Fxt%3Durn%3Abtih%3Aabc");
+    assert_eq!(redirect_link(&config, "magnet:?xt=urn:btih:abc&dn=One Piece&tr=http://tracker/announce").await.unwrap(),
+               "https://yukino.onrender.com/?r=magnet%3A%3Fxt%3Durn%3Abtih%3Aabc%26dn%3DOne%20Piece%26tr%3Dhttp%3A%2F%2Ftracker%2Fannounce");
+}
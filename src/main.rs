@@ -1,22 +1,50 @@
-use std::env;
 use std::error::Error;
+use std::sync::Arc;
 
 
+use chrono::Weekday;
 use serenity::async_trait;
+use serenity::client::bridge::gateway::event::ShardStageUpdateEvent;
 use serenity::client::{Client, Context, EventHandler};
 use serenity::framework::standard::StandardFramework;
+use serenity::gateway::ConnectionStage;
 use serenity::model::channel::Message;
+use serenity::model::event::ResumedEvent;
+use serenity::model::gateway::Ready;
 use tokio::spawn;
 use tokio_schedule::{every, Job};
 
+use crate::config::Config;
+use crate::metrics::METRICS;
+use crate::shutdown::{JobGuard, Shutdown};
+use crate::subs_pls::db;
 use crate::subs_pls::db::RssIdDbCommunicator;
 use crate::subs_pls::notify::notify_users;
 use crate::subs_pls::release_parser::SubsPlsChannel;
 use crate::user_manager::is_user_registered;
 
+mod config;
+mod metrics;
+mod server;
+mod shutdown;
 mod subs_pls;
 mod user_manager;
 mod message_handler;
+mod trackers;
+#[cfg(test)]
+mod test_server;
+mod watchlist_file;
+mod schedule;
+mod schedule_image;
+mod ical;
+mod links;
+mod downloads;
+mod premiumize;
+mod secrets;
+mod webhooks;
+mod outbound;
+mod user_data;
+mod notifiers;
 
 
 struct Handler;
@@ -26,6 +54,18 @@ impl EventHandler for Handler {
     async fn message(&self, ctx: Context, msg: Message) {
         if msg.is_private() && !msg.author.bot { message_handler(ctx, msg).await; }
     }
+
+    async fn ready(&self, _ctx: Context, _ready: Ready) {
+        METRICS.set_gateway_connected(true);
+    }
+
+    async fn resume(&self, _ctx: Context, _resumed: ResumedEvent) {
+        METRICS.set_gateway_connected(true);
+    }
+
+    async fn shard_stage_update(&self, _ctx: Context, update: ShardStageUpdateEvent) {
+        METRICS.set_gateway_connected(update.new == ConnectionStage::Connected);
+    }
 }
 
 async fn message_handler(ctx: Context, msg: Message) {
@@ -40,29 +80,97 @@ async fn message_handler(ctx: Context, msg: Message) {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let config = Arc::new(Config::load()?);
+    db::init(&config.database)?;
+    outbound::allow_private_urls(config.allow_private_urls);
+    db::migrate().await?;
+
     let framework = StandardFramework::new()
         .configure(|c| c.no_dm_prefix(true));
-    let token = env::var("DISCORD_TOKEN").expect("token");
-    let mut client = Client::builder(token)
+    let mut client = Client::builder(&config.discord_token)
         .event_handler(Handler)
         .framework(framework)
         .await
         .expect("Error creating client");
+    client.data.write().await.insert::<Config>(config.clone());
+
 
+    let shutdown = Arc::new(Shutdown::new());
 
-    let release_check = every((&*env::var("RSS_REFRESH")
-        .expect("rss refresh")).parse()?)
-        .second().perform(|| async {
-        check_rss(env::var("RSS_LINK").expect("rss link")).await;
+    let (rss_config, rss_shutdown) = (config.clone(), shutdown.clone());
+    let release_check = every(rss_config.feeds.rss_refresh_secs)
+        .second().perform(move || {
+        let (config, shutdown) = (rss_config.clone(), rss_shutdown.clone());
+        async move {
+            if let Some(job) = shutdown.start_job() {
+                check_rss(&config, &job).await;
+            }
+        }
     });
     spawn(release_check);
 
-    let eu = every(1).day().perform(|| async {
-        println!("Updating shows");
-        episode_update().await
+    // the daily job first runs a day after the start, so new shows are seeded before any digest
+    let (seed_config, seed_shutdown) = (config.clone(), shutdown.clone());
+    spawn(async move {
+        if let Some(_job) = seed_shutdown.start_job() {
+            subs_pls::season::detect_new_shows(&seed_config).await;
+        }
+    });
+
+    let (eu_config, eu_shutdown) = (config.clone(), shutdown.clone());
+    let eu = every(1).day().perform(move || {
+        let (config, shutdown) = (eu_config.clone(), eu_shutdown.clone());
+        async move {
+            if let Some(_job) = shutdown.start_job() {
+                subs_pls::season::detect_new_shows(&config).await;
+                println!("Updating shows");
+                episode_update(&config, &shutdown).await;
+                if let Err(e) = db::delete_expired_links().await {
+                    println!("Error deleting expired links: {}", e);
+                }
+                if let Err(e) = db::delete_old_notification_history().await {
+                    println!("Error deleting old notification history: {}", e);
+                }
+                user_manager::purge_unregistered(&config).await;
+            }
+        }
     });
     spawn(eu);
 
+    let (digest_config, digest_shutdown) = (config.clone(), shutdown.clone());
+    let digest = every(1).week().on(Weekday::Mon).at(12, 0, 0).perform(move || {
+        let (config, shutdown) = (digest_config.clone(), digest_shutdown.clone());
+        async move {
+            if let Some(_job) = shutdown.start_job() {
+                subs_pls::season::send_digests(&config).await
+            }
+        }
+    });
+    spawn(digest);
+
+    let (delivery_config, delivery_shutdown) = (config.clone(), shutdown.clone());
+    let delivery = every(1).hour().at(0, 0).perform(move || {
+        let (config, shutdown) = (delivery_config.clone(), delivery_shutdown.clone());
+        async move {
+            if let Some(_job) = shutdown.start_job() {
+                subs_pls::digest::send_digests(&config).await
+            }
+        }
+    });
+    spawn(delivery);
+
+    spawn(server::serve(config.clone(), shutdown.clone()));
+
+    let shard_manager = client.shard_manager.clone();
+    spawn(async move {
+        shutdown::wait_for_signal().await;
+        println!("Shutting down, waiting for running jobs");
+        if !shutdown.drain(config.shutdown_timeout).await {
+            println!("Running jobs didn't finish in time");
+        }
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
 
     if let Err(why) = client.start().await {
         println!("An error occurred while running the client: {:?}", why);
@@ -72,10 +180,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn check_rss(rss_link: String) {
+async fn check_rss(config: &Arc<Config>, job: &JobGuard) {
     let rss_db_communicator: RssIdDbCommunicator = RssIdDbCommunicator::new().await;
     let rss =
-        match reqwest::get(&rss_link).await {
+        match reqwest::get(&config.feeds.rss_url).await {
             Ok(r) => match r.text().await {
                 Ok(t) => t,
                 _ => "not available".to_string()
@@ -85,21 +193,18 @@ async fn check_rss(rss_link: String) {
     let feed_res = SubsPlsChannel::from_xml(&rss);
     match feed_res {
         Ok(feed) => {
+            METRICS.rss_poll_succeeded();
             let last_rss = rss_db_communicator.get_guid().await;
-            let new_newest = feed.items[0].guid.to_string();
-            if new_newest != last_rss {
-                notify_users(&feed, &last_rss).await;
-                rss_db_communicator.save_guid(&new_newest).await.ok();
-            }
+            notify_users(config, job, &feed, &last_rss, &rss_db_communicator).await;
+        }
+        Err(e) => {
+            METRICS.rss_parse_failed();
+            println!("Rss parsing Error: {}", e)
         }
-        Err(e) => {println!("Rss parsing Error: {}", e)}
     }
 
 }
 
-async fn episode_update() {
-    subs_pls::update_shows::update_shows().await
+async fn episode_update(config: &Config, shutdown: &Shutdown) {
+    subs_pls::update_shows::update_shows(config, shutdown).await
 }
-
-
-
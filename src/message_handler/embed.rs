@@ -0,0 +1,210 @@
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::model::id::ChannelId;
+
+/// Discord rejects embeds with more fields than this.
+pub const MAX_FIELDS: usize = 25;
+/// Limit for the title, description, field names and values and footer of an embed together.
+pub const MAX_EMBED_CHARS: usize = 6000;
+pub const MAX_TITLE: usize = 256;
+pub const MAX_DESCRIPTION: usize = 4096;
+pub const MAX_FIELD_NAME: usize = 256;
+pub const MAX_FIELD_VALUE: usize = 1024;
+/// Room kept for the "Page x of y" footer of numbered embeds.
+const PAGE_FOOTER_CHARS: usize = 32;
+
+/// Content for embeds that might not fit into a single one. Texts are cut to Discord's
+/// limits and the fields are spread over as many embeds as needed. Title, description and
+/// images go on the first embed only.
+pub struct EmbedPages {
+    title: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    thumbnail: Option<String>,
+    fields: Vec<(String, String, bool)>,
+    fields_per_embed: usize,
+    numbered: bool,
+}
+
+impl Default for EmbedPages {
+    fn default() -> Self {
+        EmbedPages {
+            title: None,
+            description: None,
+            image: None,
+            thumbnail: None,
+            fields: Vec::new(),
+            fields_per_embed: MAX_FIELDS,
+            numbered: false,
+        }
+    }
+}
+
+impl EmbedPages {
+    pub fn title(&mut self, title: impl ToString) -> &mut Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn description(&mut self, description: impl ToString) -> &mut Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn image(&mut self, url: impl ToString) -> &mut Self {
+        self.image = Some(url.to_string());
+        self
+    }
+
+    pub fn thumbnail(&mut self, url: impl ToString) -> &mut Self {
+        self.thumbnail = Some(url.to_string());
+        self
+    }
+
+    pub fn field(&mut self, name: impl ToString, value: impl ToString, inline: bool) -> &mut Self {
+        self.fields.push((name.to_string(), value.to_string(), inline));
+        self
+    }
+
+    /// Puts at most `count` fields into each embed, to page through long lists.
+    pub fn fields_per_embed(&mut self, count: usize) -> &mut Self {
+        self.fields_per_embed = count.clamp(1, MAX_FIELDS);
+        self
+    }
+
+    /// Adds a "Page x of y" footer to every embed.
+    pub fn numbered(&mut self) -> &mut Self {
+        self.numbered = true;
+        self
+    }
+
+    pub fn build(&self) -> Vec<CreateEmbed> {
+        let reserved = if self.numbered { PAGE_FOOTER_CHARS } else { 0 };
+        let mut embeds = Vec::new();
+        let mut current = CreateEmbed::default();
+        let mut chars = reserved;
+        let mut field_count = 0;
+        if let Some(title) = &self.title {
+            let title = truncate(title, MAX_TITLE);
+            chars += title.chars().count();
+            current.title(title);
+        }
+        if let Some(description) = &self.description {
+            let description = truncate(description, MAX_DESCRIPTION);
+            chars += description.chars().count();
+            current.description(description);
+        }
+        if let Some(image) = &self.image {
+            current.image(image);
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            current.thumbnail(thumbnail);
+        }
+        for (name, value, inline) in &self.fields {
+            let (name, value) = (truncate(name, MAX_FIELD_NAME), truncate(value, MAX_FIELD_VALUE));
+            let len = name.chars().count() + value.chars().count();
+            if field_count > 0 && (field_count == self.fields_per_embed || chars + len > MAX_EMBED_CHARS)
+                || field_count == 0 && chars + len > MAX_EMBED_CHARS {
+                embeds.push(std::mem::take(&mut current));
+                chars = reserved;
+                field_count = 0;
+            }
+            chars += len;
+            field_count += 1;
+            current.field(name, value, *inline);
+        }
+        embeds.push(current);
+        if self.numbered {
+            let count = embeds.len();
+            for (i, embed) in embeds.iter_mut().enumerate() {
+                embed.footer(|f| f.text(format!("Page {} of {}", i + 1, count)));
+            }
+        }
+        embeds
+    }
+
+    /// Sends every embed in its own message, as the length limit counts for all embeds of a
+    /// message together.
+    pub async fn send(&self, ctx: &Context, channel_id: ChannelId) -> Result<(), serenity::Error> {
+        for embed in self.build() {
+            channel_id.send_message(ctx, |m| m.set_embed(embed)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Cuts `text` to at most `max` chars, ending with an ellipsis if anything was cut. Discord
+/// doesn't allow empty field names or values, so those become a zero width space.
+fn truncate(text: &str, max: usize) -> String {
+    if text.is_empty() {
+        "\u{200b}".to_string()
+    } else if text.chars().count() > max {
+        let mut cut: String = text.chars().take(max - 1).collect();
+        cut.push('…');
+        cut
+    } else {
+        text.to_string()
+    }
+}
+
+
+#[cfg(test)]
+fn embed_text(embed: &CreateEmbed) -> Vec<String> {
+    let mut texts = Vec::new();
+    for key in ["title", "description"] {
+        if let Some(text) = embed.0.get(key).and_then(|v| v.as_str()) { texts.push(text.to_string()); }
+    }
+    if let Some(text) = embed.0.get("footer").and_then(|f| f["text"].as_str()) { texts.push(text.to_string()); }
+    for field in embed.0.get("fields").and_then(|f| f.as_array()).into_iter().flatten() {
+        texts.push(field["name"].as_str().unwrap().to_string());
+        texts.push(field["value"].as_str().unwrap().to_string());
+    }
+    texts
+}
+
+#[cfg(test)]
+fn field_count(embed: &CreateEmbed) -> usize {
+    embed.0.get("fields").and_then(|f| f.as_array()).map_or(0, |f| f.len())
+}
+
+#[test]
+fn test_embed_limits() {
+    let mut pages = EmbedPages::default();
+    pages.title("t".repeat(300)).description("d".repeat(5000));
+    for i in 0..60 {
+        pages.field(i, "v".repeat(if i % 2 == 0 { 2000 } else { 10 }), false);
+    }
+    let embeds = pages.build();
+    assert!(embeds.len() > 1);
+    for embed in &embeds {
+        let texts = embed_text(embed);
+        assert!(texts.iter().map(|t| t.chars().count()).sum::<usize>() <= MAX_EMBED_CHARS);
+        assert!(field_count(embed) <= MAX_FIELDS);
+        assert!(embed.0.get("fields").and_then(|f| f.as_array()).into_iter().flatten()
+            .all(|f| f["value"].as_str().unwrap().chars().count() <= MAX_FIELD_VALUE));
+    }
+    assert_eq!(embeds.iter().map(field_count).sum::<usize>(), 60);
+    let first = embed_text(&embeds[0]);
+    assert_eq!(first[0].chars().count(), MAX_TITLE);
+    assert!(first[0].ends_with('…'));
+    assert_eq!(first[1].chars().count(), MAX_DESCRIPTION);
+    assert!(!embeds[1].0.contains_key("title"));
+}
+
+#[test]
+fn test_embed_pages() {
+    let build = |count: usize, len: usize, per_embed: usize| {
+        let mut pages = EmbedPages::default();
+        pages.fields_per_embed(per_embed).numbered();
+        for i in 0..count { pages.field(i, "x".repeat(len), true); }
+        pages.build()
+    };
+    let sizes = |embeds: Vec<CreateEmbed>| embeds.iter().map(field_count).collect::<Vec<usize>>();
+    assert_eq!(sizes(build(23, 10, 10)), vec![10, 10, 3]);
+    assert_eq!(sizes(build(30, 10, 100)), vec![25, 5]);
+    // a one char name and 1000 chars value each, five fit next to the page footer
+    assert_eq!(sizes(build(7, 1000, 25)), vec![5, 2]);
+    assert_eq!(embed_text(&build(23, 10, 10)[1])[0], "Page 2 of 3");
+    assert_eq!(sizes(EmbedPages::default().build()), vec![0]);
+    assert_eq!(embed_text(&EmbedPages::default().field("", "", false).build()[0]), vec!["\u{200b}", "\u{200b}"]);
+}
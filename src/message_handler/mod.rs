@@ -1,3 +1,4 @@
+pub mod embed;
 pub mod registered;
 pub mod unregistered;
 
@@ -7,7 +8,7 @@ async fn split_at_fist_space(command: &str) -> (String, String) {
     let mut argument = Vec::new();
     let mut take_operand = true;
     for c in command.chars() {
-        if c == ' ' && take_operand {
+        if c.is_whitespace() && take_operand {
             take_operand = false;
             continue;
         }
@@ -26,4 +27,5 @@ async fn test_split() {
     assert_eq!(split_at_fist_space("addlink").await, ("addlink".to_string(), "".to_string()));
     assert_eq!(split_at_fist_space("add link and so on").await,
                ("add".to_string(), "link and so on".to_string()));
+    assert_eq!(split_at_fist_space("import\nlink\nlink").await, ("import".to_string(), "link\nlink".to_string()));
 }
\ No newline at end of file
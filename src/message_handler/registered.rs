@@ -1,22 +1,87 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use chrono::{Datelike, Utc};
 use serenity::client::Context;
-use serenity::model::channel::Message;
+use serenity::futures::StreamExt;
+use serenity::http::AttachmentType;
+use serenity::model::channel::{Message, ReactionType};
 
+use crate::config::Config;
+use crate::downloads::{ClientKind, ClientSettings, DownloadError, DownloadOptions};
+use crate::notifiers::{Channel, ChannelKind};
+use crate::metrics::METRICS;
+use crate::schedule::ScheduleView;
+use crate::schedule_image;
 use crate::user_manager;
+use crate::webhooks;
 
+use super::embed;
 use super::split_at_fist_space;
 use crate::subs_pls::page_parser::AddFailure;
-use crate::user_manager::RemoveFailure;
+use crate::trackers::TrackerError;
+use crate::subs_pls::page_parser::AirTime;
+use crate::subs_pls::digest::{DeliveryMode, QuietHours};
+use crate::user_manager::{AiringFailure, AiringShow, CalendarFailure, ChannelFailure, ClientFailure, InfoFailure, LinkFailure, ListFilter, ListSite, ListSort, PremiumizeFailure, RemoveFailure, Tracker, WebhookFailure};
+use crate::watchlist_file::{self, ExportFormat, ImportError};
 
+const COMMANDS: [&str; 21] = ["help", "unregister", "add", "remove", "info", "list", "schedule", "airing",
+    "season", "delivery", "quiet", "channel", "premiumize", "client", "webhook", "ical", "export", "import", "link",
+    "mydata", "examples"];
+/// Discord rejects plain messages longer than this.
+const MESSAGE_LIMIT: usize = 2000;
+const LIST_PAGE_SIZE: usize = 10;
+const PREVIOUS_PAGE: &str = "⬅️";
+const NEXT_PAGE: &str = "➡️";
+/// How long the buttons of a list keep working.
+const LIST_TIMEOUT: Duration = Duration::from_secs(300);
+/// Discord allows this many different reactions on a message.
+const MAX_REACTIONS: usize = 20;
 
 pub async fn main(ctx: Context, msg: Message) {
     let (op, arg) = split_at_fist_space(&msg.content).await;
+    METRICS.command_handled(if COMMANDS.contains(&op.as_str()) { &op } else { "unknown" });
     match (op.as_str(), arg.as_str()) {
         ("help", _) => { help(ctx, msg).await }
-        ("unregister", _) => { unregister(ctx, msg).await }
+        ("unregister", "confirm") => { unregister(ctx, msg).await }
+        ("unregister", _) => { unregister_prompt(ctx, msg).await }
         ("add", ident) => { add(ctx, msg, ident).await }
         ("remove", "non-airing") => { remove_na(ctx, msg).await }
         ("remove", ident) => { remove(ctx, msg, ident).await }
-        ("schedule", "") => { schedule(ctx, msg).await }
+        ("info", ident) => { info(ctx, msg, ident).await }
+        ("list", args) => { list(ctx, msg, args).await }
+        ("schedule", "") => { schedule(ctx, msg, ScheduleView::Week).await }
+        ("schedule", "today") => { schedule(ctx, msg, ScheduleView::Today).await }
+        ("schedule", "tomorrow") => { schedule(ctx, msg, ScheduleView::Tomorrow).await }
+        ("schedule", "week") => { schedule(ctx, msg, ScheduleView::Upcoming).await }
+        ("schedule", "image") => { schedule_image(ctx, msg).await }
+        ("airing", day) => { airing(ctx, msg, day).await }
+        ("season", "") => { season(ctx, msg).await }
+        ("season", "digest on") => { season_digest(ctx, msg, true).await }
+        ("season", "digest off") => { season_digest(ctx, msg, false).await }
+        ("delivery", args) => { delivery(ctx, msg, args).await }
+        ("quiet", args) => { quiet(ctx, msg, args).await }
+        ("channel", "") => { channels(ctx, msg).await }
+        ("channel", args) => { channel(ctx, msg, args).await }
+        ("premiumize", "") => { premiumize_status(ctx, msg).await }
+        ("premiumize", "off") => { premiumize_off(ctx, msg).await }
+        ("premiumize", api_key) => { premiumize_key(ctx, msg, api_key).await }
+        ("client", "") => { client_status(ctx, msg).await }
+        ("client", "off") => { client_off(ctx, msg).await }
+        ("client", args) => { client(ctx, msg, args).await }
+        ("webhook", "") => { webhook_status(ctx, msg).await }
+        ("webhook", "off") => { webhook_off(ctx, msg).await }
+        ("webhook", "test") => { webhook_test(ctx, msg).await }
+        ("webhook", "secret") => { webhook(ctx, msg, None).await }
+        ("webhook", url) => { webhook(ctx, msg, Some(url)).await }
+        ("ical", "") => { ical(ctx, msg).await }
+        ("ical", "url") => { ical_url(ctx, msg, false).await }
+        ("ical", "reset") => { ical_url(ctx, msg, true).await }
+        ("export", "") | ("export", "json") => { export(ctx, msg, ExportFormat::Json).await }
+        ("export", "csv") => { export(ctx, msg, ExportFormat::Csv).await }
+        ("import", list) => { import(ctx, &msg, list).await }
+        ("link", args) => { link(ctx, &msg, args).await }
+        ("mydata", _) => { my_data(ctx, msg).await }
         ("examples", _) => { examples(ctx,msg).await}
         _ => { msg.channel_id.say(ctx, "Command not recognized. Use the help command for a list of actions.").await.ok(); }
     };
@@ -24,125 +89,1048 @@ pub async fn main(ctx: Context, msg: Message) {
 
 
 async fn help(ctx: Context, msg: Message) {
-    let titles = ["help", "unregister", "add", "remove", "schedule", "examples"];
+    let titles = COMMANDS;
     let descriptions = ["Shows this message",
-        "This will remove everything about you & your saved shows from the database.",
+        "This will remove everything about you & your saved shows from the database. Type \"unregister confirm\" \
+         to go ahead. Your watchlist is kept for a few days, registering again in that time brings it back.",
         "With add you can extend your watchlist. Pass with the argument a valid link of the shows overview page.",
         "Remove lets you scrap shows from your watchlist. You can either use a link, the exact show name or the \"non-airing\"
          keyword to remove all non airing-shows.",
-        "Prints a personal release schedule.",
+        "Shows everything known about a show, whether it's on your watchlist or not. Pass a link or the name.",
+        "Pages through your watchlist. Sort it with \"name\", \"day\" or \"added\" and show only \"airing\" \
+         or \"non-airing\" shows, e.g. \"list day airing\".",
+        "Prints a personal release schedule. \"schedule today\", \"schedule tomorrow\" and \"schedule week\" \
+         show what's next, \"schedule image\" sends it as a timetable picture.",
+        "Shows everything subsplease releases on a day, e.g. \"airing today\" or \"airing friday\". \
+         React with the letter of a show to add it to your watchlist.",
+        "Lists the shows new this season. \"season digest on\" sends you the new shows every week, \
+         \"season digest off\" stops that.",
+        "Changes how you get new releases: \"delivery instant\" sends each one right away, \
+         \"delivery hourly\", \"delivery daily 18\" and \"delivery weekly sunday 18\" collect them into \
+         one message. Hours are in your timezone, see \"quiet\". Without an argument it shows your setting.",
+        "Holds back releases during the night or whenever you want some peace, e.g. \"quiet 23-7\". \
+         They come in one message when the quiet hours end, or one by one with \"quiet 23-7 separate\". \
+         \"quiet timezone America/New_York\" sets your timezone, \"quiet off\" turns it off.",
+        "Sends releases to other places too: \"channel telegram <chat id>\", \"channel matrix <room>\", \
+         \"channel ntfy <topic url> [token]\", \"channel gotify <server url> <app token>\" or \
+         \"channel email <address>\". Each gets a code to send back with \"channel <name> confirm <code>\" \
+         before releases go there. \"channel <name> off\" and \"channel <name> on\" choose which ones \
+         get releases, Discord included, \"channel <name> remove\" deletes one. Digests go to the same places.",
+        "Starts a Premiumize transfer for every new release on your watchlist. Pass your api key \
+         from premiumize.me/account, it is stored encrypted. \"premiumize off\" deletes it again.",
+        "Sends every new release on your watchlist to your torrent client, e.g. \"client qbittorrent \
+         https://seedbox.example.com:8080 admin password\", \"client transmission <url> [user password]\" or \
+         \"client deluge <url> password\". \"client category anime\" and \"client path /data/anime\" \
+         choose where they go, \"client off\" deletes it.",
+        "Posts every new release on your watchlist as JSON to your own url, e.g. \"webhook \
+         https://example.com/hook\". Requests are signed with a secret you get in return, \"webhook secret\" \
+         makes a new one. \"webhook test\" sends a made up release, \"webhook off\" deletes it.",
+        "Sends your airing shows as a calendar file with weekly events. \"ical url\" gives you a secret \
+         link your calendar app can subscribe to, \"ical reset\" replaces it with a new one.",
+        "Sends you your watchlist as a file. Pass \"csv\" for a csv file, json is the default and \
+         also holds your delivery settings and whether you get the season digest.",
+        "Adds up to 200 shows at once. Attach a file from export or a text file with one link per line, \
+         or write the links after the command. \"import mal <username>\" and \"import anilist <username>\" \
+         add the shows you are watching or plan to watch there.",
+        "Fixes the MyAnimeList, AniList or Kitsu entry of a show for everyone, e.g. \"link mal 21 One Piece\". \
+         Only the admins of the bot can do that.",
+        "Sends you everything the bot knows about you as a JSON file: watchlist, settings and the \
+         releases it sent you. Api keys and passwords only show up as saved or not.",
         "Couple of examples on how to use this bot."
         ];
-    let s = msg.channel_id.send_message(ctx, |m| {
-        m.content("");
-        m.embed(|e| {
-            for (t, d) in titles.iter().zip(&descriptions) {
-                e.field(t, d, false);
-            }
-            e
-        });
-        m
-    }).await;
-    if s.is_err() {
-        println!("Discord Error: {}", { s.err().unwrap().to_string() });
+    let mut embed = embed::EmbedPages::default();
+    for (t, d) in titles.iter().zip(&descriptions) {
+        embed.field(t, d, false);
+    }
+    if let Err(e) = embed.send(&ctx, msg.channel_id).await {
+        println!("Discord Error: {}", e);
     }
 }
 
+async fn unregister_prompt(ctx: Context, msg: Message) {
+    msg.reply(ctx, "This deletes your watchlist, settings and everything else the bot knows about you. \
+                    Type \"unregister confirm\" if you're sure.").await.ok();
+}
+
 async fn unregister(ctx: Context, msg: Message) {
-    let register_res = user_manager::unregister_user(msg.author.id.0 as i64).await;
+    let config = Config::from_context(&ctx).await;
+    let register_res = user_manager::unregister_user(&config, msg.author.id.0 as i64).await;
+    let grace_days = config.unregister_grace.as_secs() / (24 * 60 * 60);
     let reply = match register_res {
-        Ok(_) => "Successfully unregistered! Good bye!",
-        Err(_) => "An error has occurred while unregistering. Please try again later."
+        Ok(_) if config.unregister_grace.is_zero() => "Successfully unregistered! Good bye!".to_string(),
+        Ok(_) => format!("Successfully unregistered! Good bye! Your watchlist is kept for {} days, \
+                          \"register\" brings it back until then.", grace_days),
+        Err(_) => "An error has occurred while unregistering. Please try again later.".to_string()
     };
     msg.reply(ctx, reply).await.ok();
 }
 
 async fn add(ctx: Context, msg: Message, identifier: &str) {
-    let res = user_manager::add_user_show(msg.author.id.0 as i64, identifier).await;
+    let config = Config::from_context(&ctx).await;
+    let res = user_manager::add_user_show(&config, msg.author.id.0 as i64, identifier).await;
 
-    match res {
+    let sent = match res {
         Ok(show) => {
+            let mut embed = embed::EmbedPages::default();
+            embed.title("Show successfully added!")
+                .field(&show.name, &show.synopsis, false)
+                .image(&show.image_url)
+                .field("Links", show.links(), false);
+            if show.air_time.is_airing {
+                embed.field("Is airing currently. Estimated release: ", &show.air_time, true);
+            } else {
+                embed.field("Currently not airing.", "Check the Website for further information.", true);
+            }
+            embed.send(&ctx, msg.channel_id).await
+        }
+        Err(failure) => msg.reply(&ctx, add_failure_reason(&failure)).await.map(|_| ()),
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn info(ctx: Context, msg: Message, identifier: &str) {
+    let config = Config::from_context(&ctx).await;
+    let res = user_manager::show_info(&config, msg.author.id.0 as i64, identifier.trim()).await;
+
+    let sent = match res {
+        Ok(info) => {
+            let mut embed = embed::EmbedPages::default();
+            embed.title(&info.show.name)
+                .description(&info.show.synopsis)
+                .image(&info.show.image_url);
+            if info.show.air_time.is_airing {
+                embed.field("Is airing currently. Estimated release: ", &info.show.air_time, true);
+            } else {
+                embed.field("Currently not airing.", "Check the Website for further information.", true);
+            }
+            embed.field("Watchers", info.watchers, true);
+            if let Some(season) = &info.show.season {
+                embed.field("Season", season, true);
+            }
+            match &info.latest_release {
+                Some(release) => embed.field("Latest episode",
+                    format!("{} ({})", release.episode.as_deref().unwrap_or("?"),
+                            release.released_at.format("%Y-%m-%d")), true),
+                None => embed.field("Latest episode", "None seen yet.", true),
+            };
+            embed.field("Links", info.show.links(), false)
+                .field("Watchlist", if info.on_watchlist { "On your watchlist." } else { "Not on your watchlist." }, false);
+            embed.send(&ctx, msg.channel_id).await
+        }
+        Err(InfoFailure::ShowNotFound) => msg.reply(&ctx, "I couldn't find that show. Check the link or the name.").await.map(|_| ()),
+        Err(InfoFailure::DBError) => msg.reply(&ctx, "Error communicating with database. Try again later.").await.map(|_| ()),
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+/// Reads the sort order and filter of the list command in any order, e.g. "airing added".
+fn parse_list_args(args: &str) -> Option<(ListSort, ListFilter)> {
+    let (mut sort, mut filter) = (ListSort::Name, ListFilter::All);
+    for arg in args.split_whitespace() {
+        match arg {
+            "name" => sort = ListSort::Name,
+            "day" => sort = ListSort::AirDay,
+            "added" => sort = ListSort::Added,
+            "airing" => filter = ListFilter::Airing,
+            "non-airing" => filter = ListFilter::NonAiring,
+            _ => return None,
+        }
+    }
+    Some((sort, filter))
+}
+
+async fn list(ctx: Context, msg: Message, args: &str) {
+    let (sort, filter) = match parse_list_args(args) {
+        Some(options) => options,
+        None => {
+            msg.reply(ctx, "Use it like this: list [name|day|added] [airing|non-airing]").await.ok();
+            return;
+        }
+    };
+    let entries = match user_manager::get_watchlist(msg.author.id.0 as i64, sort, filter).await {
+        Ok(entries) if entries.is_empty() => {
+            msg.reply(ctx, "No shows on your watchlist match.").await.ok();
+            return;
+        }
+        Ok(entries) => entries,
+        Err(_) => {
+            msg.reply(ctx, "Error communicating with database. Try again later.").await.ok();
+            return;
+        }
+    };
+    let mut embed = embed::EmbedPages::default();
+    embed.title(format!("Your watchlist ({} shows)", entries.len()))
+        .fields_per_embed(LIST_PAGE_SIZE)
+        .numbered();
+    for e in entries.iter() {
+        let air_time = if e.show.air_time.is_airing { e.show.air_time.to_string() } else { "Not airing".to_string() };
+        embed.field(&e.show.name, format!("{} · added {}", air_time, e.added_at.format("%Y-%m-%d")), false);
+    }
+    let pages = embed.build();
+
+    let mut index = 0;
+    let sent = msg.channel_id.send_message(&ctx, |m| m.set_embed(pages[index].clone())).await;
+    let mut list_msg = match sent {
+        Ok(list_msg) => list_msg,
+        Err(e) => return println!("Discord Error: {}", e),
+    };
+    if pages.len() < 2 { return; }
+    for button in [PREVIOUS_PAGE, NEXT_PAGE] {
+        list_msg.react(&ctx, ReactionType::Unicode(button.to_string())).await.ok();
+    }
+
+    // bots can't remove reactions in DMs, so taking a reaction back counts as a press too
+    let mut presses = list_msg.await_reactions(&ctx)
+        .author_id(msg.author.id)
+        .added(true)
+        .removed(true)
+        .timeout(LIST_TIMEOUT)
+        .await;
+    while let Some(action) = presses.next().await {
+        let emoji = &action.as_inner_ref().emoji;
+        let new_index = if emoji.unicode_eq(NEXT_PAGE) {
+            (index + 1) % pages.len()
+        } else if emoji.unicode_eq(PREVIOUS_PAGE) {
+            (index + pages.len() - 1) % pages.len()
+        } else {
+            continue;
+        };
+        index = new_index;
+        let edited = list_msg.edit(&ctx, |m| m.set_embed(pages[index].clone())).await;
+        if let Err(e) = edited {
+            println!("Discord Error: {}", e);
+            break;
+        }
+    }
+}
+
+fn add_failure_reason(failure: &AddFailure) -> &'static str {
+    match failure {
+        AddFailure::AlreadyAdded => "show already added.",
+        AddFailure::InvalidUrl => "Invalid url. Use the url of a show page.",
+        AddFailure::ShowNotAvailable => "This show doesn't exist. Please check the identifier in the url.",
+        AddFailure::DatabaseError => "Error communicating with database. Try again later.",
+        AddFailure::NameNotFound => "Adding by name is not supported (yet).",
+    }
+}
+
+async fn export(ctx: Context, msg: Message, format: ExportFormat) {
+    match user_manager::get_export(msg.author.id.0 as i64).await {
+        Ok((shows, _)) if shows.is_empty() => msg.reply(ctx, "Your watchlist is empty.").await,
+        Ok((shows, preferences)) => {
+            let data = watchlist_file::export(&shows, &preferences, format);
             msg.channel_id.send_message(ctx, |m| {
-                m.content("");
-                m.embed(|e| {
-                    e.title("Show successfully added!");
-                    e.field(&show.name, &show.synopsis, false);
-                    e.image(&show.image_url);
-                    if show.air_time.is_airing {
-                        e.field("Is airing currently. Estimated release: ", &show.air_time.to_string(), true);
-                    } else {
-                        e.field("Currently not airing.", "Check the Website for further information.", true);
-                    }
-                    e
-                });
+                m.content(format!("Your watchlist with {} shows:", shows.len()));
+                m.add_file(AttachmentType::Bytes { data: Cow::from(data), filename: format.file_name().to_string() });
                 m
             }).await
         }
-        Err(AddFailure::AlreadyAdded) => msg.reply(ctx, "show already added.").await,
-        Err(AddFailure::InvalidUrl) => msg.reply(ctx, "Invalid url. Use the url of a show page.").await,
-        Err(AddFailure::ShowNotAvailable) => msg.reply(ctx, "This show doesn't exist. Please check the identifier in the url.").await,
-        Err(AddFailure::DatabaseError) => msg.reply(ctx, "Error communicating with database. Try again later.").await,
-        Err(AddFailure::NameNotFound) => msg.reply(ctx, "Adding by name is not supported (yet).").await
+        Err(_) => msg.reply(ctx, "Error communicating with database. Try again later.").await
     }.ok();
 }
 
+async fn my_data(ctx: Context, msg: Message) {
+    let sent = match user_manager::get_user_data(msg.author.id.0 as i64).await {
+        Ok(data) => msg.channel_id.send_message(&ctx, |m| {
+            m.content("Everything stored about you:");
+            m.add_file(AttachmentType::Bytes { data: Cow::from(data.to_json()), filename: "mydata.json".to_string() })
+        }).await.map(|_| ()),
+        Err(_) => msg.reply(&ctx, "Error communicating with database. Try again later.").await.map(|_| ())
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn import(ctx: Context, msg: &Message, list: &str) {
+    let (source, user_name) = split_at_fist_space(list).await;
+    match (source.as_str(), user_name.trim()) {
+        ("mal", name) if !name.is_empty() => return import_tracker(ctx, msg, Tracker::MyAnimeList, name).await,
+        ("anilist", name) if !name.is_empty() => return import_tracker(ctx, msg, Tracker::AniList, name).await,
+        _ => {}
+    }
+    let identifiers = match msg.attachments.first() {
+        Some(attachment) => match attachment.download().await {
+            Ok(data) => watchlist_file::parse_import(&attachment.filename, &data),
+            Err(_) => {
+                msg.reply(&ctx, "I couldn't download your file. Try again later.").await.ok();
+                return;
+            }
+        },
+        None => Ok(watchlist_file::parse_identifier_lines(list)),
+    };
+    let identifiers = match identifiers {
+        Ok(i) if !i.is_empty() => i,
+        Ok(_) => {
+            msg.reply(&ctx, "Nothing to import. Attach a file or put one show link per line after the command.").await.ok();
+            return;
+        }
+        Err(e) => {
+            let reason = match e {
+                ImportError::InvalidJson(e) => format!("That isn't a watchlist json file: {}", e),
+                ImportError::InvalidCsv(e) => format!("That isn't a watchlist csv file: {}", e),
+                ImportError::NotText => "I can only read json, csv and text files.".to_string(),
+            };
+            msg.reply(&ctx, reason).await.ok();
+            return;
+        }
+    };
+
+    let config = Config::from_context(&ctx).await;
+    let results = user_manager::import_user_shows(&config, msg.author.id.0 as i64, &identifiers).await;
+    let added = results.iter().filter(|(_, r)| r.is_ok()).count();
+    let mut report = vec![format!("Imported {} of {} shows:", added, results.len())];
+    for (identifier, result) in results {
+        report.push(match result {
+            Ok(show) => format!("✅ {}", show.name),
+            Err(failure) => format!("❌ `{}` {}", identifier, add_failure_reason(&failure)),
+        });
+    }
+    let skipped = identifiers.len().saturating_sub(user_manager::MAX_IMPORT_SHOWS);
+    if skipped > 0 {
+        report.push(format!("Skipped the last {} lines, an import adds at most {} shows.",
+                            skipped, user_manager::MAX_IMPORT_SHOWS));
+    }
+    send_report(&ctx, msg, &report).await;
+}
+
+async fn import_tracker(ctx: Context, msg: &Message, tracker: Tracker, user_name: &str) {
+    let config = Config::from_context(&ctx).await;
+    let res = user_manager::import_from_tracker(&config, msg.author.id.0 as i64, tracker, user_name).await;
+    let import = match res {
+        Ok(import) => import,
+        Err(e) => {
+            let reason = match e {
+                TrackerError::NotConfigured => "Importing from MyAnimeList isn't set up for this bot.".to_string(),
+                TrackerError::UserNotFound => format!("I couldn't find a public list of {}.", user_name),
+                TrackerError::Request(_) | TrackerError::InvalidResponse(_) =>
+                    "The list couldn't be fetched. Try again later.".to_string(),
+            };
+            msg.reply(&ctx, reason).await.ok();
+            return;
+        }
+    };
+    let added = import.added.iter().filter(|(_, r)| r.is_ok()).count();
+    let mut report = vec![format!("Found {} of your shows on subsplease, {} added:",
+                                  import.added.len(), added)];
+    for (title, result) in import.added {
+        report.push(match result {
+            Ok(show) => format!("✅ {} → {}", title, show.name),
+            Err(failure) => format!("❌ {} {}", title, add_failure_reason(&failure)),
+        });
+    }
+    if !import.unmatched.is_empty() {
+        report.push(format!("No subsplease release found for: {}", import.unmatched.join(", ")));
+    }
+    send_report(&ctx, msg, &report).await;
+}
+
+async fn link(ctx: Context, msg: &Message, args: &str) {
+    let config = Config::from_context(&ctx).await;
+    if !config.admins.contains(&msg.author.id.0) {
+        msg.reply(&ctx, "Only the admins of the bot can change the links of a show.").await.ok();
+        return;
+    }
+    let (site, rest) = split_at_fist_space(args).await;
+    let (id, identifier) = split_at_fist_space(&rest).await;
+    let site = match site.as_str() {
+        "mal" => Some(ListSite::MyAnimeList),
+        "anilist" => Some(ListSite::AniList),
+        "kitsu" => Some(ListSite::Kitsu),
+        _ => None
+    };
+    let reply = match (site, id.parse::<i64>(), identifier.trim()) {
+        (Some(site), Ok(id), identifier) if !identifier.is_empty() => {
+            match user_manager::set_external_id(identifier, site, id).await {
+                Ok(show) => format!("Links of {} updated: {}", show.name, show.links()),
+                Err(LinkFailure::ShowNotFound) => "I don't know that show. Add it first or check the name.".to_string(),
+                Err(LinkFailure::DBError) => "Error communicating with database. Try again later.".to_string(),
+            }
+        }
+        _ => "Use it like this: link <mal|anilist|kitsu> <id> <show link or name>".to_string()
+    };
+    msg.reply(&ctx, reply).await.ok();
+}
+
+/// Sends the lines of an import report in as few messages as possible.
+async fn send_report(ctx: &Context, msg: &Message, report: &[String]) {
+    for chunk in split_into_messages(report, MESSAGE_LIMIT) {
+        if let Err(e) = msg.channel_id.say(ctx, chunk).await {
+            return println!("Discord Error: {}", e);
+        }
+    }
+}
+
+/// Joins lines into as few messages as possible. Lines longer than the limit are cut.
+fn split_into_messages(lines: &[String], limit: usize) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        let line: String = line.chars().take(limit).collect();
+        if !current.is_empty() && current.chars().count() + line.chars().count() + 1 > limit {
+            messages.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() { current.push('\n'); }
+        current.push_str(&line);
+    }
+    if !current.is_empty() { messages.push(current); }
+    messages
+}
+
 async fn remove_na(ctx: Context, msg: Message) {
     let removed_shows_res = user_manager::remove_non_airing(msg.author.id.0 as i64).await;
-    match removed_shows_res {
+    let sent = match removed_shows_res {
         Ok(shows) if !shows.is_empty() => {
-            msg.channel_id.send_message(ctx, |m| {
-                m.content("");
-                m.embed(|e| {
-                    e.title("The following shows have been removed from your watchlist:");
-                    for show in shows.iter() {
-                        e.field(&show.name, &show.synopsis, false);
-                    }
-                    e
-                });
-                m
-            }).await
+            let mut embed = embed::EmbedPages::default();
+            embed.title("The following shows have been removed from your watchlist:");
+            for show in shows.iter() {
+                embed.field(&show.name, &show.synopsis, false);
+            }
+            embed.send(&ctx, msg.channel_id).await
         }
-        Ok(_) => msg.reply(ctx, "I haven't found any shows on your watchlist, that aren't airing.").await,
-        Err(_) => msg.reply(ctx, "Something went wrong and only some or no shows at \
-        all have been removed. Try again later or remove the rest manually.").await
-    }.ok();
+        Ok(_) => msg.reply(&ctx, "I haven't found any shows on your watchlist, that aren't airing.").await.map(|_| ()),
+        Err(_) => msg.reply(&ctx, "Something went wrong and no shows have been removed. \
+        Try again later.").await.map(|_| ())
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
 }
 
 async fn remove(ctx: Context, msg: Message, identifier: &str) {
     let res = user_manager::remove_user_show(msg.author.id.0 as i64, identifier).await;
-    match res {
-        Ok(()) => msg.reply(ctx, "Show from watchlist removed.").await.ok(),
-        Err(RemoveFailure::InvalidIdentifier) => msg.reply(ctx, "Invalid url.").await.ok(),
-        Err(RemoveFailure::DBError) => msg.reply(ctx, "Error communicating with database. Try again later.").await.ok(),
-        Err(RemoveFailure::ShowNotFound) => msg.reply(ctx, "I couldn't find a matching show in your watchlist.\
-            Give me a _correct_ the url of the show with this command.").await.ok()
-    };
-}
-
-async fn schedule(ctx: Context, msg: Message) {
-    let table_res = user_manager::generate_schedule(msg.author.id.0 as i64).await;
-    match table_res {
-        Ok(table_res) => {
-            let data = table_res.get_printable_table();
-            match data {
-                Ok(d) => {
-                    msg.channel_id.send_message(ctx, |m| {
-                        m.content("");
-                        m.embed(|e| {
-                            e.title("Currently Watching:");
-                            for (day, shows) in d {
-                                if shows != "".to_string() { e.field(day, shows, false); }
-                            };
-                            e
-                        });
-                        m
-                    }).await
+    let reply = match res {
+        Ok(()) => "Show from watchlist removed.",
+        Err(RemoveFailure::InvalidIdentifier) => "Invalid url.",
+        Err(RemoveFailure::DBError) => "Error communicating with database. Try again later.",
+        Err(RemoveFailure::ShowNotFound) => "I couldn't find a matching show in your watchlist. \
+            Give me a _correct_ the url of the show with this command.",
+    };
+    if let Err(e) = msg.reply(ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn schedule(ctx: Context, msg: Message, view: ScheduleView) {
+    let config = Config::from_context(&ctx).await;
+    let today = Utc::now().with_timezone(&config.schedule_timezone).weekday().num_days_from_monday() as usize;
+    let sent = match user_manager::generate_schedule(msg.author.id.0 as i64).await {
+        Ok(schedule) => {
+            let mut embed = embed::EmbedPages::default();
+            embed.title(match view {
+                ScheduleView::Today => "Airing today:",
+                ScheduleView::Tomorrow => "Airing tomorrow:",
+                ScheduleView::Week | ScheduleView::Upcoming => "Currently Watching:",
+            });
+            for (i, day) in schedule.view(view, today).into_iter().enumerate() {
+                let lines: Vec<String> = day.entries.iter()
+                    .map(|e| format!("{} - {}", e.clock_stamp(), e.name))
+                    .collect();
+                let name = if view == ScheduleView::Upcoming && i < 2 {
+                    format!("{} ({})", day.name(), if i == 0 { "today" } else { "tomorrow" })
+                } else {
+                    day.name().to_string()
+                };
+                match view {
+                    ScheduleView::Today | ScheduleView::Tomorrow if lines.is_empty() =>
+                        { embed.field(name, "Nothing on your watchlist airs.", false); }
+                    _ if lines.is_empty() => {}
+                    _ => { embed.field(name, lines.join("\n"), false); }
                 }
-                Err(_) => msg.reply(ctx, "Error Generating Table.").await
             }
+            if matches!(view, ScheduleView::Week | ScheduleView::Upcoming) && !schedule.non_airing.is_empty() {
+                embed.field("Not currently airing:", schedule.non_airing.join("\n"), false);
+            }
+            embed.send(&ctx, msg.channel_id).await
         }
-        Err(_) => msg.reply(ctx, "Something went wrong, try again later.").await
-    }.ok();
+        Err(_) => msg.reply(&ctx, "Something went wrong, try again later.").await.map(|_| ())
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn schedule_image(ctx: Context, msg: Message) {
+    let sent = match user_manager::generate_schedule(msg.author.id.0 as i64).await {
+        Ok(schedule) if schedule.is_empty() =>
+            msg.reply(&ctx, "None of the shows on your watchlist are airing.").await.map(|_| ()),
+        Ok(schedule) => match schedule_image::render(&schedule) {
+            Ok(data) => msg.channel_id.send_message(&ctx, |m| {
+                m.add_file(AttachmentType::Bytes { data: Cow::from(data), filename: "schedule.png".to_string() })
+            }).await.map(|_| ()),
+            Err(e) => {
+                println!("Error rendering schedule: {}", e);
+                msg.reply(&ctx, "Error Generating Table.").await.map(|_| ())
+            }
+        },
+        Err(_) => msg.reply(&ctx, "Something went wrong, try again later.").await.map(|_| ())
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+/// Reads "today", "tomorrow" or a week day name, which may be shortened to three letters.
+fn parse_week_day(arg: &str, today: usize) -> Option<usize> {
+    let arg = arg.trim().to_lowercase();
+    match arg.as_str() {
+        "" | "today" => Some(today),
+        "tomorrow" => Some((today + 1) % 7),
+        _ if arg.len() >= 3 => AirTime::weekdays().iter()
+            .position(|d| d.to_lowercase().starts_with(&arg)),
+        _ => None,
+    }
+}
+
+/// The letter emoji 🇦 to 🇹 used to pick the show at `index`.
+fn letter_emoji(index: usize) -> String {
+    char::from_u32(0x1F1E6 + index as u32).map(String::from).unwrap_or_default()
+}
+
+async fn airing(ctx: Context, msg: Message, day: &str) {
+    let config = Config::from_context(&ctx).await;
+    let today = Utc::now().with_timezone(&config.schedule_timezone).weekday().num_days_from_monday() as usize;
+    let week_day = match parse_week_day(day, today) {
+        Some(week_day) => week_day,
+        None => {
+            msg.reply(&ctx, "Use it like this: airing <today|tomorrow|monday|...>").await.ok();
+            return;
+        }
+    };
+    let user_id = msg.author.id.0 as i64;
+    let shows = match user_manager::get_airing(&config, user_id, week_day).await {
+        Ok(shows) => shows,
+        Err(AiringFailure::ScheduleUnavailable) => {
+            msg.reply(&ctx, "I couldn't get the schedule from subsplease. Try again later.").await.ok();
+            return;
+        }
+        Err(AiringFailure::DBError) => {
+            msg.reply(&ctx, "Error communicating with database. Try again later.").await.ok();
+            return;
+        }
+    };
+
+    // letters for the shows that can still be added
+    let addable: Vec<&AiringShow> = shows.iter().filter(|s| !s.on_watchlist).take(MAX_REACTIONS).collect();
+    let lines: Vec<String> = shows.iter()
+        .map(|s| {
+            let mark = if s.on_watchlist {
+                "✅".to_string()
+            } else {
+                addable.iter().position(|a| a.show_id == s.show_id).map_or("▫️".to_string(), letter_emoji)
+            };
+            format!("{} {} [{}]({})", mark, s.time, s.title, watchlist_file::show_url(&s.show_id))
+        })
+        .collect();
+    let mut embed = embed::EmbedPages::default();
+    embed.title(format!("Airing on {} ({})", AirTime::weekdays()[week_day], config.schedule_timezone.name()));
+    if lines.is_empty() {
+        embed.description("Nothing.");
+    }
+    for chunk in split_into_messages(&lines, embed::MAX_FIELD_VALUE) {
+        embed.field("", chunk, false);
+    }
+    if !addable.is_empty() {
+        embed.field("Quick add", "✅ is on your watchlist. React with a letter to add that show.", false);
+    }
+    // the letters go on the last page, under the quick add hint
+    let mut last_page = None;
+    for page in embed.build() {
+        match msg.channel_id.send_message(&ctx, |m| m.set_embed(page)).await {
+            Ok(sent) => last_page = Some(sent),
+            Err(e) => return println!("Discord Error: {}", e),
+        }
+    }
+    let airing_msg = match last_page {
+        Some(airing_msg) => airing_msg,
+        None => return,
+    };
+    for i in 0..addable.len() {
+        airing_msg.react(&ctx, ReactionType::Unicode(letter_emoji(i))).await.ok();
+    }
+    if addable.is_empty() { return; }
+
+    let mut picks = airing_msg.await_reactions(&ctx)
+        .author_id(msg.author.id)
+        .added(true)
+        .timeout(LIST_TIMEOUT)
+        .await;
+    while let Some(action) = picks.next().await {
+        let emoji = &action.as_inner_ref().emoji;
+        let show = match (0..addable.len()).find(|&i| emoji.unicode_eq(&letter_emoji(i))) {
+            Some(i) => addable[i],
+            None => continue,
+        };
+        let url = watchlist_file::show_url(&show.show_id);
+        let reply = match user_manager::add_user_show(&config, user_id, &url).await {
+            Ok(added) => format!("Added {} to your watchlist.", added.name),
+            Err(failure) => format!("{}: {}", show.title, add_failure_reason(&failure)),
+        };
+        if let Err(e) = msg.channel_id.say(&ctx, reply).await {
+            println!("Discord Error: {}", e);
+        }
+    }
+}
+
+async fn season(ctx: Context, msg: Message) {
+    let config = Config::from_context(&ctx).await;
+    let sent = match user_manager::get_season(&config, msg.author.id.0 as i64).await {
+        Ok((season, shows)) if shows.is_empty() =>
+            msg.reply(&ctx, format!("I haven't seen any new shows in {} yet.", season)).await.map(|_| ()),
+        Ok((season, shows)) => {
+            let lines: Vec<String> = shows.iter()
+                .map(|(id, title, on_watchlist)| format!("{} [{}]({})", if *on_watchlist { "✅" } else { "▫️" },
+                                                         title, watchlist_file::show_url(id)))
+                .collect();
+            let mut embed = embed::EmbedPages::default();
+            embed.title(format!("New in {} ({} shows)", season, shows.len()));
+            for chunk in split_into_messages(&lines, embed::MAX_FIELD_VALUE) {
+                embed.field("", chunk, false);
+            }
+            embed.send(&ctx, msg.channel_id).await
+        }
+        Err(_) => msg.reply(&ctx, "Error communicating with database. Try again later.").await.map(|_| ())
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn season_digest(ctx: Context, msg: Message, enabled: bool) {
+    let reply = match user_manager::set_season_digest(msg.author.id.0 as i64, enabled).await {
+        Ok(()) if enabled => "You'll get a list of the new shows every Monday.",
+        Ok(()) => "No more weekly new show lists.",
+        Err(_) => "Error communicating with database. Try again later.",
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+/// Parses "instant", "hourly", "daily <hour>" or "weekly <day> <hour>". Hours may be
+/// given like "18" or "18:00".
+fn parse_delivery_args(args: &str, today: usize) -> Option<DeliveryMode> {
+    let parse_hour = |arg: &str| arg.strip_suffix(":00").unwrap_or(arg).parse::<u32>().ok().filter(|&h| h < 24);
+    let args: Vec<String> = args.split_whitespace().map(|a| a.to_lowercase()).collect();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["instant"] => Some(DeliveryMode::Instant),
+        ["hourly"] => Some(DeliveryMode::Hourly),
+        ["daily", hour] => Some(DeliveryMode::Daily { hour: parse_hour(hour)? }),
+        ["weekly", day, hour] => Some(DeliveryMode::Weekly {
+            week_day: parse_week_day(day, today)? as u32,
+            hour: parse_hour(hour)?,
+        }),
+        _ => None,
+    }
+}
+
+async fn delivery(ctx: Context, msg: Message, args: &str) {
+    let config = Config::from_context(&ctx).await;
+    let user_id = msg.author.id.0 as i64;
+    let settings = match user_manager::get_delivery_settings(user_id).await {
+        Ok(settings) => settings,
+        Err(_) => {
+            if let Err(e) = msg.reply(&ctx, "Error communicating with database. Try again later.").await {
+                println!("Discord Error: {}", e);
+            }
+            return;
+        }
+    };
+    let tz = settings.timezone(config.schedule_timezone);
+    let reply = if args.is_empty() && settings.mode == DeliveryMode::Instant {
+        format!("Releases are delivered {}.", settings.mode)
+    } else if args.is_empty() {
+        format!("Releases are delivered {} ({} time).", settings.mode, tz.name())
+    } else {
+        let today = Utc::now().with_timezone(&tz).weekday().num_days_from_monday() as usize;
+        match parse_delivery_args(args, today) {
+            Some(mode) => match user_manager::set_delivery_mode(user_id, mode).await {
+                Ok(()) if mode == DeliveryMode::Instant => "You'll get a message for every release again.".to_string(),
+                Ok(()) => format!("Releases are now delivered {} ({} time).", mode, tz.name()),
+                Err(_) => "Error communicating with database. Try again later.".to_string(),
+            },
+            None => "Use it like this: delivery <instant|hourly|daily 18|weekly sunday 18>".to_string(),
+        }
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+/// Parses quiet hours like "23-7", optionally followed by "separate".
+fn parse_quiet_hours(args: &str) -> Option<QuietHours> {
+    let mut args = args.split_whitespace();
+    let (start, end) = args.next()?.split_once('-')?;
+    let (start, end) = (start.trim().parse::<u32>().ok()?, end.trim().parse::<u32>().ok()?);
+    let batched = match args.next() {
+        None => true,
+        Some(arg) if arg.eq_ignore_ascii_case("separate") => false,
+        Some(_) => return None,
+    };
+    if start > 23 || end > 23 || start == end || args.next().is_some() { return None; }
+    Some(QuietHours { start, end, batched })
+}
+
+async fn quiet(ctx: Context, msg: Message, args: &str) {
+    let config = Config::from_context(&ctx).await;
+    let user_id = msg.author.id.0 as i64;
+    let db_error = "Error communicating with database. Try again later.".to_string();
+    let reply = if args.is_empty() {
+        match user_manager::get_delivery_settings(user_id).await {
+            Ok(settings) => {
+                let tz = settings.timezone(config.schedule_timezone);
+                match settings.quiet_hours {
+                    Some(q) => format!("Quiet hours are {} ({} time), releases come {} afterwards.", q, tz.name(),
+                                       if q.batched { "in one message" } else { "one by one" }),
+                    None => format!("No quiet hours set. Your timezone is {}.", tz.name()),
+                }
+            }
+            Err(_) => db_error,
+        }
+    } else if args == "off" {
+        match user_manager::set_quiet_hours(user_id, None).await {
+            Ok(()) => "Quiet hours are off, releases come whenever they are out.".to_string(),
+            Err(_) => db_error,
+        }
+    } else if let Some(tz) = args.strip_prefix("timezone ") {
+        match tz.trim().parse() {
+            Ok(tz) => match user_manager::set_timezone(user_id, tz).await {
+                Ok(()) => format!("Your quiet hours are now in {} time.", tz.name()),
+                Err(_) => db_error,
+            },
+            Err(_) => "I don't know that timezone. Use a name like Europe/Berlin or America/New_York.".to_string(),
+        }
+    } else {
+        match parse_quiet_hours(args) {
+            Some(q) => match user_manager::set_quiet_hours(user_id, Some(q)).await {
+                Ok(()) => format!("No messages from {} on. \"quiet\" shows which timezone that is in.", q),
+                Err(_) => db_error,
+            },
+            None => "Use it like this: quiet <23-7|23-7 separate|off|timezone Europe/Berlin>".to_string(),
+        }
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ChannelArgs {
+    Link(Channel),
+    Enable(ChannelKind, bool),
+    Remove(ChannelKind),
+    Confirm(ChannelKind, String),
+}
+
+fn parse_channel_args(args: &str) -> Option<ChannelArgs> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let (kind, rest) = parts.split_first()?;
+    let kind = ChannelKind::parse(kind)?;
+    let (target, token) = match (kind, rest) {
+        (_, ["on"]) => return Some(ChannelArgs::Enable(kind, true)),
+        (_, ["off"]) => return Some(ChannelArgs::Enable(kind, false)),
+        (ChannelKind::Discord, _) => return None,
+        (_, ["remove"]) => return Some(ChannelArgs::Remove(kind)),
+        (_, ["confirm", code]) => return Some(ChannelArgs::Confirm(kind, code.to_string())),
+        (ChannelKind::Telegram, [chat]) if chat.starts_with('@') || chat.parse::<i64>().is_ok() => (chat, None),
+        (ChannelKind::Matrix, [room]) if (room.starts_with('!') || room.starts_with('#')) && room.contains(':') =>
+            (room, None),
+        (ChannelKind::Ntfy, [url]) => (url, None),
+        (ChannelKind::Ntfy, [url, token]) | (ChannelKind::Gotify, [url, token]) => (url, Some(token)),
+        (ChannelKind::Email, [address]) if address.contains('@') => (address, None),
+        _ => return None,
+    };
+    let target = match kind {
+        ChannelKind::Ntfy | ChannelKind::Gotify => {
+            let url = target.trim_end_matches('/');
+            if !url.starts_with("http://") && !url.starts_with("https://") { return None; }
+            // a topic url needs the topic after the server
+            if kind == ChannelKind::Ntfy && url.splitn(4, '/').nth(3).is_none_or(str::is_empty) { return None; }
+            url
+        }
+        _ => target,
+    };
+    Some(ChannelArgs::Link(Channel {
+        kind,
+        target: target.to_string(),
+        token: token.map(|t| t.to_string()),
+        enabled: true,
+        confirmed: false,
+    }))
+}
+
+async fn channels(ctx: Context, msg: Message) {
+    let reply = match user_manager::get_channels(msg.author.id.0 as i64).await {
+        Ok(channels) => {
+            let lines: Vec<String> = channels.iter().map(|c| match c.kind {
+                ChannelKind::Discord => format!("**Discord** DMs: {}", if c.enabled { "on" } else { "off" }),
+                _ if !c.confirmed => format!("**{}** {}: waiting for the code", c.kind.name(), c.target),
+                _ => format!("**{}** {}: {}", c.kind.name(), c.target, if c.enabled { "on" } else { "off" }),
+            }).collect();
+            format!("New releases go to:\n{}", lines.join("\n"))
+        }
+        Err(_) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn channel(ctx: Context, msg: Message, args: &str) {
+    let user_id = msg.author.id.0 as i64;
+    let result = match parse_channel_args(args) {
+        Some(ChannelArgs::Link(channel)) => {
+            let config = Config::from_context(&ctx).await;
+            let kind = channel.kind;
+            user_manager::link_channel(&config, user_id, channel).await
+                .map(|()| format!("Sent a code to your {}. Send \"channel {} confirm <code>\" and new releases \
+                                   will go there as well.", kind.name(), kind.id()))
+        }
+        Some(ChannelArgs::Confirm(kind, code)) => {
+            let config = Config::from_context(&ctx).await;
+            user_manager::confirm_channel(&config, user_id, kind, &code).await
+                .map(|()| format!("{} is confirmed, new releases will go there as well.", kind.name()))
+        }
+        Some(ChannelArgs::Enable(kind, enabled)) => user_manager::set_channel_enabled(user_id, kind, enabled).await
+            .map(|()| format!("{} is {} for new releases.", kind.name(), if enabled { "on" } else { "off" })),
+        Some(ChannelArgs::Remove(kind)) => user_manager::remove_channel(user_id, kind).await
+            .map(|()| format!("Your {} is deleted.", kind.name())),
+        None => Ok("Use it like this: channel <telegram|matrix|ntfy|gotify|email> <where to> [token], \
+                    channel <name> <on|off|remove|confirm <code>>".to_string()),
+    };
+    let reply = match result {
+        Ok(reply) => reply,
+        Err(ChannelFailure::NotConfigured) => "Channels with tokens aren't set up for this bot.".to_string(),
+        Err(ChannelFailure::NotLinked) => "You haven't added that one. \"channel\" shows what you have.".to_string(),
+        Err(ChannelFailure::NotConfirmed) => "That one isn't confirmed yet. Send the code it got with \
+                                              \"channel <name> confirm <code>\".".to_string(),
+        Err(ChannelFailure::WrongCode) => "That's not the code, or the channel is confirmed already.".to_string(),
+        Err(ChannelFailure::Failed(e)) => format!("Couldn't send the code: {}", e),
+        Err(ChannelFailure::DBError) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn premiumize_status(ctx: Context, msg: Message) {
+    let reply = match user_manager::has_premiumize_key(msg.author.id.0 as i64).await {
+        Ok(true) => "New releases on your watchlist go to Premiumize. \"premiumize off\" stops that.",
+        Ok(false) => "No Premiumize api key saved. Add one with \"premiumize <api key>\".",
+        Err(_) => "Error communicating with database. Try again later.",
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn premiumize_off(ctx: Context, msg: Message) {
+    let reply = match user_manager::remove_premiumize_key(msg.author.id.0 as i64).await {
+        Ok(()) => "Your Premiumize api key is deleted.",
+        Err(_) => "Error communicating with database. Try again later.",
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn premiumize_key(ctx: Context, msg: Message, api_key: &str) {
+    let config = Config::from_context(&ctx).await;
+    let reply = match user_manager::set_premiumize_key(&config, msg.author.id.0 as i64, api_key.trim()).await {
+        Ok(()) => "Saved. New releases on your watchlist will go straight to your Premiumize cloud.".to_string(),
+        Err(PremiumizeFailure::NotConfigured) => "Premiumize transfers aren't set up for this bot.".to_string(),
+        Err(PremiumizeFailure::InvalidKey(reason)) => format!("Premiumize didn't accept that key: {}", reason),
+        Err(PremiumizeFailure::Unreachable) => "Couldn't reach Premiumize. Try again later.".to_string(),
+        Err(PremiumizeFailure::DBError) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+enum ClientArgs {
+    Client(ClientSettings),
+    Category(Option<String>),
+    SavePath(Option<String>),
+}
+
+fn parse_client_args(args: &str) -> Option<ClientArgs> {
+    let (first, rest) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+    let value = match rest.trim() {
+        "" => return None,
+        "none" => None,
+        value => Some(value.to_string()),
+    };
+    match first.to_lowercase().as_str() {
+        "category" => return Some(ClientArgs::Category(value)),
+        "path" => return Some(ClientArgs::SavePath(value)),
+        _ => {}
+    }
+    let kind = ClientKind::parse(first)?;
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    let (url, username, password) = match (kind, parts.as_slice()) {
+        (ClientKind::Deluge, [url, password]) => (url, None, Some(password)),
+        (ClientKind::Deluge, _) => return None,
+        (_, [url]) => (url, None, None),
+        (_, [url, username, password]) => (url, Some(username), Some(password)),
+        _ => return None,
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return None;
+    }
+    Some(ClientArgs::Client(ClientSettings {
+        kind,
+        url: url.trim_end_matches('/').to_string(),
+        username: username.map(|u| u.to_string()),
+        password: password.map(|p| p.to_string()),
+        options: DownloadOptions::default(),
+    }))
+}
+
+fn describe_options(options: &DownloadOptions) -> String {
+    format!("category {}, save path {}",
+            options.category.as_deref().unwrap_or("none"), options.save_path.as_deref().unwrap_or("default"))
+}
+
+async fn client_status(ctx: Context, msg: Message) {
+    let reply = match user_manager::get_download_client(msg.author.id.0 as i64).await {
+        Ok(Some(client)) => format!("New releases on your watchlist go to {} at <{}> ({}). \"client off\" stops that.",
+                                    client.kind.name(), client.url, describe_options(&client.options)),
+        Ok(None) => "No download client saved. Add one with \"client <qbittorrent|transmission|deluge> <url> ...\".".to_string(),
+        Err(_) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn client_off(ctx: Context, msg: Message) {
+    let reply = match user_manager::remove_download_client(msg.author.id.0 as i64).await {
+        Ok(()) => "Your download client is deleted.",
+        Err(_) => "Error communicating with database. Try again later.",
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn client(ctx: Context, msg: Message, args: &str) {
+    let user_id = msg.author.id.0 as i64;
+    let result = match parse_client_args(args) {
+        Some(ClientArgs::Client(settings)) => {
+            let config = Config::from_context(&ctx).await;
+            let name = settings.kind.name();
+            user_manager::set_download_client(&config, user_id, settings).await
+                .map(|()| format!("Saved. New releases on your watchlist will go straight to {}.", name))
+        }
+        Some(ClientArgs::Category(category)) =>
+            user_manager::set_download_options(user_id, |options| options.category = category).await
+                .map(|options| format!("Done, {}.", describe_options(&options))),
+        Some(ClientArgs::SavePath(path)) =>
+            user_manager::set_download_options(user_id, |options| options.save_path = path).await
+                .map(|options| format!("Done, {}.", describe_options(&options))),
+        None => Ok("Use it like this: client <qbittorrent|transmission> <url> [user password], \
+                    client deluge <url> <password>, client <category|path> <value|none> or client off".to_string()),
+    };
+    let reply = match result {
+        Ok(reply) => reply,
+        Err(ClientFailure::NotConfigured) => "Download clients with passwords aren't set up for this bot.".to_string(),
+        Err(ClientFailure::NoClient) => "Add a download client first.".to_string(),
+        Err(ClientFailure::Client(DownloadError::Unauthorized)) =>
+            "Your client didn't accept the username or password.".to_string(),
+        Err(ClientFailure::Client(e)) => format!("That didn't work: {}", e),
+        Err(ClientFailure::DBError) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn webhook_status(ctx: Context, msg: Message) {
+    let reply = match user_manager::get_webhook(msg.author.id.0 as i64).await {
+        Ok(Some((url, _))) => format!("New releases on your watchlist are posted to <{}>. \"webhook test\" tries it, \
+                                       \"webhook off\" stops it.", url),
+        Ok(None) => "No webhook saved. Add one with \"webhook <url>\".".to_string(),
+        Err(_) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn webhook_off(ctx: Context, msg: Message) {
+    let reply = match user_manager::remove_webhook(msg.author.id.0 as i64).await {
+        Ok(()) => "Your webhook is deleted.",
+        Err(_) => "Error communicating with database. Try again later.",
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn webhook_test(ctx: Context, msg: Message) {
+    let reply = match user_manager::test_webhook(msg.author.id.0 as i64).await {
+        Ok(()) => "Your webhook took the test release.".to_string(),
+        Err(WebhookFailure::NoWebhook) => "No webhook saved. Add one with \"webhook <url>\".".to_string(),
+        Err(WebhookFailure::Failed(e)) => format!("The test release didn't arrive: {}", e),
+        Err(WebhookFailure::NotAllowed(e)) => format!("The test release didn't arrive: {}", e),
+        Err(WebhookFailure::DBError) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+/// Saves a new url, or makes a new secret for the saved one without `url`.
+async fn webhook(ctx: Context, msg: Message, url: Option<&str>) {
+    let url = url.map(str::trim);
+    if url.is_some_and(|url| !url.starts_with("http://") && !url.starts_with("https://")) {
+        msg.reply(&ctx, "Use it like this: webhook <https://...|test|secret|off>").await.ok();
+        return;
+    }
+    let reply = match user_manager::set_webhook(msg.author.id.0 as i64, url, url.is_none()).await {
+        Ok((url, secret)) => format!("New releases on your watchlist will be posted to <{}>. The {} header \
+                                      is the HMAC-SHA256 of the body with the secret `{}`, keep it to yourself.",
+                                     url, webhooks::SIGNATURE_HEADER, secret),
+        Err(WebhookFailure::NoWebhook) => "No webhook saved. Add one with \"webhook <url>\".".to_string(),
+        Err(WebhookFailure::NotAllowed(e)) => format!("I can't post to that url: {}.", e),
+        Err(WebhookFailure::Failed(_)) | Err(WebhookFailure::DBError) =>
+            "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn ical(ctx: Context, msg: Message) {
+    let config = Config::from_context(&ctx).await;
+    let sent = match user_manager::get_calendar(&config, msg.author.id.0 as i64).await {
+        Ok(calendar) => msg.channel_id.send_message(&ctx, |m| {
+            m.content("Import this into your calendar app:");
+            m.add_file(AttachmentType::Bytes { data: Cow::from(calendar.into_bytes()), filename: "watchlist.ics".to_string() })
+        }).await.map(|_| ()),
+        Err(_) => msg.reply(&ctx, "Error communicating with database. Try again later.").await.map(|_| ())
+    };
+    if let Err(e) = sent {
+        println!("Discord Error: {}", e);
+    }
+}
+
+async fn ical_url(ctx: Context, msg: Message, reset: bool) {
+    let config = Config::from_context(&ctx).await;
+    let reply = match user_manager::get_calendar_url(&config, msg.author.id.0 as i64, reset).await {
+        Ok(url) => format!("Subscribe to this url in your calendar app. It always shows your current watchlist, \
+                            so keep it to yourself: <{}>", url),
+        Err(CalendarFailure::NotConfigured) => "Calendar links aren't set up for this bot. Use \"ical\" for a file.".to_string(),
+        Err(CalendarFailure::DBError) => "Error communicating with database. Try again later.".to_string(),
+    };
+    if let Err(e) = msg.reply(&ctx, reply).await {
+        println!("Discord Error: {}", e);
+    }
 }
 
 async fn examples(ctx: Context, msg: Message) {
@@ -155,10 +1143,146 @@ async fn examples(ctx: Context, msg: Message) {
         remove https://subsplease.org/shows/one-piece/
         -- also possible
         remove One Piece
+        -- everything about a show
+        info Kingdom S3
+        -- everything stored about me
+        mydata
         -- delete everything about me
-        unregister
+        unregister confirm
         -- display schedule
-        schedule```
+        schedule
+        schedule today
+        schedule image
+        -- everything airing on friday
+        airing fri
+        -- new shows every week
+        season digest on
+        -- all releases of the day in one message at 20:00
+        delivery daily 20
+        -- no messages at night
+        quiet timezone Europe/London
+        quiet 23-7
+        -- releases on your phone instead of Discord
+        channel ntfy https://ntfy.sh/my-releases
+        channel discord off
+        -- download new releases to your Premiumize cloud
+        premiumize <your api key>
+        -- download new releases with qBittorrent into a category
+        client qbittorrent https://seedbox.example.com:8080 admin <password>
+        client category anime
+        -- post new releases to your own automation
+        webhook https://example.com/hook
+        webhook test
+        -- subscribe to your watchlist in a calendar app
+        ical url
+        -- page through your watchlist, next airing first
+        list day airing
+        -- save your watchlist and add it again later
+        export csv
+        import https://subsplease.org/shows/one-piece/
+               https://subsplease.org/shows/boruto/
+        import anilist yukinoshita
+        -- fix the MyAnimeList entry of a show
+        link mal 21 https://subsplease.org/shows/one-piece/```
         "
     ).await.ok();
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_parse_list_args() {
+    assert_eq!(parse_list_args(""), Some((ListSort::Name, ListFilter::All)));
+    assert_eq!(parse_list_args("airing added"), Some((ListSort::Added, ListFilter::Airing)));
+    assert_eq!(parse_list_args("day non-airing"), Some((ListSort::AirDay, ListFilter::NonAiring)));
+    assert_eq!(parse_list_args("by name"), None);
+}
+
+#[test]
+fn test_parse_week_day() {
+    assert_eq!(parse_week_day("", 2), Some(2));
+    assert_eq!(parse_week_day("tomorrow", 6), Some(0));
+    assert_eq!(parse_week_day("Friday", 0), Some(4));
+    assert_eq!(parse_week_day("sun", 0), Some(6));
+    assert_eq!(parse_week_day("t", 0), None);
+    assert_eq!(parse_week_day("someday", 0), None);
+    assert_eq!(letter_emoji(1), "🇧");
+}
+
+#[test]
+fn test_parse_delivery_args() {
+    assert_eq!(parse_delivery_args("instant", 0), Some(DeliveryMode::Instant));
+    assert_eq!(parse_delivery_args("Hourly", 0), Some(DeliveryMode::Hourly));
+    assert_eq!(parse_delivery_args("daily 18", 0), Some(DeliveryMode::Daily { hour: 18 }));
+    assert_eq!(parse_delivery_args("daily 07:00", 0), Some(DeliveryMode::Daily { hour: 7 }));
+    assert_eq!(parse_delivery_args("weekly sun 9", 0), Some(DeliveryMode::Weekly { week_day: 6, hour: 9 }));
+    assert_eq!(parse_delivery_args("weekly today 9", 3), Some(DeliveryMode::Weekly { week_day: 3, hour: 9 }));
+    assert_eq!(parse_delivery_args("daily 24", 0), None);
+    assert_eq!(parse_delivery_args("daily", 0), None);
+    assert_eq!(parse_delivery_args("weekly 9", 0), None);
+    assert_eq!(parse_delivery_args("sometimes", 0), None);
+}
+
+#[test]
+fn test_parse_quiet_hours() {
+    assert_eq!(parse_quiet_hours("23-7"), Some(QuietHours { start: 23, end: 7, batched: true }));
+    assert_eq!(parse_quiet_hours("9-17 Separate"), Some(QuietHours { start: 9, end: 17, batched: false }));
+    assert_eq!(parse_quiet_hours("7-7"), None);
+    assert_eq!(parse_quiet_hours("23-24"), None);
+    assert_eq!(parse_quiet_hours("23"), None);
+    assert_eq!(parse_quiet_hours("23-7 sometimes"), None);
+}
+
+#[test]
+fn test_parse_client_args() {
+    let client = |args| match parse_client_args(args) {
+        Some(ClientArgs::Client(settings)) => Some(settings),
+        _ => None,
+    };
+    let qbittorrent = client("qBittorrent http://10.0.0.2:8080/ admin secret").unwrap();
+    assert_eq!(qbittorrent.kind, ClientKind::QBittorrent);
+    assert_eq!(qbittorrent.url, "http://10.0.0.2:8080");
+    assert_eq!(qbittorrent.username.as_deref(), Some("admin"));
+    assert_eq!(qbittorrent.password.as_deref(), Some("secret"));
+    let transmission = client("transmission https://seedbox.example").unwrap();
+    assert_eq!((transmission.username, transmission.password), (None, None));
+    let deluge = client("deluge http://localhost:8112 deluge").unwrap();
+    assert_eq!((deluge.username, deluge.password.as_deref()), (None, Some("deluge")));
+    assert!(client("deluge http://localhost:8112").is_none());
+    assert!(client("transmission http://localhost:9091 admin").is_none());
+    assert!(client("qbittorrent 10.0.0.2:8080").is_none());
+    assert!(client("utorrent http://localhost").is_none());
+    assert!(matches!(parse_client_args("category anime"), Some(ClientArgs::Category(Some(c))) if c == "anime"));
+    assert!(matches!(parse_client_args("path none"), Some(ClientArgs::SavePath(None))));
+    assert!(parse_client_args("path").is_none());
+}
+
+#[test]
+fn test_parse_channel_args() {
+    let link = |kind, target: &str, token: Option<&str>| Some(ChannelArgs::Link(Channel {
+        kind, target: target.to_string(), token: token.map(str::to_string), enabled: true, confirmed: false }));
+    assert_eq!(parse_channel_args("telegram 123456"), link(ChannelKind::Telegram, "123456", None));
+    assert_eq!(parse_channel_args("Matrix !abc:matrix.org"), link(ChannelKind::Matrix, "!abc:matrix.org", None));
+    assert_eq!(parse_channel_args("ntfy https://ntfy.sh/releases/ tk_1"),
+               link(ChannelKind::Ntfy, "https://ntfy.sh/releases", Some("tk_1")));
+    assert_eq!(parse_channel_args("gotify https://push.example.org app"),
+               link(ChannelKind::Gotify, "https://push.example.org", Some("app")));
+    assert_eq!(parse_channel_args("email me@example.org"), link(ChannelKind::Email, "me@example.org", None));
+    assert_eq!(parse_channel_args("discord off"), Some(ChannelArgs::Enable(ChannelKind::Discord, false)));
+    assert_eq!(parse_channel_args("email remove"), Some(ChannelArgs::Remove(ChannelKind::Email)));
+    assert_eq!(parse_channel_args("telegram confirm 042917"),
+               Some(ChannelArgs::Confirm(ChannelKind::Telegram, "042917".to_string())));
+    assert_eq!(parse_channel_args("discord confirm 042917"), None);
+    assert_eq!(parse_channel_args("discord remove"), None);
+    assert_eq!(parse_channel_args("telegram someone"), None);
+    assert_eq!(parse_channel_args("matrix #anime"), None);
+    assert_eq!(parse_channel_args("ntfy https://ntfy.sh/"), None);
+    assert_eq!(parse_channel_args("gotify https://push.example.org"), None);
+    assert_eq!(parse_channel_args("signal +49123"), None);
+}
+
+#[test]
+fn test_split_into_messages() {
+    let lines: Vec<String> = vec!["a".repeat(6), "b".repeat(3), "c".repeat(12), "d".to_string()];
+    assert_eq!(split_into_messages(&lines, 10), vec![
+        format!("{}\n{}", "a".repeat(6), "b".repeat(3)), "c".repeat(10), "d".to_string()]);
+    assert!(split_into_messages(&[], 10).is_empty());
+}
@@ -1,10 +1,15 @@
 use serenity::client::Context;
 use serenity::model::channel::Message;
-use super::split_at_fist_space;
+use super::{embed, split_at_fist_space};
+use crate::config::Config;
+use crate::metrics::METRICS;
 use crate::user_manager;
 
+const COMMANDS: [&str; 2] = ["register", "help"];
+
 pub async fn main(ctx: Context, msg: Message) {
     let (op, arg) = split_at_fist_space(&msg.content).await;
+    METRICS.command_handled(if COMMANDS.contains(&op.as_str()) { &op } else { "unknown" });
     match (op.as_str(), arg.as_str()) {
         ("register", _) => { register(ctx, msg).await; }
         ("help", _) => { help(ctx, msg).await; }
@@ -13,26 +18,25 @@ pub async fn main(ctx: Context, msg: Message) {
 }
 
 async fn register(ctx: Context, msg: Message) {
-    let register_res = user_manager::register_user(msg.author.id.0 as i64).await;
+    let config = Config::from_context(&ctx).await;
+    let register_res = user_manager::register_user(&config, msg.author.id.0 as i64).await;
     let reply = match register_res {
-        Ok(_) => "Successfully registered!",
-        Err(_) => "An error has occurred while registering. Please try again later."
+        Ok(0) => "Successfully registered!".to_string(),
+        Ok(restored) => format!("Welcome back! Your watchlist with {} shows is restored.", restored),
+        Err(_) => "An error has occurred while registering. Please try again later.".to_string()
     };
     msg.reply(ctx, reply).await.ok();
 }
 
 async fn help(ctx: Context, msg: Message) {
-    let titles = ["register", "help"];
+    let titles = COMMANDS;
     let descriptions = ["Type this to unlock the functionality of the bot. Your UserID will be saved.",
         "Shows this message"];
-    msg.channel_id.send_message(ctx, |m| {
-        m.content("");
-        m.embed(|e| {
-            for (t, d) in titles.iter().zip(&descriptions) {
-                e.field(t, d, false);
-            }
-            e
-        });
-        m
-    }).await.ok();
+    let mut embed = embed::EmbedPages::default();
+    for (t, d) in titles.iter().zip(&descriptions) {
+        embed.field(t, d, false);
+    }
+    if let Err(e) = embed.send(&ctx, msg.channel_id).await {
+        println!("Discord Error: {}", e);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Process wide counters and health flags. Everything is kept in memory and only
+/// exposed through the http server.
+pub struct Metrics {
+    rss_polls: AtomicU64,
+    rss_parse_errors: AtomicU64,
+    notifications_sent: AtomicU64,
+    notifications_failed: AtomicU64,
+    commands: Mutex<BTreeMap<String, u64>>,
+    gateway_connected: AtomicBool,
+    last_rss_poll: AtomicI64,
+}
+
+lazy_static::lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+/// Counts that are read from the database when the metrics are scraped.
+pub struct DbGauges {
+    pub registered_users: i64,
+    pub tracked_shows: i64,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        Metrics {
+            rss_polls: AtomicU64::new(0),
+            rss_parse_errors: AtomicU64::new(0),
+            notifications_sent: AtomicU64::new(0),
+            notifications_failed: AtomicU64::new(0),
+            commands: Mutex::new(BTreeMap::new()),
+            gateway_connected: AtomicBool::new(false),
+            last_rss_poll: AtomicI64::new(0),
+        }
+    }
+
+    pub fn rss_poll_succeeded(&self) {
+        self.rss_polls.fetch_add(1, Ordering::Relaxed);
+        self.last_rss_poll.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn rss_parse_failed(&self) {
+        self.rss_polls.fetch_add(1, Ordering::Relaxed);
+        self.rss_parse_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn notification_sent(&self) {
+        self.notifications_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn notification_failed(&self) {
+        self.notifications_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_handled(&self, command: &str) {
+        let mut commands = self.commands.lock().unwrap();
+        *commands.entry(command.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn set_gateway_connected(&self, connected: bool) {
+        self.gateway_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn is_gateway_connected(&self) -> bool {
+        self.gateway_connected.load(Ordering::Relaxed)
+    }
+
+    /// Seconds since the feed was last fetched and parsed, `None` if that never happened.
+    pub fn last_rss_poll_age(&self) -> Option<i64> {
+        match self.last_rss_poll.load(Ordering::Relaxed) {
+            0 => None,
+            t => Some(chrono::Utc::now().timestamp() - t)
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self, gauges: Option<&DbGauges>) -> String {
+        let mut out = String::new();
+        counter(&mut out, "yukino_rss_polls_total", "RSS feed polls.",
+                self.rss_polls.load(Ordering::Relaxed));
+        counter(&mut out, "yukino_rss_parse_errors_total", "RSS feed polls that couldn't be parsed.",
+                self.rss_parse_errors.load(Ordering::Relaxed));
+        counter(&mut out, "yukino_notifications_sent_total", "Release notifications delivered.",
+                self.notifications_sent.load(Ordering::Relaxed));
+        counter(&mut out, "yukino_notifications_failed_total", "Release notifications that couldn't be delivered.",
+                self.notifications_failed.load(Ordering::Relaxed));
+
+        out.push_str("# HELP yukino_commands_total Commands handled per type.\n");
+        out.push_str("# TYPE yukino_commands_total counter\n");
+        for (command, count) in self.commands.lock().unwrap().iter() {
+            writeln!(out, "yukino_commands_total{{command=\"{}\"}} {}", command, count).ok();
+        }
+
+        gauge(&mut out, "yukino_gateway_connected", "1 if the Discord gateway is connected.",
+              self.is_gateway_connected() as i64);
+        if let Some(g) = gauges {
+            gauge(&mut out, "yukino_registered_users", "Registered users.", g.registered_users);
+            gauge(&mut out, "yukino_tracked_shows", "Shows stored in the database.", g.tracked_shows);
+        }
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value).ok();
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value).ok();
+}
+
+
+#[test]
+fn test_render_metrics() {
+    let metrics = Metrics::new();
+    metrics.rss_poll_succeeded();
+    metrics.rss_parse_failed();
+    metrics.notification_sent();
+    metrics.command_handled("add");
+    metrics.command_handled("add");
+    metrics.command_handled("schedule");
+    let text = metrics.render(Some(&DbGauges { registered_users: 3, tracked_shows: 12 }));
+    assert!(text.contains("# TYPE yukino_rss_polls_total counter\nyukino_rss_polls_total 2\n"));
+    assert!(text.contains("\nyukino_rss_parse_errors_total 1\n"));
+    assert!(text.contains("\nyukino_notifications_sent_total 1\n"));
+    assert!(text.contains("\nyukino_notifications_failed_total 0\n"));
+    assert!(text.contains("yukino_commands_total{command=\"add\"} 2\n"));
+    assert!(text.contains("yukino_commands_total{command=\"schedule\"} 1\n"));
+    assert!(text.contains("\nyukino_registered_users 3\n"));
+    assert!(text.contains("\nyukino_tracked_shows 12\n"));
+    assert!(metrics.last_rss_poll_age().unwrap() <= 1);
+    assert!(!metrics.render(None).contains("yukino_tracked_shows"));
+}
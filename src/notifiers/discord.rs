@@ -0,0 +1,139 @@
+use serenity::async_trait;
+use serenity::http::client::Http;
+use serenity::model::id::UserId;
+
+use crate::message_handler::embed::{EmbedPages, MAX_FIELD_VALUE};
+
+use super::{Episode, Notification, Notifier, NotifierError};
+
+pub struct DiscordDm<'a> {
+    pub http: &'a Http,
+    pub user_id: &'a str,
+}
+
+#[async_trait]
+impl Notifier for DiscordDm<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError> {
+        let user_id: u64 = self.user_id.parse()
+            .map_err(|_| NotifierError::Rejected(format!("{} is no Discord user id", self.user_id)))?;
+        let user = UserId::from(user_id).to_user(self.http).await
+            .map_err(|e| NotifierError::Request(e.to_string()))?;
+        for embed in embed(notification).build() {
+            user.dm(self.http, |m| m.set_embed(embed)).await
+                .map_err(|e| NotifierError::Request(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+fn embed(notification: &Notification) -> EmbedPages {
+    let mut embed = EmbedPages::default();
+    embed.title(&notification.title);
+    if !notification.description.is_empty() {
+        embed.description(&notification.description);
+    }
+    if let Some(image_url) = &notification.image_url {
+        embed.thumbnail(image_url);
+    }
+    if let Some((size, link)) = &notification.download {
+        embed.field(format!("Download - {}", size), format!("[🧲]({})", link), true);
+    }
+    if let Some(links) = &notification.show_links {
+        embed.field("Show Information", links, true);
+    }
+    // one field per show, with a line for each episode
+    for (show_name, episodes) in notification.episodes_by_show() {
+        embed.field(show_name, episode_lines(&episodes), false);
+    }
+    embed
+}
+
+fn episode_lines(episodes: &[&Episode]) -> String {
+    let lines: Vec<String> = episodes.iter()
+        .map(|e| match &e.note {
+            Some(note) => format!("[🧲 {}]({}) {} · {}", e.name, e.link, e.file_size, note),
+            None => format!("[🧲 {}]({}) {}", e.name, e.link, e.file_size),
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Whether the notification fits into one embed without any of its links being cut off.
+pub(super) fn fits_one_embed(notification: &Notification) -> bool {
+    notification.episodes_by_show().iter()
+        .all(|(_, episodes)| episode_lines(episodes).chars().count() <= MAX_FIELD_VALUE)
+        && embed(notification).build().len() == 1
+}
+
+
+#[test]
+fn test_release_embed() {
+    use crate::message_handler::embed::MAX_DESCRIPTION;
+    let notification = Notification {
+        title: "[SubsPlease] One Piece - 1000 (1080p) [F9A3D1B8].mkv".to_string(),
+        description: "Gol D. Roger was known as the Pirate King. ".repeat(200),
+        image_url: Some("https://subsplease.org/wp-content/uploads/2019/10/one-piece.jpg".to_string()),
+        download: Some(("1.4 GiB".to_string(), "https://yukino.example.com/r/abc".to_string())),
+        ..Notification::default()
+    };
+    let embeds = embed(&notification).build();
+    assert_eq!(embeds.len(), 1);
+    let description = embeds[0].0.get("description").unwrap().as_str().unwrap();
+    assert_eq!(description.chars().count(), MAX_DESCRIPTION);
+    assert_eq!(embeds[0].0.get("thumbnail").unwrap()["url"], notification.image_url.unwrap().as_str());
+    assert_eq!(embeds[0].0.get("fields").unwrap()[0]["value"], "[🧲](https://yukino.example.com/r/abc)");
+}
+
+#[test]
+fn test_digest_embed() {
+    use chrono::Utc;
+    use crate::notifiers::Episode;
+    use crate::subs_pls::release_parser::Release;
+    let release = |guid: &str, episode: Option<&str>| Release {
+        guid: guid.to_string(),
+        show_id: String::new(),
+        title: format!("[SubsPlease] {} (1080p).mkv", guid),
+        episode: episode.map(str::to_string),
+        link: String::new(),
+        file_size: "1.4 GiB".to_string(),
+        released_at: Utc::now(),
+    };
+    let episode = |show: &str, guid: &str, episode: Option<&str>|
+        Episode::new(show, &release(guid, episode), format!("https://yukino.example.com/r/{}", guid), None);
+    let noted = Episode { note: Some("Premiumize is downloading it.".to_string()), ..episode("Boruto", "b", None) };
+    let notification = Notification::digest(vec![episode("One Piece", "a", Some("1000")),
+                                                 noted,
+                                                 episode("One Piece", "c", Some("1001"))]);
+    let embeds = embed(&notification).build();
+    assert_eq!(embeds.len(), 1);
+    let fields = embeds[0].0.get("fields").unwrap().as_array().unwrap();
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields[0]["name"], "One Piece");
+    assert_eq!(fields[0]["value"], "[🧲 Episode 1000](https://yukino.example.com/r/a) 1.4 GiB\n\
+        [🧲 Episode 1001](https://yukino.example.com/r/c) 1.4 GiB");
+    assert_eq!(fields[1]["value"], "[🧲 [SubsPlease] b (1080p).mkv](https://yukino.example.com/r/b) 1.4 GiB · \
+        Premiumize is downloading it.");
+    assert_eq!(embeds[0].0.get("title").unwrap(), "3 new episodes");
+}
+
+#[test]
+fn test_digests_fit_one_embed() {
+    use crate::notifiers::Episode;
+    let episode = |show: usize, number: usize| Episode {
+        guid: format!("{}-{}", show, number),
+        show_name: format!("Show {}", show),
+        name: format!("Episode {}", number),
+        file_size: "1.4 GiB".to_string(),
+        link: format!("https://yukino.example.com/r/{}", "a".repeat(40)),
+        note: None,
+    };
+    // more shows than fields, then more episodes of one show than fit into its field
+    let episodes: Vec<Episode> = (0..30).map(|show| episode(show, 1))
+        .chain((1..=20).map(|number| episode(30, number)))
+        .collect();
+    let digests = Notification::digests(episodes.clone());
+    assert!(digests.len() > 2);
+    assert!(digests.iter().all(fits_one_embed));
+    let sent: Vec<Episode> = digests.into_iter().flat_map(|d| d.episodes).collect();
+    assert_eq!(sent, episodes);
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serenity::async_trait;
+
+use crate::config::{SmtpConfig, SmtpTls};
+
+use super::{Notification, Notifier, NotifierError};
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Mails through the smtp server of the bot.
+pub struct Email<'a> {
+    pub config: &'a SmtpConfig,
+    pub address: &'a str,
+}
+
+impl Email<'_> {
+    fn message(&self, notification: &Notification) -> Result<Message, NotifierError> {
+        let to: Mailbox = self.address.parse()
+            .map_err(|_| NotifierError::Rejected(format!("{} is no mail address", self.address)))?;
+        let from: Mailbox = self.config.from.parse()
+            .map_err(|_| NotifierError::Rejected(format!("{} is no mail address", self.config.from)))?;
+        Message::builder()
+            .from(from)
+            .to(to)
+            .subject(&notification.title)
+            .body(notification.text())
+            .map_err(|e| NotifierError::Rejected(e.to_string()))
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, NotifierError> {
+        let host = &self.config.host;
+        let builder = match self.config.tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)),
+        }.map_err(|e| NotifierError::Request(e.to_string()))?;
+        let mut builder = builder.port(self.config.port).timeout(Some(SMTP_TIMEOUT));
+        if let Some(username) = &self.config.username {
+            builder = builder.credentials(Credentials::new(
+                username.to_string(), self.config.password.clone().unwrap_or_default()));
+        }
+        Ok(builder.build())
+    }
+}
+
+#[async_trait]
+impl Notifier for Email<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError> {
+        let message = self.message(notification)?;
+        match self.transport()?.send(message).await {
+            Ok(_) => Ok(()),
+            // 5xx answers are permanent, like an unknown recipient
+            Err(e) if e.is_permanent() => Err(NotifierError::Rejected(e.to_string())),
+            Err(e) => Err(NotifierError::Request(e.to_string())),
+        }
+    }
+}
+
+
+#[test]
+fn test_email_message() {
+    let config = SmtpConfig {
+        host: "localhost".to_string(),
+        port: 25,
+        tls: SmtpTls::None,
+        username: None,
+        password: None,
+        from: "Yukino <yukino@example.org>".to_string(),
+    };
+    let email = Email { config: &config, address: "someone@example.org" };
+    let message = String::from_utf8(email.message(&Notification::welcome()).unwrap().formatted()).unwrap();
+    assert!(message.contains("From: Yukino <yukino@example.org>\r\n"));
+    assert!(message.contains("To: someone@example.org\r\n"));
+    assert!(message.contains("Subject: Hi from Yukino\r\n"));
+    assert!(message.ends_with("New releases on your watchlist will show up here."));
+    let invalid = Email { config: &config, address: "someone" };
+    assert!(matches!(invalid.message(&Notification::welcome()), Err(NotifierError::Rejected(_))));
+}
@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use serde_json::json;
+use serenity::async_trait;
+
+use crate::config::MatrixConfig;
+
+use super::{http_client, request_error, Notification, Notifier, NotifierError};
+
+/// Messages from the bot account into a room. It joins the room first, so inviting the bot
+/// is enough.
+pub struct Matrix<'a> {
+    pub config: &'a MatrixConfig,
+    pub room_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl Matrix<'_> {
+    /// Joining a room the bot is in already does nothing.
+    async fn join(&self, client: &reqwest::Client) -> Result<String, NotifierError> {
+        let res = client.post(format!("{}/_matrix/client/v3/join/{}", self.config.homeserver_url, encode(self.room_id)))
+            .bearer_auth(&self.config.access_token)
+            .json(&json!({}))
+            .send().await
+            .map_err(request_error)?;
+        #[derive(Deserialize)]
+        struct Joined {
+            room_id: String,
+        }
+        match res.status().as_u16() {
+            200 => res.json::<Joined>().await.map(|joined| joined.room_id).map_err(request_error),
+            code => Err(error(res, code).await),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Matrix<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError> {
+        let client = http_client();
+        let room_id = self.join(&client).await?;
+        let transaction_id: u64 = rand::random();
+        let res = client.put(format!("{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+                                     self.config.homeserver_url, encode(&room_id), transaction_id))
+            .bearer_auth(&self.config.access_token)
+            .json(&json!({ "msgtype": "m.text", "body": format!("{}\n{}", notification.title, notification.text()) }))
+            .send().await
+            .map_err(request_error)?;
+        match res.status().as_u16() {
+            200 => Ok(()),
+            code => Err(error(res, code).await),
+        }
+    }
+}
+
+/// Room ids and aliases start with `!` or `#`, which have to be escaped in paths.
+fn encode(room: &str) -> String {
+    room.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+async fn error(res: reqwest::Response, code: u16) -> NotifierError {
+    match res.json::<ErrorResponse>().await {
+        Ok(e) => NotifierError::Rejected(format!("Matrix says: {}", e.error)),
+        Err(_) => NotifierError::Request(format!("Matrix answered with {}", code)),
+    }
+}
+
+
+#[tokio::test]
+async fn test_matrix() {
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| {
+        if req.headers.get("Authorization").map(|a| a.as_bytes()) != Some(b"Bearer syt_token") {
+            return reply(401, r#"{"errcode": "M_UNKNOWN_TOKEN", "error": "Invalid access token"}"#);
+        }
+        match req.path.as_str() {
+            "/_matrix/client/v3/join/%23anime%3Aexample.org" => reply(200, r#"{"room_id": "!abc:example.org"}"#),
+            "/_matrix/client/v3/join/%21private%3Aexample.org" =>
+                reply(403, r#"{"errcode": "M_FORBIDDEN", "error": "You are not invited to this room."}"#),
+            path if path.starts_with("/_matrix/client/v3/rooms/%21abc%3Aexample.org/send/m.room.message/") =>
+                reply(200, r#"{"event_id": "$1"}"#),
+            _ => reply(404, r#"{"errcode": "M_UNRECOGNIZED", "error": "Unrecognized request"}"#),
+        }
+    }).await;
+    let config = MatrixConfig { homeserver_url: server.url.to_string(), access_token: "syt_token".to_string() };
+
+    let matrix = Matrix { config: &config, room_id: "#anime:example.org" };
+    assert_eq!(matrix.notify(&Notification::welcome()).await, Ok(()));
+    let requests = server.requests();
+    assert_eq!(requests[1].method, "PUT");
+    let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(body["msgtype"], "m.text");
+    let private = Matrix { config: &config, room_id: "!private:example.org" };
+    assert_eq!(private.notify(&Notification::welcome()).await,
+               Err(NotifierError::Rejected("Matrix says: You are not invited to this room.".to_string())));
+}
@@ -0,0 +1,347 @@
+//! Places besides Discord DMs that users can have new releases sent to.
+
+use std::fmt;
+use std::time::Duration;
+
+use serenity::async_trait;
+use serenity::http::client::Http;
+
+use crate::config::Config;
+use crate::outbound::OutboundError;
+use crate::secrets;
+use crate::subs_pls::page_parser::Show;
+use crate::subs_pls::release_parser::{FeedItem, Release};
+use crate::watchlist_file::show_url;
+
+pub mod discord;
+pub mod email;
+pub mod matrix;
+pub mod push;
+pub mod telegram;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq)]
+pub enum NotifierError {
+    /// The bot has no account for this kind of channel.
+    NotConfigured,
+    /// The service answered, but didn't deliver, with its reason.
+    Rejected(String),
+    Request(String),
+    /// The server of the user is somewhere the bot doesn't send requests to.
+    NotAllowed(OutboundError),
+}
+
+impl fmt::Display for NotifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotifierError::NotConfigured => write!(f, "this kind of channel isn't set up for the bot"),
+            NotifierError::Rejected(reason) => write!(f, "{}", reason),
+            NotifierError::Request(e) => write!(f, "couldn't reach it ({})", e),
+            NotifierError::NotAllowed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelKind {
+    Discord,
+    Telegram,
+    Matrix,
+    Ntfy,
+    Gotify,
+    Email,
+}
+
+impl ChannelKind {
+    pub const ALL: [ChannelKind; 6] = [ChannelKind::Discord, ChannelKind::Telegram, ChannelKind::Matrix,
+        ChannelKind::Ntfy, ChannelKind::Gotify, ChannelKind::Email];
+
+    pub fn parse(name: &str) -> Option<ChannelKind> {
+        ChannelKind::ALL.iter().copied().find(|kind| kind.id() == name.to_lowercase())
+    }
+
+    /// Lowercase name, as stored in the db.
+    pub fn id(&self) -> &'static str {
+        match self {
+            ChannelKind::Discord => "discord",
+            ChannelKind::Telegram => "telegram",
+            ChannelKind::Matrix => "matrix",
+            ChannelKind::Ntfy => "ntfy",
+            ChannelKind::Gotify => "gotify",
+            ChannelKind::Email => "email",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelKind::Discord => "Discord",
+            ChannelKind::Telegram => "Telegram",
+            ChannelKind::Matrix => "Matrix",
+            ChannelKind::Ntfy => "ntfy",
+            ChannelKind::Gotify => "Gotify",
+            ChannelKind::Email => "Email",
+        }
+    }
+}
+
+/// A place a user gets releases at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub kind: ChannelKind,
+    /// The Discord user id, Telegram chat id, Matrix room, ntfy topic url, gotify server url
+    /// or mail address.
+    pub target: String,
+    /// Access token of ntfy and gotify.
+    pub token: Option<String>,
+    pub enabled: bool,
+    /// False until the user sent back the code the channel got, which proves it's theirs.
+    pub confirmed: bool,
+}
+
+impl Channel {
+    /// Discord DMs are on for everyone until they turn them off.
+    pub fn discord(user_id: i64) -> Channel {
+        Channel { kind: ChannelKind::Discord, target: user_id.to_string(), token: None, enabled: true, confirmed: true }
+    }
+}
+
+/// A stored channel with its token decrypted. Without the key of the bot it has none.
+pub fn decrypted(config: &Config, channel: &Channel, token: &Option<Vec<u8>>) -> Channel {
+    Channel {
+        token: match (token, &config.encryption_key) {
+            (Some(encrypted), Some(key)) => secrets::decrypt(key, encrypted),
+            _ => None,
+        },
+        ..channel.clone()
+    }
+}
+
+/// The channels of a user as stored, with Discord added in front when it never was changed.
+pub fn with_discord(user_id: i64, mut channels: Vec<Channel>) -> Vec<Channel> {
+    if !channels.iter().any(|c| c.kind == ChannelKind::Discord) {
+        channels.insert(0, Channel::discord(user_id));
+    }
+    channels
+}
+
+/// What gets sent. Discord shows it as an embed, the others as text.
+#[derive(Default)]
+pub struct Notification {
+    pub title: String,
+    pub description: String,
+    pub image_url: Option<String>,
+    /// File size and download link.
+    pub download: Option<(String, String)>,
+    /// Markdown links to subsplease and the list sites.
+    pub show_links: Option<String>,
+    pub show_url: Option<String>,
+    /// The releases of a digest, in the order they came out.
+    pub episodes: Vec<Episode>,
+}
+
+/// A release in a digest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Episode {
+    pub guid: String,
+    pub show_name: String,
+    /// `Episode 1000`, or the file name for releases without an episode number.
+    pub name: String,
+    pub file_size: String,
+    pub link: String,
+    /// What happened to it while it was held back, like a Premiumize transfer.
+    pub note: Option<String>,
+}
+
+impl Episode {
+    /// `link` is where it's downloaded from, usually a redirect to the release link.
+    pub fn new(show_name: &str, release: &Release, link: String, note: Option<String>) -> Episode {
+        Episode {
+            guid: release.guid.to_string(),
+            show_name: show_name.to_string(),
+            name: release.episode.as_ref().map_or(release.title.clone(), |e| format!("Episode {}", e)),
+            file_size: release.file_size.to_string(),
+            link,
+            note,
+        }
+    }
+}
+
+impl Notification {
+    pub fn release(show: &Show, item: &FeedItem, download_link: String) -> Notification {
+        Notification {
+            title: item.title.to_string(),
+            description: show.synopsis.to_string(),
+            image_url: Some(show.image_url.to_string()),
+            download: Some((item.file_size.to_string(), download_link)),
+            show_links: Some(show.links()),
+            show_url: Some(show_url(&show.id)),
+            episodes: Vec::new(),
+        }
+    }
+
+    /// Releases that were held back, sent together.
+    pub fn digest(episodes: Vec<Episode>) -> Notification {
+        Notification {
+            title: format!("{} new episode{}", episodes.len(), if episodes.len() == 1 { "" } else { "s" }),
+            episodes,
+            ..Notification::default()
+        }
+    }
+
+    /// Digests of the releases that fit into one Discord message each, so a message that
+    /// fails doesn't bring back the ones sent before it.
+    pub fn digests(episodes: Vec<Episode>) -> Vec<Notification> {
+        let mut parts = Vec::new();
+        let mut current: Vec<Episode> = Vec::new();
+        for episode in episodes {
+            current.push(episode);
+            if current.len() > 1 && !discord::fits_one_embed(&Notification::digest(current.clone())) {
+                let last = current.pop().expect("just pushed");
+                parts.push(std::mem::replace(&mut current, vec![last]));
+            }
+        }
+        if !current.is_empty() {
+            parts.push(current);
+        }
+        parts.into_iter().map(Notification::digest).collect()
+    }
+
+    /// The episodes grouped by show, in the order the shows first came up.
+    pub fn episodes_by_show(&self) -> Vec<(&str, Vec<&Episode>)> {
+        let mut shows: Vec<(&str, Vec<&Episode>)> = Vec::new();
+        for episode in &self.episodes {
+            match shows.iter_mut().find(|(name, _)| *name == episode.show_name) {
+                Some((_, episodes)) => episodes.push(episode),
+                None => shows.push((&episode.show_name, vec![episode])),
+            }
+        }
+        shows
+    }
+
+    /// Sent when a channel is linked, to make sure it works.
+    /// Asks whoever reads the channel to send `code` back, so nobody gets releases they
+    /// didn't ask for.
+    pub fn confirmation(kind: ChannelKind, code: &str) -> Notification {
+        Notification {
+            title: "Hi from Yukino".to_string(),
+            description: format!("Someone wants new anime releases to show up here. If that's you, send \
+                                  \"channel {} confirm {}\" to Yukino on Discord.", kind.id(), code),
+            ..Notification::default()
+        }
+    }
+
+    pub fn welcome() -> Notification {
+        Notification {
+            title: "Hi from Yukino".to_string(),
+            description: "New releases on your watchlist will show up here.".to_string(),
+            ..Notification::default()
+        }
+    }
+
+    /// Everything but the title as plain text.
+    pub fn text(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some((size, link)) = &self.download {
+            lines.push(format!("Download ({}): {}", size, link));
+        }
+        if let Some(url) = &self.show_url {
+            lines.push(url.to_string());
+        }
+        if !self.description.is_empty() {
+            lines.push(self.description.to_string());
+        }
+        for (show_name, episodes) in self.episodes_by_show() {
+            lines.push(show_name.to_string());
+            for episode in episodes {
+                lines.push(format!("{} ({}): {}", episode.name, episode.file_size, episode.link));
+                lines.extend(episode.note.clone());
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError>;
+}
+
+/// The notifier for a channel, or `NotConfigured` when the bot has no account for its kind.
+pub fn notifier<'a>(config: &'a Config, http: &'a Http, channel: &'a Channel)
+                    -> Result<Box<dyn Notifier + 'a>, NotifierError> {
+    let channels = &config.channels;
+    Ok(match channel.kind {
+        ChannelKind::Discord => Box::new(discord::DiscordDm { http, user_id: &channel.target }),
+        ChannelKind::Telegram => Box::new(telegram::Telegram {
+            config: channels.telegram.as_ref().ok_or(NotifierError::NotConfigured)?,
+            chat_id: &channel.target,
+        }),
+        ChannelKind::Matrix => Box::new(matrix::Matrix {
+            config: channels.matrix.as_ref().ok_or(NotifierError::NotConfigured)?,
+            room_id: &channel.target,
+        }),
+        ChannelKind::Ntfy => Box::new(push::Ntfy { topic_url: &channel.target, token: channel.token.as_deref() }),
+        ChannelKind::Gotify => Box::new(push::Gotify {
+            server_url: &channel.target,
+            token: channel.token.as_deref().unwrap_or_default(),
+        }),
+        ChannelKind::Email => Box::new(email::Email {
+            config: channels.smtp.as_ref().ok_or(NotifierError::NotConfigured)?,
+            address: &channel.target,
+        }),
+    })
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+fn request_error(e: reqwest::Error) -> NotifierError {
+    NotifierError::Request(e.to_string())
+}
+
+
+#[test]
+fn test_channel_kind() {
+    for kind in ChannelKind::ALL {
+        assert_eq!(ChannelKind::parse(kind.id()), Some(kind));
+        assert_eq!(ChannelKind::parse(kind.name()), Some(kind));
+    }
+    assert_eq!(ChannelKind::parse("signal"), None);
+    let channels = with_discord(7, vec![Channel { kind: ChannelKind::Ntfy, target: "https://ntfy.sh/t".to_string(),
+        token: None, enabled: true, confirmed: true }]);
+    assert_eq!(channels[0], Channel::discord(7));
+    assert_eq!(with_discord(7, channels.clone()), channels);
+}
+
+#[test]
+fn test_notification_text() {
+    let notification = Notification {
+        title: "Kingdom S3 - 14".to_string(),
+        description: "War.".to_string(),
+        download: Some(("1.4 GiB".to_string(), "https://yukino.example.com/r/abc".to_string())),
+        show_url: Some("https://subsplease.org/shows/kingdom-s3/".to_string()),
+        ..Notification::default()
+    };
+    assert_eq!(notification.text(), "Download (1.4 GiB): https://yukino.example.com/r/abc\n\
+        https://subsplease.org/shows/kingdom-s3/\nWar.");
+    assert_eq!(Notification::welcome().text(), "New releases on your watchlist will show up here.");
+    assert_eq!(Notification::confirmation(ChannelKind::Email, "042917").text(), "Someone wants new anime releases \
+        to show up here. If that's you, send \"channel email confirm 042917\" to Yukino on Discord.");
+
+    let episode = |show: &str, name: &str| Episode { guid: String::new(), show_name: show.to_string(), note: None,
+        name: name.to_string(), file_size: "1.4 GiB".to_string(), link: format!("https://yukino.example.com/r/{}", name.len()) };
+    let digest = Notification::digest(vec![episode("One Piece", "Episode 1000"), episode("Boruto", "b.mkv"),
+                                           episode("One Piece", "Episode 1001")]);
+    assert_eq!(digest.title, "3 new episodes");
+    assert_eq!(digest.text(), "One Piece\nEpisode 1000 (1.4 GiB): https://yukino.example.com/r/12\n\
+        Episode 1001 (1.4 GiB): https://yukino.example.com/r/12\nBoruto\nb.mkv (1.4 GiB): https://yukino.example.com/r/5");
+
+    let noted = Episode { note: Some("Premiumize is downloading it.".to_string()), ..episode("Boruto", "b.mkv") };
+    assert_eq!(Notification::digest(vec![noted]).text(), "Boruto\nb.mkv (1.4 GiB): https://yukino.example.com/r/5\n\
+        Premiumize is downloading it.");
+}
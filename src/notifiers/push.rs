@@ -0,0 +1,93 @@
+use serde_json::json;
+use serenity::async_trait;
+
+use crate::outbound;
+
+use super::{request_error, Notification, Notifier, NotifierError, REQUEST_TIMEOUT};
+
+/// A topic on an ntfy server, like `https://ntfy.sh/my-releases`, optionally with an
+/// access token for protected topics.
+pub struct Ntfy<'a> {
+    pub topic_url: &'a str,
+    pub token: Option<&'a str>,
+}
+
+/// A gotify server with the token of an application on it.
+pub struct Gotify<'a> {
+    pub server_url: &'a str,
+    pub token: &'a str,
+}
+
+#[async_trait]
+impl Notifier for Ntfy<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError> {
+        // titles with non ascii names don't fit into headers, so this uses the json api,
+        // which is posted to the server instead of the topic
+        let (server, topic) = self.topic_url.rsplit_once('/')
+            .ok_or_else(|| NotifierError::Rejected(format!("{} is no topic url", self.topic_url)))?;
+        let mut message = json!({ "topic": topic, "title": notification.title, "message": notification.text() });
+        if let Some((_, link)) = &notification.download {
+            message["click"] = json!(link);
+        }
+        let mut req = client(server).await?.post(server).json(&message);
+        if let Some(token) = self.token {
+            req = req.bearer_auth(token);
+        }
+        let res = req.send().await.map_err(request_error)?;
+        match res.status().as_u16() {
+            200 => Ok(()),
+            401 | 403 => Err(NotifierError::Rejected("ntfy didn't accept the access token".to_string())),
+            code => Err(NotifierError::Request(format!("ntfy answered with {}", code))),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Gotify<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError> {
+        let res = client(self.server_url).await?.post(format!("{}/message", self.server_url))
+            .header("X-Gotify-Key", self.token)
+            .json(&json!({ "title": notification.title, "message": notification.text(), "priority": 5 }))
+            .send().await
+            .map_err(request_error)?;
+        match res.status().as_u16() {
+            200 => Ok(()),
+            401 | 403 => Err(NotifierError::Rejected("Gotify didn't accept the app token".to_string())),
+            code => Err(NotifierError::Request(format!("Gotify answered with {}", code))),
+        }
+    }
+}
+
+/// The servers are chosen by users, so they have to be public, see `outbound`.
+async fn client(server_url: &str) -> Result<reqwest::Client, NotifierError> {
+    outbound::check_url(server_url).await.map_err(NotifierError::NotAllowed)?;
+    Ok(outbound::client(REQUEST_TIMEOUT))
+}
+
+
+#[tokio::test]
+async fn test_push() {
+    use serde_json::Value;
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| match req.path.as_str() {
+        "/ntfy" if req.headers.get("Authorization").is_none_or(|a| a == "Bearer tk_1") => reply(200, "{}"),
+        "/ntfy" => reply(403, r#"{"code": 40301, "error": "forbidden"}"#),
+        "/gotify/message" if req.headers["X-Gotify-Key"] == "app" => reply(200, "{}"),
+        _ => reply(401, r#"{"error": "Unauthorized"}"#),
+    }).await;
+
+    let topic_url = format!("{}/ntfy/releases", server.url);
+    let ntfy = Ntfy { topic_url: &topic_url, token: Some("tk_1") };
+    assert_eq!(ntfy.notify(&Notification::welcome()).await, Ok(()));
+    let body: Value = serde_json::from_slice(&server.requests()[0].body).unwrap();
+    assert_eq!((body["topic"].as_str(), body["title"].as_str()), (Some("releases"), Some("Hi from Yukino")));
+    assert_eq!(Ntfy { topic_url: &topic_url, token: Some("wrong") }.notify(&Notification::welcome()).await,
+               Err(NotifierError::Rejected("ntfy didn't accept the access token".to_string())));
+
+    let server_url = format!("{}/gotify", server.url);
+    assert_eq!(Gotify { server_url: &server_url, token: "app" }.notify(&Notification::welcome()).await, Ok(()));
+    let body: Value = serde_json::from_slice(&server.requests()[2].body).unwrap();
+    assert_eq!(body["priority"], 5);
+    assert_eq!(Gotify { server_url: &server_url, token: "wrong" }.notify(&Notification::welcome()).await,
+               Err(NotifierError::Rejected("Gotify didn't accept the app token".to_string())));
+}
@@ -0,0 +1,68 @@
+use serde::Deserialize;
+use serde_json::json;
+use serenity::async_trait;
+
+use crate::config::TelegramConfig;
+
+use super::{http_client, request_error, Notification, Notifier, NotifierError};
+
+/// Messages from the bot account, to a chat id or `@channel`. People have to start a chat
+/// with the bot before it can write them.
+pub struct Telegram<'a> {
+    pub config: &'a TelegramConfig,
+    pub chat_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+#[async_trait]
+impl Notifier for Telegram<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifierError> {
+        let res = http_client()
+            .post(format!("{}/bot{}/sendMessage", self.config.api_url, self.config.bot_token))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}\n{}", notification.title, notification.text()),
+                "disable_web_page_preview": true,
+            }))
+            .send().await
+            .map_err(request_error)?;
+        let code = res.status().as_u16();
+        // errors come with a description as well, like "Bad Request: chat not found"
+        let res: ApiResponse = res.json().await
+            .map_err(|_| NotifierError::Request(format!("Telegram answered with {}", code)))?;
+        match res {
+            ApiResponse { ok: true, .. } => Ok(()),
+            ApiResponse { description, .. } =>
+                Err(NotifierError::Rejected(description.unwrap_or_else(|| format!("Telegram answered with {}", code)))),
+        }
+    }
+}
+
+
+#[tokio::test]
+async fn test_telegram() {
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| {
+        let body: serde_json::Value = serde_json::from_slice(&req.body).unwrap_or_default();
+        match (req.path.as_str(), body["chat_id"].as_str()) {
+            ("/bot123:abc/sendMessage", Some("42")) => reply(200, r#"{"ok": true, "result": {}}"#),
+            ("/bot123:abc/sendMessage", _) =>
+                reply(400, r#"{"ok": false, "error_code": 400, "description": "Bad Request: chat not found"}"#),
+            _ => reply(404, r#"{"ok": false, "error_code": 404, "description": "Not Found"}"#),
+        }
+    }).await;
+    let config = TelegramConfig { api_url: server.url.to_string(), bot_token: "123:abc".to_string() };
+
+    let telegram = Telegram { config: &config, chat_id: "42" };
+    assert_eq!(telegram.notify(&Notification::welcome()).await, Ok(()));
+    let body: serde_json::Value = serde_json::from_slice(&server.requests()[0].body).unwrap();
+    assert_eq!(body["text"], "Hi from Yukino\nNew releases on your watchlist will show up here.");
+    let unknown = Telegram { config: &config, chat_id: "43" };
+    assert_eq!(unknown.notify(&Notification::welcome()).await,
+               Err(NotifierError::Rejected("Bad Request: chat not found".to_string())));
+}
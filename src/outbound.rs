@@ -0,0 +1,203 @@
+//! Requests to urls users hand the bot, like webhooks and download clients. Those must not
+//! reach the bot's own network, such as its http server or the metadata service of the
+//! cloud it runs in, unless the owner allows it.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::Url;
+
+const MAX_REDIRECTS: usize = 5;
+
+// the tests talk to stub servers on localhost
+static ALLOW_PRIVATE: AtomicBool = AtomicBool::new(cfg!(test));
+
+#[derive(Debug, PartialEq)]
+pub enum OutboundError {
+    InvalidUrl,
+    Unresolvable(String),
+    NotPublic(IpAddr),
+}
+
+impl fmt::Display for OutboundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutboundError::InvalidUrl => write!(f, "it isn't an http or https url"),
+            OutboundError::Unresolvable(host) => write!(f, "{} couldn't be looked up", host),
+            OutboundError::NotPublic(ip) => write!(f, "{} isn't a public address", ip),
+        }
+    }
+}
+
+impl std::error::Error for OutboundError {}
+
+/// Lets users point the bot at private addresses, for instances that only serve their
+/// own network.
+pub fn allow_private_urls(allow: bool) {
+    ALLOW_PRIVATE.store(allow, Ordering::Relaxed);
+}
+
+/// Makes sure the host of `url` only resolves to public addresses. `client` checks again
+/// when connecting, this catches ip addresses in the url and gives a readable error early.
+pub async fn check_url(url: &str) -> Result<(), OutboundError> {
+    check_url_with(url, ALLOW_PRIVATE.load(Ordering::Relaxed)).await
+}
+
+async fn check_url_with(url: &str, allow_private: bool) -> Result<(), OutboundError> {
+    let url = Url::parse(url).map_err(|_| OutboundError::InvalidUrl)?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(OutboundError::InvalidUrl);
+    }
+    if allow_private {
+        return Ok(());
+    }
+    let host = url.host_str().ok_or(OutboundError::InvalidUrl)?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = match ip_host(&url) {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => tokio::net::lookup_host((host, port)).await
+            .map_err(|_| OutboundError::Unresolvable(host.to_string()))?
+            .collect(),
+    };
+    match addrs.iter().find(|addr| !is_public(addr.ip())) {
+        Some(addr) => Err(OutboundError::NotPublic(addr.ip())),
+        None => Ok(()),
+    }
+}
+
+/// A client that only connects to public addresses, also after redirects.
+pub fn client(timeout: Duration) -> reqwest::Client {
+    client_with(timeout, ALLOW_PRIVATE.load(Ordering::Relaxed))
+}
+
+fn client_with(timeout: Duration, allow_private: bool) -> reqwest::Client {
+    let builder = reqwest::Client::builder().timeout(timeout);
+    if allow_private {
+        return builder.build().unwrap_or_default();
+    }
+    builder
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(Policy::custom(follow_public))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Host names are checked by the resolver, ip addresses have to be checked here.
+fn follow_public(attempt: Attempt) -> reqwest::redirect::Action {
+    if attempt.previous().len() >= MAX_REDIRECTS {
+        return attempt.error("too many redirects");
+    }
+    match ip_host(attempt.url()) {
+        Some(ip) if !is_public(ip) => attempt.error(OutboundError::NotPublic(ip)),
+        _ => attempt.follow(),
+    }
+}
+
+/// The host of the url, if it's an ip address rather than a name.
+fn ip_host(url: &Url) -> Option<IpAddr> {
+    url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Resolves like the system does, but leaves out every address that isn't public, so a
+/// host name can't be switched to a private address between `check_url` and the request.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let addrs: Vec<SocketAddr> = resolved.iter().copied().filter(|addr| is_public(addr.ip())).collect();
+            if addrs.is_empty() {
+                let e = match resolved.first() {
+                    Some(addr) => OutboundError::NotPublic(addr.ip()),
+                    None => OutboundError::Unresolvable(host),
+                };
+                return Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether the address is reachable from the internet, so not loopback, private, link
+/// local or reserved for anything else.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_private() || ip.is_link_local()
+        || ip.is_broadcast() || ip.is_documentation() || ip.is_multicast()
+        || a == 0
+        // shared address space of carrier grade NATs
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    // NAT64 addresses lead to the embedded ipv4 address
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast()
+        // unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link local and the old site local
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        // documentation
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+        // ipv4 compatible, long deprecated
+        || segments[..6] == [0, 0, 0, 0, 0, 0])
+}
+
+
+#[test]
+fn test_is_public() {
+    for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111", "64:ff9b::5db8:d822"] {
+        assert!(is_public(ip.parse().unwrap()), "{}", ip);
+    }
+    for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.10", "169.254.169.254", "100.64.0.1",
+        "0.0.0.0", "255.255.255.255", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "::ffff:10.0.0.1",
+        "64:ff9b::a9fe:a9fe"] {
+        assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+    }
+}
+
+#[tokio::test]
+async fn test_check_url() {
+    use crate::test_server::{reply, StubServer};
+    assert_eq!(check_url_with("http://93.184.216.34/hook", false).await, Ok(()));
+    assert_eq!(check_url_with("http://169.254.169.254/latest/meta-data/", false).await,
+               Err(OutboundError::NotPublic("169.254.169.254".parse().unwrap())));
+    assert_eq!(check_url_with("http://[::1]:8080/metrics", false).await,
+               Err(OutboundError::NotPublic("::1".parse().unwrap())));
+    assert!(matches!(check_url_with("http://localhost:8080/", false).await, Err(OutboundError::NotPublic(_))));
+    assert_eq!(check_url_with("file:///etc/passwd", false).await, Err(OutboundError::InvalidUrl));
+    assert_eq!(check_url_with("http://192.168.1.10:8080", true).await, Ok(()));
+
+    let server = StubServer::start(|_| reply(200, "metrics")).await;
+    let local = server.url.replace("127.0.0.1", "localhost");
+    assert!(client_with(Duration::from_secs(5), false).get(&local).send().await.is_err());
+    assert!(client_with(Duration::from_secs(5), true).get(&local).send().await.is_ok());
+}
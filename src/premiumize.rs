@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::config::PremiumizeConfig;
+
+/// Transfers are started while notifying, which shouldn't wait long on Premiumize.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+lazy_static::lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PremiumizeError {
+    /// Premiumize refused, with its reason, e.g. for a wrong api key.
+    Rejected(String),
+    Request(String),
+    InvalidResponse(String),
+}
+
+/// A transfer Premiumize started, downloading into the cloud of the user.
+#[derive(Debug, PartialEq)]
+pub struct Transfer {
+    pub id: String,
+    pub name: String,
+}
+
+/// Premiumize answers 200 to almost everything and puts the outcome in `status`.
+#[derive(Deserialize)]
+struct ApiResponse {
+    status: String,
+    message: Option<String>,
+    id: Option<String>,
+    name: Option<String>,
+}
+
+/// Checks that the api key belongs to an account.
+pub async fn check_api_key(config: &PremiumizeConfig, api_key: &str) -> Result<(), PremiumizeError> {
+    let res = CLIENT
+        .get(format!("{}/account/info", config.api_url))
+        .query(&[("apikey", api_key)])
+        .send().await
+        .map_err(request_error)?;
+    parse_response(res).await.map(|_| ())
+}
+
+/// Starts a transfer of a magnet or torrent link.
+pub async fn create_transfer(config: &PremiumizeConfig, api_key: &str, src: &str) -> Result<Transfer, PremiumizeError> {
+    let res = CLIENT
+        .post(format!("{}/transfer/create", config.api_url))
+        .query(&[("apikey", api_key)])
+        .form(&[("src", src)])
+        .send().await
+        .map_err(request_error)?;
+    let res = parse_response(res).await?;
+    Ok(Transfer {
+        id: res.id.ok_or_else(|| PremiumizeError::InvalidResponse("transfer without id".to_string()))?,
+        name: res.name.unwrap_or_default(),
+    })
+}
+
+/// The api key is part of the url, which mustn't end up in the logs.
+fn request_error(e: reqwest::Error) -> PremiumizeError {
+    PremiumizeError::Request(e.without_url().to_string())
+}
+
+async fn parse_response(res: reqwest::Response) -> Result<ApiResponse, PremiumizeError> {
+    let code = res.status().as_u16();
+    let body: ApiResponse = match res.json().await {
+        Ok(body) => body,
+        Err(_) if code != 200 => return Err(PremiumizeError::Request(format!("Premiumize answered with {}", code))),
+        Err(e) => return Err(PremiumizeError::InvalidResponse(e.to_string())),
+    };
+    match body.status.as_str() {
+        "success" => Ok(body),
+        _ => Err(PremiumizeError::Rejected(body.message.unwrap_or_else(|| "unknown error".to_string()))),
+    }
+}
+
+
+#[tokio::test]
+async fn test_create_transfer() {
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| {
+        if req.path.contains("apikey=wrong") {
+            return reply(200, r#"{"status": "error", "message": "Not logged in."}"#);
+        }
+        match req.path.split('?').next() {
+            Some("/api/transfer/create") =>
+                reply(200, r#"{"status": "success", "type": "torrent", "id": "A1b2", "name": "One Piece - 1000"}"#),
+            Some("/api/account/info") => reply(200, r#"{"status": "success", "customer_id": "42"}"#),
+            _ => reply(502, "bad gateway"),
+        }
+    }).await;
+    let config = PremiumizeConfig { api_url: format!("{}/api", server.url) };
+
+    let transfer = create_transfer(&config, "key", "magnet:?xt=urn:btih:abc&dn=One Piece").await.unwrap();
+    assert_eq!(transfer, Transfer { id: "A1b2".to_string(), name: "One Piece - 1000".to_string() });
+    let requests = server.requests();
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].path, "/api/transfer/create?apikey=key");
+    assert_eq!(String::from_utf8_lossy(&requests[0].body), "src=magnet%3A%3Fxt%3Durn%3Abtih%3Aabc%26dn%3DOne+Piece");
+
+    assert_eq!(create_transfer(&config, "wrong", "magnet:?").await.err(),
+               Some(PremiumizeError::Rejected("Not logged in.".to_string())));
+    assert_eq!(check_api_key(&config, "key").await, Ok(()));
+    assert!(check_api_key(&config, "wrong").await.is_err());
+    let broken = PremiumizeConfig { api_url: format!("{}/nothing", server.url) };
+    assert_eq!(check_api_key(&broken, "key").await,
+               Err(PremiumizeError::Request("Premiumize answered with 502".to_string())));
+    let unreachable = PremiumizeConfig { api_url: "http://127.0.0.1:1".to_string() };
+    match check_api_key(&unreachable, "secret-key").await {
+        Err(PremiumizeError::Request(e)) => assert!(!e.contains("secret-key"), "{}", e),
+        res => panic!("{:?}", res),
+    }
+}
@@ -0,0 +1,126 @@
+use crate::subs_pls::page_parser::{AirTime, Show};
+
+pub struct ScheduleEntry {
+    pub hour: i32,
+    pub minute: i32,
+    pub name: String,
+}
+
+impl ScheduleEntry {
+    pub fn clock_stamp(&self) -> String {
+        format!("{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+pub struct ScheduleDay {
+    /// 0 is Monday.
+    pub week_day: usize,
+    /// Sorted by time, then name.
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl ScheduleDay {
+    pub fn name(&self) -> &'static str {
+        AirTime::weekdays()[self.week_day]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScheduleView {
+    /// Monday to Sunday.
+    Week,
+    Today,
+    Tomorrow,
+    /// The next seven days, starting today.
+    Upcoming,
+}
+
+/// Release times of a watchlist by week day, in the timezone of the subsplease schedule.
+pub struct Schedule {
+    /// Always seven days, Monday first.
+    pub days: Vec<ScheduleDay>,
+    /// Sorted by name.
+    pub non_airing: Vec<String>,
+}
+
+impl Schedule {
+    pub fn from_shows(shows: &[Show]) -> Schedule {
+        let mut days: Vec<ScheduleDay> = (0..7).map(|week_day| ScheduleDay { week_day, entries: Vec::new() }).collect();
+        let mut non_airing = Vec::new();
+        for show in shows {
+            let air_time = &show.air_time;
+            match days.get_mut(air_time.est_week_day as usize) {
+                Some(day) if air_time.is_airing && air_time.est_week_day >= 0 => day.entries.push(ScheduleEntry {
+                    hour: air_time.est_h,
+                    minute: air_time.est_m,
+                    name: show.name.to_string(),
+                }),
+                _ => non_airing.push(show.name.to_string()),
+            }
+        }
+        for day in days.iter_mut() {
+            day.entries.sort_by(|a, b| (a.hour, a.minute, &a.name).cmp(&(b.hour, b.minute, &b.name)));
+        }
+        non_airing.sort_by_key(|name| name.to_lowercase());
+        Schedule { days, non_airing }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.days.iter().all(|d| d.entries.is_empty())
+    }
+
+    /// The days of a view in the order they come. `today` is the current week day, 0 being Monday.
+    pub fn view(&self, view: ScheduleView, today: usize) -> Vec<&ScheduleDay> {
+        match view {
+            ScheduleView::Week => self.days.iter().collect(),
+            ScheduleView::Today => vec![&self.days[today % 7]],
+            ScheduleView::Tomorrow => vec![&self.days[(today + 1) % 7]],
+            ScheduleView::Upcoming => (0..7).map(|i| &self.days[(today + i) % 7]).collect(),
+        }
+    }
+
+    /// Every release time of the week once, earliest first.
+    pub fn time_slots(&self) -> Vec<(i32, i32)> {
+        let mut slots: Vec<(i32, i32)> = self.days.iter()
+            .flat_map(|d| d.entries.iter().map(|e| (e.hour, e.minute)))
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        slots
+    }
+}
+
+
+#[cfg(test)]
+fn test_show(name: &str, week_day: i32, hour: i32, minute: i32) -> Show {
+    crate::subs_pls::page_parser::test_show(&name.to_lowercase(), name,
+        AirTime { is_airing: week_day >= 0, est_week_day: week_day, est_h: hour, est_m: minute })
+}
+
+#[test]
+fn test_schedule_order() {
+    let shows = vec![test_show("Kingdom S3", 6, 17, 30), test_show("One Piece", 6, 2, 30),
+                     test_show("Boruto", 6, 17, 30), test_show("Aria", -1, -1, -1), test_show("86", 5, 17, 30)];
+    let schedule = Schedule::from_shows(&shows);
+    let sunday: Vec<String> = schedule.days[6].entries.iter()
+        .map(|e| format!("{} {}", e.clock_stamp(), e.name))
+        .collect();
+    assert_eq!(sunday, vec!["02:30 One Piece", "17:30 Boruto", "17:30 Kingdom S3"]);
+    assert_eq!(schedule.days[5].name(), "Saturday");
+    assert_eq!(schedule.non_airing, vec!["Aria"]);
+    assert_eq!(schedule.time_slots(), vec![(2, 30), (17, 30)]);
+    assert!(!schedule.is_empty());
+    assert!(Schedule::from_shows(&[test_show("Aria", -1, -1, -1)]).is_empty());
+}
+
+#[test]
+fn test_schedule_views() {
+    let schedule = Schedule::from_shows(&[]);
+    let days = |view: ScheduleView, today: usize| schedule.view(view, today).iter()
+        .map(|d| d.week_day)
+        .collect::<Vec<usize>>();
+    assert_eq!(days(ScheduleView::Week, 3), vec![0, 1, 2, 3, 4, 5, 6]);
+    assert_eq!(days(ScheduleView::Today, 3), vec![3]);
+    assert_eq!(days(ScheduleView::Tomorrow, 6), vec![0]);
+    assert_eq!(days(ScheduleView::Upcoming, 5), vec![5, 6, 0, 1, 2, 3, 4]);
+}
@@ -0,0 +1,205 @@
+use std::convert::Infallible;
+
+use embedded_graphics::mono_font::iso_8859_1::{FONT_7X13, FONT_7X13_BOLD};
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+
+use crate::schedule::Schedule;
+
+const FONT: MonoFont = FONT_7X13;
+const HEADER_FONT: MonoFont = FONT_7X13_BOLD;
+const DAY_COLUMN_WIDTH: u32 = 150;
+const TIME_COLUMN_WIDTH: u32 = 60;
+const HEADER_HEIGHT: u32 = 26;
+const PADDING: u32 = 6;
+/// Lines of a cell before the rest gets cut.
+const MAX_CELL_LINES: usize = 6;
+/// Marks cut text. The fonts only cover Latin-1, which has no '…'.
+const ELLIPSIS: &str = "...";
+
+const BACKGROUND: Rgb888 = Rgb888::new(0x36, 0x39, 0x3f);
+const HEADER_BACKGROUND: Rgb888 = Rgb888::new(0x58, 0x65, 0xf2);
+const GRID: Rgb888 = Rgb888::new(0x20, 0x22, 0x25);
+const TEXT: Rgb888 = Rgb888::new(0xdc, 0xdd, 0xde);
+const HEADER_TEXT: Rgb888 = Rgb888::new(0xff, 0xff, 0xff);
+
+/// An RGB image to draw on.
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, color: Rgb888) -> Canvas {
+        let pixels = [color.r(), color.g(), color.b()].repeat((width * height) as usize);
+        Canvas { width, height, pixels }
+    }
+
+    fn into_png(self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(data)
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where I: IntoIterator<Item=Pixel<Self::Color>> {
+        for Pixel(point, color) in pixels {
+            if point.x < 0 || point.y < 0 || point.x as u32 >= self.width || point.y as u32 >= self.height {
+                continue;
+            }
+            let i = (point.y as usize * self.width as usize + point.x as usize) * 3;
+            self.pixels[i..i + 3].copy_from_slice(&[color.r(), color.g(), color.b()]);
+        }
+        Ok(())
+    }
+}
+
+/// Breaks text into lines of at most `width` chars at spaces. Words longer than a line
+/// are split and anything beyond `max_lines` is cut with an ellipsis.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let current_len = current.chars().count();
+        if current_len > 0 && current_len + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        while word.len() > width {
+            let rest = word.split_off(width);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        if !current.is_empty() { current.push(' '); }
+        current.extend(word);
+    }
+    if !current.is_empty() { lines.push(current); }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = &mut lines[max_lines - 1];
+        let mut cut: String = last.chars().take(width.saturating_sub(ELLIPSIS.len())).collect();
+        cut.push_str(ELLIPSIS);
+        *last = cut;
+    }
+    lines
+}
+
+fn fill(canvas: &mut Canvas, x: u32, y: u32, width: u32, height: u32, color: Rgb888) {
+    Rectangle::new(Point::new(x as i32, y as i32), Size::new(width, height))
+        .into_styled(PrimitiveStyle::with_fill(color))
+        .draw(canvas).ok();
+}
+
+fn text(canvas: &mut Canvas, lines: &[String], x: u32, y: u32, font: &MonoFont, color: Rgb888) {
+    let style = MonoTextStyle::new(font, color);
+    for (i, line) in lines.iter().enumerate() {
+        let top = y + i as u32 * font.character_size.height;
+        Text::with_baseline(line, Point::new(x as i32, top as i32), style, Baseline::Top)
+            .draw(canvas).ok();
+    }
+}
+
+/// Draws the airing shows of a schedule as a timetable with one column per day and one
+/// row per release time.
+pub fn render(schedule: &Schedule) -> Result<Vec<u8>, png::EncodingError> {
+    let chars_per_line = ((DAY_COLUMN_WIDTH - 2 * PADDING) / FONT.character_size.width) as usize;
+    let line_height = FONT.character_size.height;
+
+    let slots = schedule.time_slots();
+    // cells[row][day], every show starting on a new line
+    let cells: Vec<Vec<Vec<String>>> = slots.iter()
+        .map(|&(hour, minute)| schedule.days.iter()
+            .map(|day| {
+                let mut lines: Vec<String> = day.entries.iter()
+                    .filter(|e| e.hour == hour && e.minute == minute)
+                    .flat_map(|e| wrap(&e.name, chars_per_line, MAX_CELL_LINES))
+                    .collect();
+                if lines.len() > MAX_CELL_LINES {
+                    lines.truncate(MAX_CELL_LINES);
+                    lines[MAX_CELL_LINES - 1] = ELLIPSIS.to_string();
+                }
+                lines
+            })
+            .collect())
+        .collect();
+    let row_heights: Vec<u32> = cells.iter()
+        .map(|row| row.iter().map(Vec::len).max().unwrap_or(0).max(1) as u32 * line_height + 2 * PADDING)
+        .collect();
+
+    let width = TIME_COLUMN_WIDTH + schedule.days.len() as u32 * DAY_COLUMN_WIDTH;
+    let height = HEADER_HEIGHT + row_heights.iter().sum::<u32>();
+    let mut canvas = Canvas::new(width, height, BACKGROUND);
+
+    fill(&mut canvas, 0, 0, width, HEADER_HEIGHT, HEADER_BACKGROUND);
+    let header_top = (HEADER_HEIGHT - HEADER_FONT.character_size.height) / 2;
+    for (i, day) in schedule.days.iter().enumerate() {
+        let x = TIME_COLUMN_WIDTH + i as u32 * DAY_COLUMN_WIDTH;
+        text(&mut canvas, &[day.name().to_string()], x + PADDING, header_top, &HEADER_FONT, HEADER_TEXT);
+    }
+
+    let mut y = HEADER_HEIGHT;
+    for ((&(hour, minute), row), row_height) in slots.iter().zip(&cells).zip(&row_heights) {
+        fill(&mut canvas, 0, y, width, 1, GRID);
+        text(&mut canvas, &[format!("{:02}:{:02}", hour, minute)], PADDING, y + PADDING, &HEADER_FONT, HEADER_TEXT);
+        for (i, lines) in row.iter().enumerate() {
+            let x = TIME_COLUMN_WIDTH + i as u32 * DAY_COLUMN_WIDTH;
+            text(&mut canvas, lines, x + PADDING, y + PADDING, &FONT, TEXT);
+        }
+        y += row_height;
+    }
+    for i in 0..schedule.days.len() as u32 {
+        fill(&mut canvas, TIME_COLUMN_WIDTH + i * DAY_COLUMN_WIDTH, 0, 1, height, GRID);
+    }
+    canvas.into_png()
+}
+
+
+#[test]
+fn test_wrap() {
+    assert_eq!(wrap("Boruto - Naruto Next Generations", 12, 6), vec!["Boruto -", "Naruto Next", "Generations"]);
+    assert_eq!(wrap("Yuukoku no Moriarty", 4, 3), vec!["Yuuk", "oku", "n..."]);
+    assert_eq!(wrap("Kaguya-sama wa Kokurasetai", 12, 2), vec!["Kaguya-sama", "wa..."]);
+    assert_eq!(wrap("abcdefghij", 4, 6), vec!["abcd", "efgh", "ij"]);
+    assert!(wrap("", 10, 6).is_empty());
+}
+
+#[test]
+fn test_render() {
+    use crate::subs_pls::page_parser::{test_show, AirTime};
+    let show = |name: &str, week_day: i32, hour: i32|
+        test_show("", name, AirTime { is_airing: true, est_week_day: week_day, est_h: hour, est_m: 0 });
+    let schedule = Schedule::from_shows(&[show("One Piece", 0, 17), show("Kingdom S3", 1, 9),
+                                          show("Boruto - Naruto Next Generations", 1, 9)]);
+    let data = render(&schedule).unwrap();
+    let decoder = png::Decoder::new(data.as_slice());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    // the 09:00 row comes first and needs three lines for Tuesday: Boruto takes two, Kingdom one
+    assert_eq!((info.width, info.height), (60 + 7 * 150, 26 + (3 * 13 + 12) + (13 + 12)));
+    let text_pixels = pixels.chunks(3).filter(|p| p == &[TEXT.r(), TEXT.g(), TEXT.b()]).count();
+    assert!(text_pixels > 100);
+    // cut titles end in glyphs the font has, not its replacement glyph
+    let replacement = FONT.glyph_mapping.index('\u{2026}');
+    assert!(ELLIPSIS.chars().all(|c| FONT.glyph_mapping.index(c) != replacement));
+}
@@ -0,0 +1,41 @@
+//! Encryption of secrets users hand the bot, like api keys, before they go into the db.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts with AES-256-GCM under a fresh random nonce, which is put in front of the result.
+pub fn encrypt(key: &[u8; 32], secret: &str) -> Vec<u8> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce: [u8; NONCE_LENGTH] = rand::random();
+    let mut encrypted = nonce.to_vec();
+    // encrypting into a Vec only fails for absurdly large inputs
+    encrypted.extend(cipher.encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+        .expect("secret is small enough to encrypt"));
+    encrypted
+}
+
+/// None if the data was changed or encrypted with another key.
+pub fn decrypt(key: &[u8; 32], encrypted: &[u8]) -> Option<String> {
+    if encrypted.len() < NONCE_LENGTH { return None; }
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let secret = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+    String::from_utf8(secret).ok()
+}
+
+
+#[test]
+fn test_encryption() {
+    let key = [7; 32];
+    let encrypted = encrypt(&key, "api key");
+    assert!(!encrypted.windows(7).any(|w| w == b"api key"));
+    assert_ne!(encrypt(&key, "api key"), encrypted);
+    assert_eq!(decrypt(&key, &encrypted).as_deref(), Some("api key"));
+    assert_eq!(decrypt(&[8; 32], &encrypted), None);
+    let mut changed = encrypted.clone();
+    *changed.last_mut().unwrap() ^= 1;
+    assert_eq!(decrypt(&key, &changed), None);
+    assert_eq!(decrypt(&key, &[1, 2, 3]), None);
+}
@@ -0,0 +1,126 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::header::{CONTENT_TYPE, LOCATION};
+use hyper::service::{make_service_fn, service_fn};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::links;
+use crate::metrics::{DbGauges, METRICS};
+use crate::shutdown::Shutdown;
+use crate::subs_pls::db;
+use crate::user_manager;
+
+/// Runs the embedded http server until a shutdown is requested.
+pub async fn serve(config: Arc<Config>, shutdown: Arc<Shutdown>) {
+    let addr: SocketAddr = config.http.bind;
+    let make_service = make_service_fn(move |_| {
+        let config = config.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| handle(config.clone(), req)))
+        }
+    });
+    println!("Serving http on {}", addr);
+    let server = Server::bind(&addr)
+        .serve(make_service)
+        .with_graceful_shutdown(async move { shutdown.requested().await });
+    if let Err(e) = server.await {
+        println!("Http server error: {}", e);
+    }
+}
+
+async fn handle(config: Arc<Config>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let res = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => health(&config).await,
+        (&Method::GET, "/metrics") => metrics().await,
+        (&Method::GET, path) if path.starts_with("/ical/") => calendar(&config, path).await,
+        (&Method::GET, path) if path.starts_with("/r/") => redirect(path).await,
+        _ => text(StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+    Ok(res)
+}
+
+/// A database that accepts connections but doesn't answer counts as unreachable, rather
+/// than holding the probe until it gives up.
+const DB_PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize)]
+struct Health {
+    gateway_connected: bool,
+    database_reachable: bool,
+    last_rss_poll_age_secs: Option<i64>,
+}
+
+async fn health(config: &Config) -> Response<Body> {
+    let health = Health {
+        gateway_connected: METRICS.is_gateway_connected(),
+        database_reachable: matches!(tokio::time::timeout(DB_PING_TIMEOUT, db::ping()).await, Ok(Ok(()))),
+        last_rss_poll_age_secs: METRICS.last_rss_poll_age(),
+    };
+    // a few missed polls are fine, the feed is flaky from time to time
+    let max_poll_age = 3 * config.feeds.rss_refresh_secs as i64;
+    let is_healthy = health.gateway_connected && health.database_reachable &&
+        matches!(health.last_rss_poll_age_secs, Some(age) if age <= max_poll_age);
+    let status = if is_healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&health).unwrap_or_default()))
+        .unwrap()
+}
+
+async fn metrics() -> Response<Body> {
+    let gauges = match (db::count_users().await, db::count_shows().await) {
+        (Ok(registered_users), Ok(tracked_shows)) => Some(DbGauges { registered_users, tracked_shows }),
+        _ => None
+    };
+    Response::builder()
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(METRICS.render(gauges.as_ref())))
+        .unwrap()
+}
+
+/// Serves `/ical/<token>.ics`, the calendar feed of the user the token belongs to.
+async fn calendar(config: &Config, path: &str) -> Response<Body> {
+    let token = match path.strip_prefix("/ical/").and_then(|p| p.strip_suffix(".ics")) {
+        Some(token) if !token.is_empty() => token,
+        _ => return text(StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+    match user_manager::get_calendar_for_token(config, token).await {
+        Ok(Some(calendar)) => Response::builder()
+            .header(CONTENT_TYPE, "text/calendar; charset=utf-8")
+            .body(Body::from(calendar))
+            .unwrap(),
+        Ok(None) => text(StatusCode::NOT_FOUND, "not found".to_string()),
+        Err(_) => text(StatusCode::SERVICE_UNAVAILABLE, "database unavailable".to_string()),
+    }
+}
+
+/// Serves `/r/<token>`, a redirect to the download link the token stands for.
+async fn redirect(path: &str) -> Response<Body> {
+    let token = match path.strip_prefix("/r/") {
+        Some(token) if !token.is_empty() => token,
+        _ => return text(StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+    match db::get_link_url(token).await {
+        Ok(Some(url)) => Response::builder()
+            .status(StatusCode::FOUND)
+            .header(LOCATION, links::location(&url))
+            .body(Body::empty())
+            .unwrap(),
+        Ok(None) => text(StatusCode::NOT_FOUND, "this link is unknown or expired".to_string()),
+        Err(_) => text(StatusCode::SERVICE_UNAVAILABLE, "database unavailable".to_string()),
+    }
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
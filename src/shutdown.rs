@@ -0,0 +1,97 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+
+/// Coordinates shutting down the background jobs. Jobs register themselves with
+/// `start_job` and keep the returned guard alive while they work; once a shutdown is
+/// requested no new jobs are started and `drain` waits for the running ones.
+pub struct Shutdown {
+    requested: watch::Sender<bool>,
+    job_sender: Mutex<Option<mpsc::Sender<()>>>,
+    job_receiver: tokio::sync::Mutex<mpsc::Receiver<()>>,
+}
+
+/// Held by a running job. Dropping it marks the job as finished. Work the job hands to other
+/// tasks keeps a clone, so the job only counts as finished once that work is done too.
+#[derive(Clone)]
+pub struct JobGuard {
+    _sender: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        let (requested, _) = watch::channel(false);
+        let (job_sender, job_receiver) = mpsc::channel(1);
+        Shutdown {
+            requested,
+            job_sender: Mutex::new(Some(job_sender)),
+            job_receiver: tokio::sync::Mutex::new(job_receiver),
+        }
+    }
+
+    /// Returns `None` if the bot is shutting down and the job shouldn't run.
+    pub fn start_job(&self) -> Option<JobGuard> {
+        self.job_sender.lock().unwrap().as_ref()
+            .map(|sender| JobGuard { _sender: sender.clone() })
+    }
+
+    /// Resolves once a shutdown has been requested.
+    pub async fn requested(&self) {
+        let mut receiver = self.requested.subscribe();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() { return; }
+        }
+    }
+
+    /// Stops new jobs from starting and waits for the running ones. Returns false if
+    /// they didn't finish within `timeout`.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.requested.send_replace(true);
+        self.job_sender.lock().unwrap().take();
+        let mut receiver = self.job_receiver.lock().await;
+        tokio::time::timeout(timeout, receiver.recv()).await.is_ok()
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+/// Waits for ctrl-c or, on unix, SIGTERM.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("installing SIGTERM handler failed");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
+
+
+#[tokio::test]
+async fn test_drain_waits_for_running_jobs() {
+    let shutdown = std::sync::Arc::new(Shutdown::new());
+    let guard = shutdown.start_job().unwrap();
+
+    assert!(!shutdown.drain(Duration::from_millis(20)).await);
+    assert!(shutdown.start_job().is_none());
+    shutdown.requested().await;
+
+    let handed_off = guard.clone();
+    let draining = shutdown.clone();
+    let drained = tokio::spawn(async move { draining.drain(Duration::from_secs(5)).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    drop(guard);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!drained.is_finished());
+    drop(handed_off);
+    assert!(drained.await.unwrap());
+}
@@ -1,16 +1,59 @@
-use std::env;
-use tokio_postgres::{Client, Error, NoTls};
-use crate::subs_pls::page_parser::{Show, AirTime};
+use std::sync::OnceLock;
+use native_tls::{Certificate, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use chrono::{DateTime, Utc};
+use tokio_postgres::{Client, Error, NoTls, Row, Socket};
+use tokio_postgres::tls::MakeTlsConnect;
+use crate::config::{DatabaseConfig, TlsMode};
+use crate::downloads::{ClientKind, ClientSettings, DownloadOptions};
+use crate::notifiers::{Channel, ChannelKind};
+use crate::subs_pls::page_parser::{Show, AirTime, ExternalIds};
+use crate::subs_pls::release_parser::Release;
+use crate::subs_pls::digest::{DeliveryMode, DeliverySettings, QuietHours};
 
+struct ConnectionSettings {
+    config: tokio_postgres::Config,
+    tls: Option<MakeTlsConnector>,
+}
+
+static CONNECTION_SETTINGS: OnceLock<ConnectionSettings> = OnceLock::new();
+
+/// Stores the connection settings used by every query. Must be called once before the
+/// first database access.
+pub fn init(config: &DatabaseConfig) -> Result<(), native_tls::Error> {
+    let tls = match config.tls {
+        TlsMode::Disable => None,
+        mode => {
+            let mut builder = TlsConnector::builder();
+            if let Some(bundle) = &config.ca_bundle {
+                for cert in Certificate::stack_from_pem(bundle)? {
+                    builder.add_root_certificate(cert);
+                }
+            }
+            // prefer and require only encrypt, like libpq. A custom CA still gets checked.
+            if mode != TlsMode::VerifyFull {
+                builder.danger_accept_invalid_hostnames(true);
+                builder.danger_accept_invalid_certs(config.ca_bundle.is_none());
+            }
+            Some(MakeTlsConnector::new(builder.build()?))
+        }
+    };
+    CONNECTION_SETTINGS.set(ConnectionSettings { config: config.connection.clone(), tls }).ok();
+    Ok(())
+}
 
 async fn connect_db() -> Result<Client, Error> {
-    let host = env::var("DB_IP").unwrap();
-    let user = env::var("DB_USER").unwrap();
-    let db_name = env::var("DB_NAME").unwrap();
-    let db_pw = env::var("DB_PW").unwrap();
-    let (client, connection) =
-        tokio_postgres::connect(&format!("host={} user={} dbname={} password={}", host, user, db_name, db_pw),
-                                NoTls).await?;
+    let settings = CONNECTION_SETTINGS.get().expect("db::init has to be called before connecting");
+    match &settings.tls {
+        Some(tls) => spawn_connection(&settings.config, tls.clone()).await,
+        None => spawn_connection(&settings.config, NoTls).await,
+    }
+}
+
+async fn spawn_connection<T>(config: &tokio_postgres::Config, tls: T) -> Result<Client, Error>
+    where T: MakeTlsConnect<Socket>,
+          T::Stream: Send + 'static {
+    let (client, connection) = config.connect(tls).await?;
 
     tokio::spawn(async move {
         if let Err(e) = connection.await {
@@ -21,15 +64,26 @@ async fn connect_db() -> Result<Client, Error> {
     Ok(client)
 }
 
-pub async fn get_user_ids_for_show_id(show_id: &str) -> Result<Vec<i64>, Error> {
+/// Creates missing tables and columns.
+pub async fn migrate() -> Result<(), Error> {
     let client = connect_db().await?;
-    let mut user_ids = Vec::new();
-    for row in client.query("select user_id from user_shows where show_id = $1", &[&show_id])
-        .await.or_else(|_| Ok(Vec::new()))? {
-        let id = row.get(0);
-        user_ids.push(id);
-    }
-    Ok(user_ids)
+    client.batch_execute(include_str!("schema.sql")).await
+}
+
+pub async fn ping() -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query_one("select 1", &[]).await?;
+    Ok(())
+}
+
+pub async fn count_users() -> Result<i64, Error> {
+    let client = connect_db().await?;
+    Ok(client.query_one("select count(*) from users", &[]).await?.get(0))
+}
+
+pub async fn count_shows() -> Result<i64, Error> {
+    let client = connect_db().await?;
+    Ok(client.query_one("select count(*) from shows", &[]).await?.get(0))
 }
 
 pub async fn is_show_saved(show_id: &str) -> Result<bool, Error> {
@@ -39,13 +93,26 @@ pub async fn is_show_saved(show_id: &str) -> Result<bool, Error> {
     Ok(!res.is_empty())
 }
 
-pub async fn insert_show(show: &Show) -> Result<(), Error> {
-    let client = connect_db().await?;
-    client.query("insert into shows values ($1, $2, $3, $4, $5, $6, $7, $8)",
-                 &[&show.id, &show.name, &show.image_url, &show.synopsis,
-                     &show.air_time.is_airing, &show.air_time.est_week_day,
-                     &show.air_time.est_h, &show.air_time.est_m]).await?;
-    Ok(())
+/// Puts the show on the watchlist of the user, saving `new_show` first if it isn't saved yet.
+/// Returns false when the show already was on the watchlist, in which case nothing changes.
+pub async fn insert_user_show(user_id: i64, show_id: &str, new_show: Option<&Show>) -> Result<bool, Error> {
+    let mut client = connect_db().await?;
+    let transaction = client.transaction().await?;
+    if let Some(show) = new_show {
+        transaction.query("insert into shows (id, name, image_url, synopsis, is_airing, est_week_day, est_h, est_m, \
+                          mal_id, anilist_id, kitsu_id, ids_resolved, season) \
+                          values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) on conflict (id) do nothing",
+                          &[&show.id, &show.name, &show.image_url, &show.synopsis,
+                              &show.air_time.is_airing, &show.air_time.est_week_day,
+                              &show.air_time.est_h, &show.air_time.est_m,
+                              &show.external_ids.mal, &show.external_ids.anilist, &show.external_ids.kitsu,
+                              &show.external_ids.resolved, &show.season]).await?;
+    }
+    let inserted = transaction.query("insert into user_shows (user_id, show_id) values ($1, $2) \
+                                     on conflict (user_id, show_id) do nothing returning show_id",
+                                     &[&user_id, &show_id]).await?;
+    transaction.commit().await?;
+    Ok(!inserted.is_empty())
 }
 
 pub async fn update_show(show: &Show) -> Result<(), Error> {
@@ -57,95 +124,117 @@ pub async fn update_show(show: &Show) -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn update_external_ids(show_id: &str, ids: &ExternalIds) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("update shows set mal_id = $2, anilist_id = $3, kitsu_id = $4, ids_resolved = $5 where id = $1",
+                 &[&show_id, &ids.mal, &ids.anilist, &ids.kitsu, &ids.resolved]).await?;
+    Ok(())
+}
+
 pub async fn get_all_show_ids() -> Result<Vec<String>, Error> {
     let client = connect_db().await?;
     let rows = client.query("select id from shows", &[]).await?;
     Ok(rows.iter().map(|r| r.get(0) ).collect())
 }
 
-pub async fn get_show_from_show_id(show_id: &str) -> Result<Show, Error> {
+/// Ids and names of all saved shows.
+pub async fn get_all_show_names() -> Result<Vec<(String, String)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select id, name from shows", &[]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// Shows whose external ids were never looked up successfully.
+pub async fn get_shows_without_external_ids() -> Result<Vec<Show>, Error> {
     let client = connect_db().await?;
-    let row = client.query_one("select * from shows where id = $1", &[&show_id]).await?;
-
-    let id: &str = row.get(0);
-    let name: &str = row.get(1);
-    let image_url: &str = row.get(2);
-    let synopsis: &str = row.get(3);
-    let is_airing: bool = row.get(4);
-    let est_week_day: i32 = row.get(5);
-    let est_h: i32 = row.get(6);
-    let est_m: i32 = row.get(7);
-
-    Ok(Show {
-        id: id.to_string(),
-        name: name.to_string(),
-        image_url: image_url.to_string(),
-        synopsis: synopsis.to_string(),
+    let rows = client.query(&*format!("select {} from shows where not ids_resolved", SHOW_COLUMNS), &[]).await?;
+    Ok(rows.iter().map(row_to_show).collect())
+}
+
+const SHOW_COLUMNS: &str = "shows.id, shows.name, shows.image_url, shows.synopsis, shows.is_airing, \
+    shows.est_week_day, shows.est_h, shows.est_m, shows.mal_id, shows.anilist_id, shows.kitsu_id, shows.ids_resolved, \
+    shows.season";
+
+fn row_to_show(row: &Row) -> Show {
+    Show {
+        id: row.get("id"),
+        name: row.get("name"),
+        image_url: row.get("image_url"),
+        synopsis: row.get("synopsis"),
         air_time: AirTime {
-            is_airing,
-            est_week_day,
-            est_h,
-            est_m,
+            is_airing: row.get("is_airing"),
+            est_week_day: row.get("est_week_day"),
+            est_h: row.get("est_h"),
+            est_m: row.get("est_m"),
         },
-    })
+        external_ids: ExternalIds {
+            mal: row.get("mal_id"),
+            anilist: row.get("anilist_id"),
+            kitsu: row.get("kitsu_id"),
+            resolved: row.get("ids_resolved"),
+        },
+        season: row.get("season"),
+    }
+}
+
+pub async fn get_show_from_show_id(show_id: &str) -> Result<Show, Error> {
+    let client = connect_db().await?;
+    let row = client.query_one(&*format!("select {} from shows where id = $1", SHOW_COLUMNS), &[&show_id]).await?;
+    Ok(row_to_show(&row))
 }
 
 pub async fn get_shows_for_user(user_id: i64) -> Result<Vec<Show>, Error> {
     let client = connect_db().await?;
-    let rows = client.query("select * from shows inner join user_shows us \
-        on shows.id = us.show_id where us.user_id = $1", &[&user_id]).await?;
-    let mut shows = Vec::with_capacity(rows.len());
-    for row in rows {
-        let id: &str = row.get(0);
-        let name: &str = row.get(1);
-        let image_url: &str = row.get(2);
-        let synopsis: &str = row.get(3);
-        let is_airing: bool = row.get(4);
-        let est_week_day: i32 = row.get(5);
-        let est_h: i32 = row.get(6);
-        let est_m: i32 = row.get(7);
-
-        shows.push(Show {
-            id: id.to_string(),
-            name: name.to_string(),
-            image_url: image_url.to_string(),
-            synopsis: synopsis.to_string(),
-            air_time: AirTime {
-                is_airing,
-                est_week_day,
-                est_h,
-                est_m,
-            },
-        })
-    }
-    Ok(shows)
+    let rows = client.query(&*format!("select {} from shows inner join user_shows us \
+        on shows.id = us.show_id where us.user_id = $1", SHOW_COLUMNS), &[&user_id]).await?;
+    Ok(rows.iter().map(row_to_show).collect())
+}
+
+/// Shows on a watchlist with the time each one was added.
+pub async fn get_watchlist(user_id: i64) -> Result<Vec<(Show, DateTime<Utc>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query(&*format!("select {}, us.added_at from shows inner join user_shows us \
+        on shows.id = us.show_id where us.user_id = $1", SHOW_COLUMNS), &[&user_id]).await?;
+    Ok(rows.iter().map(|r| (row_to_show(r), r.get("added_at"))).collect())
 }
 
 pub async fn get_show_from_name(show_name: &str) -> Result<Show, Error> {
     let client = connect_db().await?;
-    let row = client.query_one("select * from shows where name = $1", &[&show_name]).await?;
-
-    let id: &str = row.get(0);
-    let name: &str = row.get(1);
-    let image_url: &str = row.get(2);
-    let synopsis: &str = row.get(3);
-    let is_airing: bool = row.get(4);
-    let est_week_day: i32 = row.get(5);
-    let est_h: i32 = row.get(6);
-    let est_m: i32 = row.get(7);
-
-    Ok(Show {
-        id: id.to_string(),
-        name: name.to_string(),
-        image_url: image_url.to_string(),
-        synopsis: synopsis.to_string(),
-        air_time: AirTime {
-            is_airing,
-            est_week_day,
-            est_h,
-            est_m,
-        },
-    })
+    let row = client.query_one(&*format!("select {} from shows where name = $1", SHOW_COLUMNS), &[&show_name]).await?;
+    Ok(row_to_show(&row))
+}
+
+pub async fn count_watchers(show_id: &str) -> Result<i64, Error> {
+    let client = connect_db().await?;
+    Ok(client.query_one("select count(*) from user_shows where show_id = $1", &[&show_id]).await?.get(0))
+}
+
+pub async fn insert_release(release: &Release) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into releases (guid, show_id, title, episode, link, file_size, released_at) \
+                 values ($1, $2, $3, $4, $5, $6, $7) on conflict (guid) do nothing",
+                 &[&release.guid, &release.show_id, &release.title, &release.episode,
+                     &release.link, &release.file_size, &release.released_at]).await?;
+    Ok(())
+}
+
+pub async fn get_latest_release(show_id: &str) -> Result<Option<Release>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt("select guid, show_id, title, episode, link, file_size, released_at from releases \
+                               where show_id = $1 order by released_at desc limit 1", &[&show_id]).await?;
+    Ok(row.as_ref().map(row_to_release))
+}
+
+fn row_to_release(row: &Row) -> Release {
+    Release {
+        guid: row.get("guid"),
+        show_id: row.get("show_id"),
+        title: row.get("title"),
+        episode: row.get("episode"),
+        link: row.get("link"),
+        file_size: row.get("file_size"),
+        released_at: row.get("released_at"),
+    }
 }
 
 pub async fn does_user_show_exist(user_id: i64, show_id: &str) -> Result<bool, Error> {
@@ -155,16 +244,20 @@ pub async fn does_user_show_exist(user_id: i64, show_id: &str) -> Result<bool, E
     Ok(!is_empty)
 }
 
-pub async fn insert_user_show(user_id: i64, show_id: &str) -> Result<(), Error> {
+pub async fn delete_user_show(user_id: i64, show_id: &str) -> Result<(), Error> {
     let client = connect_db().await?;
-    client.query("insert into user_shows values ($1, $2)", &[&user_id, &show_id]).await?;
+    client.query("delete from user_shows where user_id = $1 and show_id = $2", &[&user_id, &show_id]).await?;
     Ok(())
 }
 
-pub async fn delete_user_show(user_id: i64, show_id: &str) -> Result<(), Error> {
+/// Takes every show that isn't airing anymore off the watchlist and returns them.
+pub async fn delete_non_airing_user_shows(user_id: i64) -> Result<Vec<Show>, Error> {
     let client = connect_db().await?;
-    client.query("delete from user_shows where user_id = $1 and show_id = $2", &[&user_id, &show_id]).await?;
-    Ok(())
+    let rows = client.query(&*format!("with removed as (delete from user_shows us using shows \
+        where shows.id = us.show_id and us.user_id = $1 and not shows.is_airing returning us.show_id) \
+        select {} from shows where shows.id in (select show_id from removed) order by shows.name", SHOW_COLUMNS),
+                            &[&user_id]).await?;
+    Ok(rows.iter().map(row_to_show).collect())
 }
 
 
@@ -174,16 +267,468 @@ pub async fn is_user_registered(user_id: i64) -> Result<bool, Error> {
     Ok(!res.is_empty())
 }
 
-pub async fn insert_user(user_id: i64) -> Result<(), Error> {
+/// Registers the user and brings back the watchlist they had when they unregistered after
+/// `restore_since`. Returns how many shows came back.
+pub async fn insert_user(user_id: i64, restore_since: DateTime<Utc>) -> Result<usize, Error> {
+    let mut client = connect_db().await?;
+    let transaction = client.transaction().await?;
+    transaction.query("insert into users values ($1)", &[&user_id]).await?;
+    let restored = transaction.query("insert into user_shows (user_id, show_id, added_at) \
+                                     select user_id, show_id, added_at from unregistered_watchlists \
+                                     where user_id = $1 and unregistered_at > $2 returning show_id",
+                                     &[&user_id, &restore_since]).await?;
+    transaction.query("delete from unregistered_watchlists where user_id = $1", &[&user_id]).await?;
+    transaction.commit().await?;
+    Ok(restored.len())
+}
+
+/// Deletes everything about the user at once. With `keep_watchlist` the watchlist is put
+/// aside, for `insert_user` to bring back.
+pub async fn remove_user(user_id: i64, keep_watchlist: bool) -> Result<(), Error> {
+    let mut client = connect_db().await?;
+    let transaction = client.transaction().await?;
+    if keep_watchlist {
+        transaction.query("insert into unregistered_watchlists (user_id, show_id, added_at) \
+                          select user_id, show_id, added_at from user_shows where user_id = $1 \
+                          on conflict (user_id, show_id) do update set added_at = excluded.added_at, unregistered_at = now()",
+                          &[&user_id]).await?;
+    }
+    transaction.query("delete from user_shows where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from calendar_tokens where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from pending_notifications where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from premiumize_keys where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from download_clients where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from webhooks where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from notification_channels where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from notification_history where user_id = $1", &[&user_id]).await?;
+    transaction.query("delete from users where id = $1", &[&user_id]).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Deletes the kept watchlists of users who unregistered before `before`.
+pub async fn delete_unregistered_watchlists(before: DateTime<Utc>) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("delete from unregistered_watchlists where unregistered_at <= $1", &[&before]).await?;
+    Ok(())
+}
+
+pub async fn get_calendar_token(user_id: i64) -> Result<Option<String>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt("select token from calendar_tokens where user_id = $1", &[&user_id]).await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Replaces the token of the user, which makes the old calendar url stop working.
+pub async fn set_calendar_token(user_id: i64, token: &str) -> Result<(), Error> {
     let client = connect_db().await?;
-    client.query("insert into users values ($1)", &[&user_id]).await?;
+    client.query("insert into calendar_tokens (user_id, token) values ($1, $2) \
+                 on conflict (user_id) do update set token = excluded.token", &[&user_id, &token]).await?;
     Ok(())
 }
 
-pub async fn remove_user(user_id: i64) -> Result<(), Error> {
+pub async fn get_user_for_calendar_token(token: &str) -> Result<Option<i64>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt("select user_id from calendar_tokens where token = $1", &[&token]).await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Ids of every show ever seen in the schedule.
+pub async fn get_seen_show_ids() -> Result<Vec<String>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select show_id from season_shows", &[]).await?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// Remembers shows, as ids and titles, that appeared in the schedule. Saved shows without a
+/// season get it too.
+pub async fn insert_season_shows(shows: &[(String, String)], season: &str, announced: bool) -> Result<(), Error> {
+    let mut client = connect_db().await?;
+    let transaction = client.transaction().await?;
+    for (show_id, title) in shows {
+        transaction.query("insert into season_shows (show_id, title, season, announced) values ($1, $2, $3, $4) \
+                          on conflict (show_id) do nothing", &[&show_id, &title, &season, &announced]).await?;
+        transaction.query("update shows set season = $2 where id = $1 and season is null", &[&show_id, &season]).await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn get_season_of_show(show_id: &str) -> Result<Option<String>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt("select season from season_shows where show_id = $1", &[&show_id]).await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Ids and titles of the shows that first appeared in a season, by title.
+pub async fn get_season_shows(season: &str) -> Result<Vec<(String, String)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select show_id, title from season_shows where season = $1 order by lower(title)",
+                            &[&season]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// Ids and titles of new shows no digest went out for yet.
+pub async fn get_unannounced_shows() -> Result<Vec<(String, String)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select show_id, title from season_shows where not announced order by lower(title)",
+                            &[]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+pub async fn set_shows_announced(show_ids: &[String]) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("update season_shows set announced = true where show_id = any($1)", &[&show_ids]).await?;
+    Ok(())
+}
+
+pub async fn set_season_digest(user_id: i64, enabled: bool) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("update users set season_digest = $2 where id = $1", &[&user_id, &enabled]).await?;
+    Ok(())
+}
+
+pub async fn get_season_digest_user_ids() -> Result<Vec<i64>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select id from users where season_digest", &[]).await?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+pub async fn set_delivery_mode(user_id: i64, mode: DeliveryMode) -> Result<(), Error> {
+    let client = connect_db().await?;
+    let (mode, hour, week_day) = mode.to_db();
+    client.query("update users set delivery_mode = $2, delivery_hour = $3, delivery_week_day = $4 where id = $1",
+                 &[&user_id, &mode, &hour, &week_day]).await?;
+    Ok(())
+}
+
+const DELIVERY_COLUMNS: &str = "users.delivery_mode, users.delivery_hour, users.delivery_week_day, \
+    users.quiet_start, users.quiet_end, users.quiet_batched, users.timezone";
+
+fn row_to_delivery_settings(row: &Row) -> DeliverySettings {
+    DeliverySettings {
+        mode: DeliveryMode::from_db(row.get("delivery_mode"), row.get("delivery_hour"), row.get("delivery_week_day")),
+        quiet_hours: QuietHours::from_db(row.get("quiet_start"), row.get("quiet_end"), row.get("quiet_batched")),
+        timezone: row.get::<_, Option<String>>("timezone").and_then(|tz| tz.parse().ok()),
+    }
+}
+
+pub async fn get_delivery_settings(user_id: i64) -> Result<DeliverySettings, Error> {
+    let client = connect_db().await?;
+    let row = client.query_one(&*format!("select {} from users where id = $1", DELIVERY_COLUMNS),
+                               &[&user_id]).await?;
+    Ok(row_to_delivery_settings(&row))
+}
+
+/// Watchers of a show with their delivery settings.
+pub async fn get_delivery_settings_for_show_id(show_id: &str) -> Result<Vec<(i64, DeliverySettings)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query(&*format!("select us.user_id, {} from user_shows us inner join users \
+        on users.id = us.user_id where us.show_id = $1", DELIVERY_COLUMNS), &[&show_id]).await?;
+    Ok(rows.iter().map(|r| (r.get("user_id"), row_to_delivery_settings(r))).collect())
+}
+
+pub async fn set_quiet_hours(user_id: i64, quiet_hours: Option<QuietHours>) -> Result<(), Error> {
+    let client = connect_db().await?;
+    let (start, end, batched) = match quiet_hours {
+        Some(q) => (Some(q.start as i32), Some(q.end as i32), q.batched),
+        None => (None, None, true),
+    };
+    client.query("update users set quiet_start = $2, quiet_end = $3, quiet_batched = $4 where id = $1",
+                 &[&user_id, &start, &end, &batched]).await?;
+    Ok(())
+}
+
+pub async fn set_user_timezone(user_id: i64, timezone: &str) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("update users set timezone = $2 where id = $1", &[&user_id, &timezone]).await?;
+    Ok(())
+}
+
+pub async fn queue_notification(user_id: i64, guid: &str) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into pending_notifications (user_id, guid) values ($1, $2) on conflict do nothing",
+                 &[&user_id, &guid]).await?;
+    Ok(())
+}
+
+/// Users with queued releases, their delivery settings and when the oldest release was queued.
+pub async fn get_pending_digests() -> Result<Vec<(i64, DeliverySettings, DateTime<Utc>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query(&*format!("select users.id, {}, min(p.queued_at) as oldest from pending_notifications p \
+        inner join users on users.id = p.user_id group by users.id", DELIVERY_COLUMNS), &[]).await?;
+    Ok(rows.iter().map(|r| (r.get("id"), row_to_delivery_settings(r), r.get("oldest"))).collect())
+}
+
+/// Releases queued for a user up to `until` with the name of their show, oldest first.
+pub async fn get_pending_notifications(user_id: i64, until: DateTime<Utc>)
+    -> Result<Vec<(String, Release, Option<String>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select coalesce(shows.name, r.show_id) as show_name, r.guid, r.show_id, r.title, \
+                            p.note, r.episode, r.link, r.file_size, r.released_at from pending_notifications p \
+                            inner join releases r on r.guid = p.guid left join shows on shows.id = r.show_id \
+                            where p.user_id = $1 and p.queued_at <= $2 order by r.released_at",
+                            &[&user_id, &until]).await?;
+    Ok(rows.iter().map(|r| (r.get("show_name"), row_to_release(r), r.get("note"))).collect())
+}
+
+/// Attaches `note` to a queued release, so the digest can tell what happened to it.
+pub async fn set_pending_note(user_id: i64, guid: &str, note: &str) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("update pending_notifications set note = $3 where user_id = $1 and guid = $2",
+                 &[&user_id, &guid, &note]).await?;
+    Ok(())
+}
+
+/// Clears the queued releases that were sent and logs them for each of `channels`, so neither
+/// happens without the other.
+pub async fn delete_pending_notifications(user_id: i64, sent: &[&str], channels: &[&str]) -> Result<(), Error> {
+    let mut client = connect_db().await?;
+    let transaction = client.transaction().await?;
+    transaction.query("insert into notification_history (user_id, guid, channel) \
+                      select $1, guid, channel from unnest($2::text[]) guid cross join unnest($3::text[]) channel",
+                      &[&user_id, &sent, &channels]).await?;
+    transaction.query("delete from pending_notifications where user_id = $1 and guid = any($2)",
+                      &[&user_id, &sent]).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Saves a redirect link and returns the token of the url, which is the given one unless
+/// the url had a token already.
+pub async fn upsert_link(token: &str, url: &str, expires_at: DateTime<Utc>) -> Result<String, Error> {
+    let client = connect_db().await?;
+    let row = client.query_one("insert into links (token, url, expires_at) values ($1, $2, $3) \
+                               on conflict (url) do update set expires_at = greatest(links.expires_at, excluded.expires_at) \
+                               returning token", &[&token, &url, &expires_at]).await?;
+    Ok(row.get(0))
+}
+
+/// The url of a link that hasn't expired yet.
+pub async fn get_link_url(token: &str) -> Result<Option<String>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt("select url from links where token = $1 and expires_at > now()", &[&token]).await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+pub async fn delete_expired_links() -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("delete from links where expires_at <= now()", &[]).await?;
+    Ok(())
+}
+
+/// Stores the encrypted premiumize api key of a user, replacing an older one.
+pub async fn set_premiumize_key(user_id: i64, api_key: &[u8]) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into premiumize_keys (user_id, api_key) values ($1, $2) \
+                 on conflict (user_id) do update set api_key = excluded.api_key", &[&user_id, &api_key]).await?;
+    Ok(())
+}
+
+pub async fn has_premiumize_key(user_id: i64) -> Result<bool, Error> {
+    let client = connect_db().await?;
+    Ok(client.query_opt("select 1 from premiumize_keys where user_id = $1", &[&user_id]).await?.is_some())
+}
+
+pub async fn delete_premiumize_key(user_id: i64) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("delete from premiumize_keys where user_id = $1", &[&user_id]).await?;
+    Ok(())
+}
+
+/// Watchers of a show with their encrypted premiumize api key.
+pub async fn get_premiumize_keys_for_show_id(show_id: &str) -> Result<Vec<(i64, Vec<u8>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select us.user_id, pk.api_key from user_shows us inner join premiumize_keys pk \
+                            on pk.user_id = us.user_id where us.show_id = $1", &[&show_id]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+const CLIENT_COLUMNS: &str = "dc.kind, dc.url, dc.username, dc.password, dc.category, dc.save_path";
+
+/// The settings without password, and the encrypted password. None for an unknown kind.
+fn row_to_client(row: &Row) -> Option<(ClientSettings, Option<Vec<u8>>)> {
+    let settings = ClientSettings {
+        kind: ClientKind::parse(row.get("kind"))?,
+        url: row.get("url"),
+        username: row.get("username"),
+        password: None,
+        options: DownloadOptions { category: row.get("category"), save_path: row.get("save_path") },
+    };
+    Some((settings, row.get("password")))
+}
+
+/// Stores the download client of a user, replacing an older one. The password of `settings`
+/// is ignored, `password` is the encrypted one.
+pub async fn set_download_client(user_id: i64, settings: &ClientSettings, password: Option<&[u8]>) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into download_clients (user_id, kind, url, username, password, category, save_path) \
+                 values ($1, $2, $3, $4, $5, $6, $7) on conflict (user_id) do update set kind = excluded.kind, \
+                 url = excluded.url, username = excluded.username, password = excluded.password, \
+                 category = excluded.category, save_path = excluded.save_path",
+                 &[&user_id, &settings.kind.id(), &settings.url, &settings.username, &password,
+                     &settings.options.category, &settings.options.save_path]).await?;
+    Ok(())
+}
+
+pub async fn get_download_client(user_id: i64) -> Result<Option<(ClientSettings, Option<Vec<u8>>)>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt(&*format!("select {} from download_clients dc where dc.user_id = $1", CLIENT_COLUMNS),
+                               &[&user_id]).await?;
+    Ok(row.as_ref().and_then(row_to_client))
+}
+
+pub async fn set_download_options(user_id: i64, options: &DownloadOptions) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("update download_clients set category = $2, save_path = $3 where user_id = $1",
+                 &[&user_id, &options.category, &options.save_path]).await?;
+    Ok(())
+}
+
+pub async fn delete_download_client(user_id: i64) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("delete from download_clients where user_id = $1", &[&user_id]).await?;
+    Ok(())
+}
+
+/// Watchers of a show with their download client and its encrypted password.
+pub async fn get_download_clients_for_show_id(show_id: &str)
+                                              -> Result<Vec<(i64, ClientSettings, Option<Vec<u8>>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query(&*format!("select us.user_id, {} from user_shows us inner join download_clients dc \
+        on dc.user_id = us.user_id where us.show_id = $1", CLIENT_COLUMNS), &[&show_id]).await?;
+    Ok(rows.iter()
+        .filter_map(|r| row_to_client(r).map(|(settings, password)| (r.get("user_id"), settings, password)))
+        .collect())
+}
+
+/// Url and secret of the webhook of a user.
+pub async fn get_webhook(user_id: i64) -> Result<Option<(String, String)>, Error> {
+    let client = connect_db().await?;
+    let row = client.query_opt("select url, secret from webhooks where user_id = $1", &[&user_id]).await?;
+    Ok(row.map(|r| (r.get(0), r.get(1))))
+}
+
+pub async fn set_webhook(user_id: i64, url: &str, secret: &str) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into webhooks (user_id, url, secret) values ($1, $2, $3) \
+                 on conflict (user_id) do update set url = excluded.url, secret = excluded.secret",
+                 &[&user_id, &url, &secret]).await?;
+    Ok(())
+}
+
+pub async fn delete_webhook(user_id: i64) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("delete from webhooks where user_id = $1", &[&user_id]).await?;
+    Ok(())
+}
+
+/// Watchers of a show with the url and secret of their webhook.
+pub async fn get_webhooks_for_show_id(show_id: &str) -> Result<Vec<(i64, String, String)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select us.user_id, wh.url, wh.secret from user_shows us inner join webhooks wh \
+                            on wh.user_id = us.user_id where us.show_id = $1", &[&show_id]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1), r.get(2))).collect())
+}
+
+const CHANNEL_COLUMNS: &str = "nc.kind, nc.target, nc.token, nc.enabled, nc.confirmation_code is null as confirmed";
+
+/// The channel without token, and the encrypted token. None for an unknown kind.
+fn row_to_channel(row: &Row) -> Option<(Channel, Option<Vec<u8>>)> {
+    let channel = Channel {
+        kind: ChannelKind::parse(row.get("kind"))?,
+        target: row.get("target"),
+        token: None,
+        enabled: row.get("enabled"),
+        confirmed: row.get("confirmed"),
+    };
+    Some((channel, row.get("token")))
+}
+
+pub async fn get_notification_channels(user_id: i64) -> Result<Vec<(Channel, Option<Vec<u8>>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query(&*format!("select {} from notification_channels nc where nc.user_id = $1 \
+        order by nc.kind", CHANNEL_COLUMNS), &[&user_id]).await?;
+    Ok(rows.iter().filter_map(row_to_channel).collect())
+}
+
+/// Stores a channel, replacing the one of the same kind. The token of `channel` is ignored,
+/// `token` is the encrypted one. With a `confirmation_code` the channel stays unconfirmed
+/// until that code is sent back.
+pub async fn set_notification_channel(user_id: i64, channel: &Channel, token: Option<&[u8]>,
+                                      confirmation_code: Option<&str>) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into notification_channels (user_id, kind, target, token, enabled, confirmation_code) \
+                 values ($1, $2, $3, $4, $5, $6) on conflict (user_id, kind) do update set target = excluded.target, \
+                 token = excluded.token, enabled = excluded.enabled, confirmation_code = excluded.confirmation_code",
+                 &[&user_id, &channel.kind.id(), &channel.target, &token, &channel.enabled, &confirmation_code]).await?;
+    Ok(())
+}
+
+/// Turns the channel on when `code` is the one it was sent. False if it isn't, or the
+/// channel is confirmed already.
+pub async fn confirm_notification_channel(user_id: i64, kind: ChannelKind, code: &str) -> Result<bool, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("update notification_channels set enabled = true, confirmation_code = null \
+                            where user_id = $1 and kind = $2 and confirmation_code = $3 returning kind",
+                            &[&user_id, &kind.id(), &code]).await?;
+    Ok(!rows.is_empty())
+}
+
+/// False if the user has no channel of that kind.
+pub async fn set_notification_channel_enabled(user_id: i64, kind: ChannelKind, enabled: bool) -> Result<bool, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("update notification_channels set enabled = $3 where user_id = $1 and kind = $2 \
+                            returning kind", &[&user_id, &kind.id(), &enabled]).await?;
+    Ok(!rows.is_empty())
+}
+
+/// False if the user has no channel of that kind.
+pub async fn delete_notification_channel(user_id: i64, kind: ChannelKind) -> Result<bool, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("delete from notification_channels where user_id = $1 and kind = $2 returning kind",
+                            &[&user_id, &kind.id()]).await?;
+    Ok(!rows.is_empty())
+}
+
+/// Channels of the watchers of a show with their encrypted token.
+pub async fn get_notification_channels_for_show_id(show_id: &str)
+                                                   -> Result<Vec<(i64, Channel, Option<Vec<u8>>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query(&*format!("select us.user_id, {} from user_shows us inner join notification_channels nc \
+        on nc.user_id = us.user_id where us.show_id = $1", CHANNEL_COLUMNS), &[&show_id]).await?;
+    Ok(rows.iter()
+        .filter_map(|r| row_to_channel(r).map(|(channel, token)| (r.get("user_id"), channel, token)))
+        .collect())
+}
+
+/// When the user registered and whether they get the season digest.
+pub async fn get_user_profile(user_id: i64) -> Result<(Option<DateTime<Utc>>, bool), Error> {
+    let client = connect_db().await?;
+    let row = client.query_one("select registered_at, season_digest from users where id = $1", &[&user_id]).await?;
+    Ok((row.get(0), row.get(1)))
+}
+
+pub async fn log_notifications(user_id: i64, guids: &[&str], channel: &str) -> Result<(), Error> {
+    let client = connect_db().await?;
+    client.query("insert into notification_history (user_id, guid, channel) select $1, unnest($2::text[]), $3",
+                 &[&user_id, &guids, &channel]).await?;
+    Ok(())
+}
+
+/// Guid, title if the release is still known, channel and time, newest first.
+pub async fn get_notification_history(user_id: i64)
+                                      -> Result<Vec<(String, Option<String>, String, DateTime<Utc>)>, Error> {
+    let client = connect_db().await?;
+    let rows = client.query("select h.guid, r.title, h.channel, h.sent_at from notification_history h \
+                            left join releases r on r.guid = h.guid where h.user_id = $1 order by h.sent_at desc",
+                            &[&user_id]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1), r.get(2), r.get(3))).collect())
+}
+
+pub async fn delete_old_notification_history() -> Result<(), Error> {
     let client = connect_db().await?;
-    client.query("delete from user_shows where user_id = $1", &[&user_id]).await?;
-    client.query("delete from users where id = $1", &[&user_id]).await?;
+    client.query("delete from notification_history where sent_at < now() - interval '90 days'", &[]).await?;
     Ok(())
 }
 
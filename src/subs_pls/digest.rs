@@ -0,0 +1,271 @@
+use std::fmt;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serenity::http::client::Http;
+
+use crate::config::Config;
+use crate::links;
+use crate::metrics::METRICS;
+use crate::notifiers::{self, Channel, Episode, Notification};
+use crate::subs_pls::db;
+use crate::subs_pls::page_parser::AirTime;
+use crate::watchlist_file::show_url;
+
+/// How a user gets release notifications. Hours are in the timezone of the user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeliveryMode {
+    /// A DM for every release, the default.
+    Instant,
+    Hourly,
+    Daily { hour: u32 },
+    /// `week_day` 0 is Monday.
+    Weekly { week_day: u32, hour: u32 },
+}
+
+impl DeliveryMode {
+    /// Reads the `delivery_mode`, `delivery_hour` and `delivery_week_day` columns of a user.
+    pub fn from_db(mode: &str, hour: i32, week_day: i32) -> DeliveryMode {
+        let (hour, week_day) = (hour.clamp(0, 23) as u32, week_day.clamp(0, 6) as u32);
+        match mode {
+            "hourly" => DeliveryMode::Hourly,
+            "daily" => DeliveryMode::Daily { hour },
+            "weekly" => DeliveryMode::Weekly { week_day, hour },
+            _ => DeliveryMode::Instant,
+        }
+    }
+
+    /// Values for the `delivery_mode`, `delivery_hour` and `delivery_week_day` columns.
+    pub fn to_db(self) -> (&'static str, i32, i32) {
+        match self {
+            DeliveryMode::Instant => ("instant", 0, 0),
+            DeliveryMode::Hourly => ("hourly", 0, 0),
+            DeliveryMode::Daily { hour } => ("daily", hour as i32, 0),
+            DeliveryMode::Weekly { week_day, hour } => ("weekly", hour as i32, week_day as i32),
+        }
+    }
+
+    /// The latest time a digest was due, up to `now`. Releases queued before it are ready to
+    /// be sent. Instant users get whatever is still queued right away.
+    pub fn last_delivery(self, tz: Tz, now: DateTime<Utc>) -> DateTime<Utc> {
+        let local = now.with_timezone(&tz).naive_local();
+        let today = local.date();
+        let due = match self {
+            DeliveryMode::Instant => return now,
+            DeliveryMode::Hourly => today.and_hms_opt(local.hour(), 0, 0),
+            DeliveryMode::Daily { hour } => today.and_hms_opt(hour, 0, 0)
+                .map(|due| if due > local { due - Duration::days(1) } else { due }),
+            DeliveryMode::Weekly { week_day, hour } => {
+                let days_back = (today.weekday().num_days_from_monday() + 7 - week_day) % 7;
+                (today - Duration::days(days_back as i64)).and_hms_opt(hour, 0, 0)
+                    .map(|due| if due > local { due - Duration::weeks(1) } else { due })
+            }
+        };
+        due.map_or(now, |due| to_utc(tz, due).min(now))
+    }
+}
+
+/// Hours without DMs, in the timezone of the user. The window may wrap around midnight,
+/// like 23 to 7. `end` is the first hour with DMs again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuietHours {
+    pub start: u32,
+    pub end: u32,
+    /// Whether the releases held back are sent as one message once the window ends.
+    pub batched: bool,
+}
+
+impl QuietHours {
+    /// Reads the `quiet_start`, `quiet_end` and `quiet_batched` columns of a user.
+    pub fn from_db(start: Option<i32>, end: Option<i32>, batched: bool) -> Option<QuietHours> {
+        match (start, end) {
+            (Some(start), Some(end)) if start != end =>
+                Some(QuietHours { start: start.clamp(0, 23) as u32, end: end.clamp(0, 23) as u32, batched }),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start < self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:00 to {:02}:00", self.start, self.end)
+    }
+}
+
+/// Everything that decides when a user gets their releases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeliverySettings {
+    pub mode: DeliveryMode,
+    pub quiet_hours: Option<QuietHours>,
+    /// Timezone of the quiet hours and digests. Without one the schedule timezone is used.
+    pub timezone: Option<Tz>,
+}
+
+impl DeliverySettings {
+    pub fn timezone(&self, default_tz: Tz) -> Tz {
+        self.timezone.unwrap_or(default_tz)
+    }
+
+    pub fn is_quiet(&self, default_tz: Tz, now: DateTime<Utc>) -> bool {
+        let hour = now.with_timezone(&self.timezone(default_tz)).hour();
+        self.quiet_hours.is_some_and(|q| q.contains(hour))
+    }
+
+    /// Whether a release should be sent right away instead of being queued.
+    pub fn is_instant(&self, default_tz: Tz, now: DateTime<Utc>) -> bool {
+        self.mode == DeliveryMode::Instant && !self.is_quiet(default_tz, now)
+    }
+
+    /// Whether queued releases are sent as one message.
+    fn is_batched(&self) -> bool {
+        self.mode != DeliveryMode::Instant || self.quiet_hours.is_none_or(|q| q.batched)
+    }
+}
+
+/// A local time that is skipped by a daylight saving change counts as the hour after.
+fn to_utc(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    tz.from_local_datetime(&local).earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+impl fmt::Display for DeliveryMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeliveryMode::Instant => write!(f, "instant, a message for every release"),
+            DeliveryMode::Hourly => write!(f, "hourly"),
+            DeliveryMode::Daily { hour } => write!(f, "daily at {:02}:00", hour),
+            DeliveryMode::Weekly { week_day, hour } =>
+                write!(f, "weekly on {} at {:02}:00", AirTime::weekdays()[week_day as usize], hour),
+        }
+    }
+}
+
+/// Sends the queued releases of every user whose digest is due and who is outside of their
+/// quiet hours, to every channel they turned on.
+pub async fn send_digests(config: &Config) {
+    let pending = match db::get_pending_digests().await {
+        Ok(pending) => pending,
+        Err(e) => return println!("DB Error fetching pending digests: {}", e),
+    };
+    let http = Http::new_with_token(&config.discord_token);
+    let now = Utc::now();
+    for (user_id, settings, oldest) in pending {
+        let until = settings.mode.last_delivery(settings.timezone(config.schedule_timezone), now);
+        if oldest > until || settings.is_quiet(config.schedule_timezone, now) { continue; }
+        let (releases, channels) = match (db::get_pending_notifications(user_id, until).await,
+                                          db::get_notification_channels(user_id).await) {
+            (Ok(releases), Ok(channels)) => (releases, channels),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("DB Error fetching digest of {}: {}", user_id, e);
+                continue;
+            }
+        };
+        let mut episodes = Vec::new();
+        for (show_name, release, note) in releases.iter() {
+            let link = links::redirect_link(config, &release.link).await.unwrap_or_else(|e| {
+                println!("Error saving download link of {}: {}", release.title, e);
+                show_url(&release.show_id)
+            });
+            episodes.push(Episode::new(show_name, release, link, note.clone()));
+        }
+        let notifications = if settings.is_batched() {
+            Notification::digests(episodes)
+        } else {
+            // one message each, like they would have come without quiet hours
+            episodes.into_iter().map(|episode| Notification::digest(vec![episode])).collect()
+        };
+        let channels: Vec<Channel> = channels.iter()
+            .map(|(channel, token)| notifiers::decrypted(config, channel, token))
+            .collect();
+        let channels: Vec<Channel> = notifiers::with_discord(user_id, channels).into_iter()
+            .filter(|c| c.enabled)
+            .collect();
+        for notification in notifications {
+            let mut delivered = Vec::new();
+            for channel in channels.iter() {
+                let sent = match notifiers::notifier(config, &http, channel) {
+                    Ok(notifier) => notifier.notify(&notification).await,
+                    Err(e) => Err(e),
+                };
+                match sent {
+                    Ok(()) => delivered.push(channel.kind.id()),
+                    Err(e) => println!("Couldn't send the digest to {} of {}: {}", channel.kind.name(), user_id, e),
+                }
+            }
+            // the rest is kept for the next hour when this got nowhere
+            if delivered.is_empty() {
+                METRICS.notification_failed();
+                break;
+            }
+            METRICS.notification_sent();
+            let guids: Vec<&str> = notification.episodes.iter().map(|e| e.guid.as_str()).collect();
+            if let Err(e) = db::delete_pending_notifications(user_id, &guids, &delivered).await {
+                println!("DB Error clearing digest of {}: {}", user_id, e);
+                break;
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test_delivery_mode_db() {
+    for mode in [DeliveryMode::Instant, DeliveryMode::Hourly, DeliveryMode::Daily { hour: 18 },
+        DeliveryMode::Weekly { week_day: 6, hour: 9 }] {
+        let (name, hour, week_day) = mode.to_db();
+        assert_eq!(DeliveryMode::from_db(name, hour, week_day), mode);
+    }
+    assert_eq!(DeliveryMode::from_db("nonsense", 3, 3), DeliveryMode::Instant);
+}
+
+#[test]
+fn test_last_delivery() {
+    let tz = chrono_tz::Europe::Berlin;
+    // a Wednesday, 14:20 in Berlin
+    let now = Utc.with_ymd_and_hms(2021, 7, 21, 12, 20, 0).unwrap();
+    let local = |d: u32, h: u32| tz.with_ymd_and_hms(2021, 7, d, h, 0, 0).unwrap().with_timezone(&Utc);
+    assert_eq!(DeliveryMode::Instant.last_delivery(tz, now), now);
+    assert_eq!(DeliveryMode::Hourly.last_delivery(tz, now), local(21, 14));
+    assert_eq!(DeliveryMode::Daily { hour: 9 }.last_delivery(tz, now), local(21, 9));
+    assert_eq!(DeliveryMode::Daily { hour: 18 }.last_delivery(tz, now), local(20, 18));
+    assert_eq!(DeliveryMode::Weekly { week_day: 2, hour: 9 }.last_delivery(tz, now), local(21, 9));
+    assert_eq!(DeliveryMode::Weekly { week_day: 2, hour: 18 }.last_delivery(tz, now), local(14, 18));
+    assert_eq!(DeliveryMode::Weekly { week_day: 6, hour: 18 }.last_delivery(tz, now), local(18, 18));
+    // 02:00 doesn't exist on this day in Berlin
+    let dst = Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap();
+    assert_eq!(DeliveryMode::Daily { hour: 2 }.last_delivery(tz, dst),
+               tz.with_ymd_and_hms(2021, 3, 28, 3, 0, 0).unwrap().with_timezone(&Utc));
+}
+
+#[test]
+fn test_quiet_hours() {
+    let night = QuietHours::from_db(Some(23), Some(7), true).unwrap();
+    assert!(night.contains(23) && night.contains(0) && night.contains(6));
+    assert!(!night.contains(7) && !night.contains(22));
+    let day = QuietHours { start: 9, end: 17, batched: false };
+    assert!(day.contains(9) && day.contains(16));
+    assert!(!day.contains(17) && !day.contains(8));
+    assert_eq!(QuietHours::from_db(Some(5), Some(5), true), None);
+    assert_eq!(QuietHours::from_db(None, Some(5), true), None);
+
+    // 23:30 in Berlin, 22:30 in London
+    let now = Utc.with_ymd_and_hms(2021, 7, 21, 21, 30, 0).unwrap();
+    let mut settings = DeliverySettings { mode: DeliveryMode::Instant, quiet_hours: Some(night), timezone: None };
+    assert!(settings.is_quiet(chrono_tz::Europe::Berlin, now));
+    assert!(!settings.is_instant(chrono_tz::Europe::Berlin, now));
+    settings.timezone = Some(chrono_tz::Europe::London);
+    assert!(!settings.is_quiet(chrono_tz::Europe::Berlin, now));
+    assert!(settings.is_instant(chrono_tz::Europe::Berlin, now));
+    settings.mode = DeliveryMode::Hourly;
+    assert!(!settings.is_instant(chrono_tz::Europe::Berlin, now));
+}
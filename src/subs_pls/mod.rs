@@ -2,4 +2,6 @@ pub mod release_parser;
 pub mod page_parser;
 pub mod db;
 pub mod notify;
-pub mod update_shows;
\ No newline at end of file
+pub mod update_shows;
+pub mod season;
+pub mod digest;
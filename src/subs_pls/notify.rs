@@ -1,23 +1,50 @@
 #![allow(clippy::needless_lifetimes)]
 
-use std::env;
+use std::sync::Arc;
 
+use chrono::Utc;
 use serenity::http::client::Http;
 
+use crate::config::Config;
+use crate::downloads;
+use crate::links;
+use crate::premiumize::{self, PremiumizeError, Transfer};
+use crate::secrets;
+use crate::shutdown::JobGuard;
+use crate::metrics::METRICS;
+use crate::notifiers::{self, Notification};
 use crate::subs_pls::db;
+use crate::subs_pls::db::RssIdDbCommunicator;
+use crate::subs_pls::digest::DeliverySettings;
 use crate::subs_pls::release_parser::{rss_category_to_show_id, FeedItem};
 use crate::subs_pls::release_parser::SubsPlsChannel;
 use crate::subs_pls::page_parser::Show;
 use serenity::model::id::UserId;
+use crate::watchlist_file::show_url;
+use crate::webhooks;
 
-extern crate html_escape;
-
-pub async fn notify_users(feed: &SubsPlsChannel, last_rss: &str) {
-    for item in &feed.items {
-        if item.guid == last_rss { break; }
-        let notification_data = get_notification_data(&*item.category, item).await;
+/// Notifies about every item newer than `last_rss`, oldest first. The guid is saved after
+/// each item, so a run that gets interrupted doesn't notify twice.
+pub async fn notify_users(config: &Arc<Config>, job: &JobGuard, feed: &SubsPlsChannel, last_rss: &str,
+                          rss_db_communicator: &RssIdDbCommunicator) {
+    let new_items: Vec<&FeedItem> = feed.items.iter()
+        .take_while(|item| item.guid != last_rss)
+        .collect();
+    for item in new_items.into_iter().rev() {
+        if let Some(show_id) = rss_category_to_show_id(&item.category) {
+            if let Err(e) = db::insert_release(&item.to_release(&show_id)).await {
+                println!("Error saving release {}: {}", item.title, e)
+            }
+        }
+        let notification_data = get_notification_data(config, &item.category, item).await;
         match notification_data {
-            Ok(data) => { send_notifications(data).await }
+            Ok(data) => {
+                queue_notifications(&data).await;
+                start_transfers(config, job, &data).await;
+                push_downloads(config, job, &data).await;
+                post_webhooks(job, &data).await;
+                send_notifications(config, data).await
+            }
             Err(e) => {
                 let t = match e {
                     NotificationError::DBShowError => "Couldn't fetch show. Probably never added?",
@@ -27,63 +54,198 @@ pub async fn notify_users(feed: &SubsPlsChannel, last_rss: &str) {
                 println!("Error notifying for {}: {}", item.title, t)
             }
         }
+        if let Err(e) = rss_db_communicator.save_guid(&item.guid).await {
+            println!("Error saving rss guid {}: {}", item.guid, e)
+        }
     };
 }
 
 struct NotificationData<'a> {
+    /// Users that get a DM right away.
     users: Vec<i64>,
+    /// Users that get the release with their next digest or after their quiet hours.
+    digest_users: Vec<i64>,
     show: Show,
     item: &'a FeedItem,
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum NotificationError {
     MappingShowIdError,
     DBUsersError,
     DBShowError
 }
 
-async fn get_notification_data<'a>(show_category: &str, item: &'a FeedItem) -> Result<NotificationData<'a>, NotificationError> {
-    let show_id = rss_category_to_show_id(show_category).ok_or_else(|| NotificationError::MappingShowIdError)?;
-    let users = db::get_user_ids_for_show_id(&*show_id).await.map_err(|_| NotificationError::DBUsersError)?;
-    let show = db::get_show_from_show_id(&*show_id).await.map_err(|_| NotificationError::DBShowError)?;
-    Ok(NotificationData { users, show, item })
+async fn get_notification_data<'a>(config: &Config, show_category: &str, item: &'a FeedItem) -> Result<NotificationData<'a>, NotificationError> {
+    let show_id = rss_category_to_show_id(show_category).ok_or(NotificationError::MappingShowIdError)?;
+    let watchers = db::get_delivery_settings_for_show_id(&show_id).await
+        .map_err(|_| NotificationError::DBUsersError)?;
+    let now = Utc::now();
+    let (users, digest_users) = watchers.into_iter()
+        .partition::<Vec<(i64, DeliverySettings)>, _>(|(_, s)| s.is_instant(config.schedule_timezone, now));
+    let (users, digest_users) = (users.into_iter().map(|(id, _)| id).collect(),
+                                 digest_users.into_iter().map(|(id, _)| id).collect());
+    let show = db::get_show_from_show_id(&show_id).await.map_err(|_| NotificationError::DBShowError)?;
+    Ok(NotificationData { users, digest_users, show, item })
 }
 
-async fn send_notifications<'a>(notification_data: NotificationData<'a>) {
-    let http: Http = Http::new_with_token(&env::var("DISCORD_TOKEN").expect("token"));
-    for &user_id in notification_data.users.iter() {
-        let user_res = UserId::from(user_id as u64).to_user(&http).await;
-        match user_res {
-            Ok(user) => {
-                let d = user.dm(&http, |m| {
-                    m.content("");
-                    m.embed(|e| {
-                        e.title(&notification_data.item.title);
-                        e.thumbnail(&notification_data.show.image_url);
-                        e.description(&notification_data.show.synopsis);
-                        e.field(format!("Download - {}", &notification_data.item.file_size),
-                                format!("[🧲](https://yukino.onrender.com/?r={})", notification_data.item.link), true);
-                        e.field("Show Information",
-                                format!("[🌐](https://subsplease.org/shows/{}/) [Ⓜ](https://myanimelist.net/search/all?q={}&cat=all)",
-                                        notification_data.show.id,
-                                        html_escape::encode_text(&notification_data.show.id))
-                                , true);
+/// Starts a Premiumize transfer for every watcher with an api key, each in the background
+/// as part of `job`. The outcome is sent to the ones that get DMs right now and attached
+/// to the queued release of the others, so it shows up in their digest.
+async fn start_transfers<'a>(config: &Arc<Config>, job: &JobGuard, notification_data: &NotificationData<'a>) {
+    if config.encryption_key.is_none() { return; }
+    let keys = match db::get_premiumize_keys_for_show_id(&notification_data.show.id).await {
+        Ok(keys) => keys,
+        Err(e) => return println!("Error fetching premiumize keys: {}", e),
+    };
+    for (user_id, encrypted) in keys {
+        let instant = notification_data.users.contains(&user_id);
+        let (title, link, guid) = (notification_data.item.title.clone(), notification_data.item.link.clone(),
+                                   notification_data.item.guid.clone());
+        let (config, job) = (config.clone(), job.clone());
+        tokio::spawn(async move {
+            let _job = job;
+            let transfer = match config.encryption_key.as_ref().and_then(|key| secrets::decrypt(key, &encrypted)) {
+                Some(api_key) => premiumize::create_transfer(&config.premiumize, &api_key, &link).await,
+                None => Err(PremiumizeError::Rejected("your saved api key can't be read, please add it again".to_string())),
+            };
+            match &transfer {
+                Ok(_) | Err(PremiumizeError::Rejected(_)) => {}
+                Err(e) => println!("Error creating premiumize transfer for {}: {:?}", user_id, e),
+            }
+            if instant {
+                send_dm(&config, user_id, &transfer_result(&transfer, &title)).await;
+            } else if let Err(e) = db::set_pending_note(user_id, &guid, &transfer_result(&transfer, "it")).await {
+                println!("Error saving transfer result of {}: {}", user_id, e);
+            }
+        });
+    }
+}
 
-                        e
-                    });
+/// What happened to the Premiumize transfer of `title`, as told to the user.
+fn transfer_result(transfer: &Result<Transfer, PremiumizeError>, title: &str) -> String {
+    match transfer {
+        Ok(_) => format!("Premiumize is downloading {}.", title),
+        Err(PremiumizeError::Rejected(reason)) => format!("Premiumize didn't take {}: {}", title, reason),
+        Err(_) => format!("Couldn't reach Premiumize to download {}.", title),
+    }
+}
 
-                    m
-                }).await;
-                match d {
-                    Ok(_) => {},
-                    Err(r) => println!("{}", r.to_string())
+async fn send_dm(config: &Config, user_id: i64, text: &str) {
+    let http: Http = Http::new_with_token(&config.discord_token);
+    let sent = match UserId::from(user_id as u64).to_user(&http).await {
+        Ok(user) => user.dm(&http, |m| m.content(text)).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        println!("Error sending DM to {}: {}", user_id, e);
+    }
+}
+
+/// Sends the release to the download client of every watcher that has one, each in the
+/// background as part of `job`. Failures are told the ones that get DMs right now.
+async fn push_downloads<'a>(config: &Arc<Config>, job: &JobGuard, notification_data: &NotificationData<'a>) {
+    let clients = match db::get_download_clients_for_show_id(&notification_data.show.id).await {
+        Ok(clients) => clients,
+        Err(e) => return println!("Error fetching download clients: {}", e),
+    };
+    for (user_id, mut settings, password) in clients {
+        settings.password = match (password, &config.encryption_key) {
+            (Some(encrypted), Some(key)) => secrets::decrypt(key, &encrypted),
+            _ => None,
+        };
+        let instant = notification_data.users.contains(&user_id);
+        let (title, link) = (notification_data.item.title.clone(), notification_data.item.link.clone());
+        let (config, job) = (config.clone(), job.clone());
+        tokio::spawn(async move {
+            let _job = job;
+            let kind = settings.kind;
+            if let Err(e) = downloads::sink(settings).add(&link).await {
+                println!("Error sending {} to the download client of {}: {:?}", title, user_id, e);
+                if instant {
+                    send_dm(&config, user_id, &format!("Couldn't send {} to {}: {}", title, kind.name(), e)).await;
                 }
             }
-            Err(e) => {
-                println!("Couldn't find user {} to notify for {}: {}",
-                         user_id, &notification_data.show.name, e.to_string())
+        });
+    }
+}
+
+/// Posts the release to the webhook of every watcher that has one. Each delivery retries in
+/// the background, so a slow webhook doesn't hold up the others. The deliveries are part of
+/// `job`, so a shutdown waits for them.
+async fn post_webhooks<'a>(job: &JobGuard, notification_data: &NotificationData<'a>) {
+    let hooks = match db::get_webhooks_for_show_id(&notification_data.show.id).await {
+        Ok(hooks) => hooks,
+        Err(e) => return println!("Error fetching webhooks: {}", e),
+    };
+    if hooks.is_empty() { return; }
+    let body = webhooks::payload("release", &notification_data.show, notification_data.item);
+    for (user_id, url, secret) in hooks {
+        let body = body.clone();
+        let title = notification_data.item.title.clone();
+        let job = job.clone();
+        tokio::spawn(async move {
+            let _job = job;
+            let sent = webhooks::send_with_retries(&url, &secret, "release", &body, &webhooks::RETRY_DELAYS).await;
+            if let Err(e) = sent {
+                println!("Error posting {} to the webhook of {}: {}", title, user_id, e);
+            }
+        });
+    }
+}
+
+async fn queue_notifications<'a>(notification_data: &NotificationData<'a>) {
+    for &user_id in notification_data.digest_users.iter() {
+        if let Err(e) = db::queue_notification(user_id, &notification_data.item.guid).await {
+            println!("Error queueing {} for {}: {}", notification_data.item.title, user_id, e)
+        }
+    }
+}
+
+/// Sends the release to every channel the users turned on, which is their Discord DMs
+/// unless they changed it.
+async fn send_notifications<'a>(config: &Config, notification_data: NotificationData<'a>) {
+    if notification_data.users.is_empty() { return; }
+    let linked = db::get_notification_channels_for_show_id(&notification_data.show.id).await
+        .unwrap_or_else(|e| {
+            println!("Error fetching notification channels, sending DMs only: {}", e);
+            Vec::new()
+        });
+    let http: Http = Http::new_with_token(&config.discord_token);
+    let download = download_link(config, &notification_data.show, &notification_data.item.link).await;
+    let notification = Notification::release(&notification_data.show, notification_data.item, download);
+    for &user_id in notification_data.users.iter() {
+        let channels = linked.iter()
+            .filter(|(id, _, _)| *id == user_id)
+            .map(|(_, channel, token)| notifiers::decrypted(config, channel, token))
+            .collect();
+        for channel in notifiers::with_discord(user_id, channels).iter().filter(|c| c.enabled) {
+            let sent = match notifiers::notifier(config, &http, channel) {
+                Ok(notifier) => notifier.notify(&notification).await,
+                Err(e) => Err(e),
+            };
+            match sent {
+                Ok(()) => {
+                    METRICS.notification_sent();
+                    if let Err(e) = db::log_notifications(user_id, &[&notification_data.item.guid], channel.kind.id()).await {
+                        println!("Error logging notification of {}: {}", user_id, e);
+                    }
+                }
+                Err(e) => {
+                    METRICS.notification_failed();
+                    println!("Couldn't send {} to {} of {}: {}",
+                             notification.title, channel.kind.name(), user_id, e)
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// A redirect to the release, or the show page when the link couldn't be saved.
+async fn download_link(config: &Config, show: &Show, link: &str) -> String {
+    links::redirect_link(config, link).await.unwrap_or_else(|e| {
+        println!("Error saving download link of {}: {}", show.name, e);
+        show_url(&show.id)
+    })
+}
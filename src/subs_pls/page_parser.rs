@@ -1,9 +1,13 @@
 use regex::Regex;
 use reqwest;
+use crate::config::Config;
 use crate::subs_pls::db;
+use crate::trackers::resolve_external_ids;
+use crate::watchlist_file::SHOW_URL_PREFIX;
 use serde::{Deserialize, Serialize};
 use easy_scraper::Pattern;
 use std::collections::BTreeMap;
+use std::fmt;
 
 #[derive(Clone)]
 pub struct Show {
@@ -12,6 +16,55 @@ pub struct Show {
     pub image_url: String,
     pub synopsis: String,
     pub air_time: AirTime,
+    pub external_ids: ExternalIds,
+    /// Season the show first showed up in the schedule, like "Fall 2021".
+    pub season: Option<String>,
+}
+
+/// Ids of the show on anime list sites.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ExternalIds {
+    pub mal: Option<i64>,
+    pub anilist: Option<i64>,
+    pub kitsu: Option<i64>,
+    /// Set once a lookup found the show or all three ids were set by hand.
+    pub resolved: bool,
+}
+
+impl Show {
+    /// Markdown links to subsplease and the list sites. Without a MyAnimeList id it
+    /// falls back to a search for the name.
+    pub fn links(&self) -> String {
+        let mal = match self.external_ids.mal {
+            Some(id) => format!("https://myanimelist.net/anime/{}", id),
+            None => reqwest::Url::parse_with_params("https://myanimelist.net/search/all",
+                                                    &[("q", self.name.as_str()), ("cat", "all")])
+                .map(|u| u.to_string())
+                .unwrap_or_default(),
+        };
+        let mut links = format!("[🌐](https://subsplease.org/shows/{}/) [Ⓜ]({})", self.id, mal);
+        if let Some(id) = self.external_ids.anilist {
+            links.push_str(&format!(" [Ⓐ](https://anilist.co/anime/{})", id));
+        }
+        if let Some(id) = self.external_ids.kitsu {
+            links.push_str(&format!(" [Ⓚ](https://kitsu.io/anime/{})", id));
+        }
+        links
+    }
+}
+
+/// A show with only an id, a name and an air time, for tests.
+#[cfg(test)]
+pub fn test_show(id: &str, name: &str, air_time: AirTime) -> Show {
+    Show {
+        id: id.to_string(),
+        name: name.to_string(),
+        image_url: String::new(),
+        synopsis: String::new(),
+        air_time,
+        external_ids: ExternalIds::default(),
+        season: None,
+    }
 }
 
 #[derive(Clone)]
@@ -22,11 +75,14 @@ pub struct AirTime {
     pub est_m: i32,
 }
 
-impl AirTime {
-    pub fn to_string(&self) -> String {
-        if !self.is_airing { return "".to_string(); };
-        format!("{}, {}", self.to_weekday_string(), self.to_clock_stamp())
+impl fmt::Display for AirTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.is_airing { return Ok(()); };
+        write!(f, "{}, {}", self.to_weekday_string(), self.to_clock_stamp())
     }
+}
+
+impl AirTime {
     pub fn to_clock_stamp(&self) -> String {
         if !self.is_airing { return "".to_string(); };
         format!("{:02}:{:02}", self.est_h, self.est_m)
@@ -35,7 +91,7 @@ impl AirTime {
     pub fn to_weekday_string(&self) -> String {
         if !self.is_airing { return "".to_string(); };
         let weekdays = AirTime::weekdays();
-        format!("{}", weekdays[self.est_week_day as usize])
+        weekdays[self.est_week_day as usize].to_string()
     }
 
     pub fn weekdays() -> [&'static str; 7] {
@@ -45,17 +101,26 @@ impl AirTime {
 
 
 #[derive(Serialize, Deserialize)]
-struct ScheduleShow {
-    title: String,
-    page: String,
-    image_url: String,
-    time: String,
+pub struct ScheduleShow {
+    pub title: String,
+    pub page: String,
+    pub image_url: String,
+    pub time: String,
 }
 
 #[derive(Serialize, Deserialize)]
-struct ScheduleContainer {
-    tz: String,
-    schedule: BTreeMap<String, Vec<ScheduleShow>>,
+pub struct ScheduleContainer {
+    pub tz: String,
+    pub schedule: BTreeMap<String, Vec<ScheduleShow>>,
+}
+
+/// Fetches the season schedule of subsplease with times in the configured timezone.
+pub async fn fetch_schedule(config: &Config) -> Option<ScheduleContainer> {
+    let schedule_data = reqwest::Client::new()
+        .get(&config.feeds.schedule_url)
+        .query(&[("tz", config.schedule_timezone.name())])
+        .send().await.ok()?.text().await.ok()?;
+    serde_json::from_str(&schedule_data).ok()
 }
 
 #[derive(Debug, PartialEq)]
@@ -80,6 +145,13 @@ fn test_is_valid_url() {
     assert!(!is_valid_url("https://subsplease.org/shows//")); //empty
 }
 
+#[test]
+fn test_show_id_from_url() {
+    assert_eq!(show_id_from_url("https://subsplease.org/shows/yami-shibai-9/"), Some("yami-shibai-9"));
+    assert_eq!(show_id_from_url("https://subsplease.org/shows/yami-shibai-9/ä"), None);
+    assert_eq!(show_id_from_url("https://google.com/"), None);
+}
+
 
 pub fn is_valid_url(url: &str) -> bool {
     lazy_static::lazy_static! {
@@ -88,23 +160,30 @@ pub fn is_valid_url(url: &str) -> bool {
     CHECK_URL.is_match(url)
 }
 
+/// The id in a show url, like "one-piece" for https://subsplease.org/shows/one-piece/.
+pub fn show_id_from_url(url: &str) -> Option<&str> {
+    url.strip_prefix(SHOW_URL_PREFIX)?.strip_suffix('/')
+}
+
 
 /// Here a user can add a Show to its watchlist. If the show is not in the db,
-/// an entry will be generated
-pub async fn add_show(user_id: i64, identifier: &str) -> Result<Show, AddFailure> {
+/// an entry will be generated. Without `resolve_ids` its list site ids are left to the
+/// daily show update, which spaces the lookups out, for imports of many shows.
+pub async fn add_show(config: &Config, user_id: i64, identifier: &str, resolve_ids: bool) -> Result<Show, AddFailure> {
     let is_url_ident = is_valid_url(identifier);
     if is_url_ident {
-        let show_id = &identifier[29..identifier.len() - 1];
+        let show_id = show_id_from_url(identifier).ok_or(AddFailure::InvalidUrl)?;
         if !db::is_show_saved(show_id).await.map_err(|_| AddFailure::DatabaseError)? {
-            let show = scrape_show(show_id)
-                .await.ok_or_else(|| AddFailure::ShowNotAvailable)?;
-            db::insert_show(&show).await.map_err(|_| AddFailure::DatabaseError)?;
-            let db_interaction = add_user_show(user_id, show_id).await;
-            db_interaction.map(|_| show)
+            let mut show = scrape_show(config, show_id)
+                .await.ok_or(AddFailure::ShowNotAvailable)?;
+            if resolve_ids {
+                show.external_ids = resolve_external_ids(&config.trackers, &show.name).await;
+            }
+            show.season = db::get_season_of_show(show_id).await.map_err(|_| AddFailure::DatabaseError)?;
+            add_user_show(user_id, show_id, Some(&show)).await.map(|_| show)
         } else {
             let show = db::get_show_from_show_id(show_id).await.map_err(|_| AddFailure::DatabaseError)?;
-            let db_interaction = add_user_show(user_id, show_id).await;
-            db_interaction.map(|_| show)
+            add_user_show(user_id, show_id, None).await.map(|_| show)
         }
     } else if !is_url_ident && identifier.contains("http") {
         Err(AddFailure::InvalidUrl)
@@ -113,42 +192,35 @@ pub async fn add_show(user_id: i64, identifier: &str) -> Result<Show, AddFailure
     }
 }
 
-async fn add_user_show(user_id: i64, show_id: &str) -> Result<(), AddFailure> {
-    let is_already_added = db::does_user_show_exist(user_id, show_id).await
+async fn add_user_show(user_id: i64, show_id: &str, new_show: Option<&Show>) -> Result<(), AddFailure> {
+    let added = db::insert_user_show(user_id, show_id, new_show).await
         .map_err(|_| AddFailure::DatabaseError)?;
-    if is_already_added {
+    if !added {
         return Err(AddFailure::AlreadyAdded);
     }
-    db::insert_user_show(user_id, show_id).await
-        .map_err(|_| AddFailure::DatabaseError)?;
     Ok(())
 }
 
-pub async fn scrape_show(show_id: &str) -> Option<Show> {
+pub async fn scrape_show(config: &Config, show_id: &str) -> Option<Show> {
     let weekdays = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
     let page_data = reqwest::get(format!("https://subsplease.org/shows/{}/", show_id))
         .await.ok()?.text().await.ok()?;
     let (image_url, synopsis, name) = get_image_synopsis_and_name(&page_data).await?;
-    let schedule_data = reqwest::get("https://subsplease.org/api/?f=schedule&tz=Europe/Berlin")
-        .await.ok()?.text().await.ok()?;
-    let schedule_c: ScheduleContainer = serde_json::from_str(&schedule_data).ok()?;
+    let schedule_c = fetch_schedule(config).await?;
     let (mut is_airing, mut est_week_day, mut est_h, mut est_m) = (false, -1, -1, -1);
     for (i, &day) in weekdays.iter().enumerate() {
         let shows_today = schedule_c.schedule.get(day)?;
         let op_show = shows_today.iter().find(|&s| s.page == show_id);
-        match op_show {
-            Some(s) => {
-                is_airing = true;
-                est_week_day = i as i32;
-                let parse_time: Vec<i32> = s.time
-                    .split(":")
-                    .map(|p| p.parse().unwrap_or_default())
-                    .collect();
-                est_h = parse_time[0];
-                est_m = parse_time[1];
-                break;
-            }
-            None => {}
+        if let Some(s) = op_show {
+            is_airing = true;
+            est_week_day = i as i32;
+            let parse_time: Vec<i32> = s.time
+                .split(':')
+                .map(|p| p.parse().unwrap_or_default())
+                .collect();
+            est_h = parse_time[0];
+            est_m = parse_time[1];
+            break;
         };
     };
     Some(Show {
@@ -157,6 +229,8 @@ pub async fn scrape_show(show_id: &str) -> Option<Show> {
         image_url,
         synopsis,
         air_time: AirTime { is_airing, est_week_day, est_h, est_m },
+        external_ids: ExternalIds::default(),
+        season: None,
     })
 }
 
@@ -167,16 +241,16 @@ async fn get_image_synopsis_and_name(data: &str) -> Option<(String, String, Stri
                         <p>{{synopsis}}</p>
                      </div>"##).ok()?;
     let name_pattern = Pattern::new(r##"<h1 class="entry-title">{{name}}</h1>"##).ok()?;
-    let image_url = im_pattern.matches(data).get(0)?.get("url")?.to_string();
-    let synopsis = synopsis_pattern.matches(data).get(0)?.get("synopsis")?.to_string();
-    let name = name_pattern.matches(data).get(0)?.get("name")?.to_string();
+    let image_url = im_pattern.matches(data).first()?.get("url")?.to_string();
+    let synopsis = synopsis_pattern.matches(data).first()?.get("synopsis")?.to_string();
+    let name = name_pattern.matches(data).first()?.get("name")?.to_string();
     Some((format!("https://subsplease.org{}", image_url), synopsis, name))
 }
 
 
 #[tokio::test]
 async fn test_show_scrape() {
-    let one_piece = scrape_show("one-piece").await.unwrap();
+    let one_piece = scrape_show(&crate::config::test_config(), "one-piece").await.unwrap();
     assert!(one_piece.air_time.is_airing);
     assert_eq!("One Piece", one_piece.name);
     assert!(one_piece.air_time.est_m < 60);
@@ -193,3 +267,15 @@ async fn test_image_synopsis_and_name() {
     assert_eq!(&synopsis[..10], "Natsuki Su");
     assert_eq!(name, "Re Zero kara Hajimeru Isekai Seikatsu");
 }
+
+
+#[test]
+fn test_show_links() {
+    let mut show = test_show("one-piece", "One Piece & Friends",
+                             AirTime { is_airing: false, est_week_day: -1, est_h: -1, est_m: -1 });
+    assert_eq!(show.links(), "[🌐](https://subsplease.org/shows/one-piece/) \
+        [Ⓜ](https://myanimelist.net/search/all?q=One+Piece+%26+Friends&cat=all)");
+    show.external_ids = ExternalIds { mal: Some(21), anilist: Some(21), kitsu: Some(12), resolved: true };
+    assert_eq!(show.links(), "[🌐](https://subsplease.org/shows/one-piece/) [Ⓜ](https://myanimelist.net/anime/21) \
+        [Ⓐ](https://anilist.co/anime/21) [Ⓚ](https://kitsu.io/anime/12)");
+}
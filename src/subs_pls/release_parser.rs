@@ -1,11 +1,10 @@
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use roxmltree::Descendants;
 use std::fmt;
 
 
 pub struct SubsPlsChannel {
-    pub title: String,
-    pub description: String,
     pub items: Vec<FeedItem>,
 }
 
@@ -13,8 +12,6 @@ pub struct SubsPlsChannel {
 #[derive(Debug, Eq, PartialEq)]
 pub enum RssParsingError {
     InvalidRssFeed,
-    RssTitleNotFound,
-    RssDescriptionNotFound,
     ItemTitleNotFound,
     ItemLinkNotFound,
     ItemGuidNotFound,
@@ -44,27 +41,20 @@ impl SubsPlsChannel {
         for i in content.descendants()
             .filter(|n| n.tag_name().name() == "item") {
             let title = get_text_in_node_by_name(i.descendants(), "title")
-                .ok_or_else(|| RssParsingError::ItemTitleNotFound)?;
+                .ok_or(RssParsingError::ItemTitleNotFound)?;
             let link = get_text_in_node_by_name(i.descendants(), "link")
-                .ok_or_else(|| RssParsingError::ItemLinkNotFound)?;
+                .ok_or(RssParsingError::ItemLinkNotFound)?;
             let guid = get_text_in_node_by_name(i.descendants(), "guid")
-                .ok_or_else(|| RssParsingError::ItemGuidNotFound)?;
+                .ok_or(RssParsingError::ItemGuidNotFound)?;
             let pub_date = get_text_in_node_by_name(i.descendants(), "pubDate")
-                .ok_or_else(|| RssParsingError::ItemPubDateNotFound)?;
+                .ok_or(RssParsingError::ItemPubDateNotFound)?;
             let category = get_text_in_node_by_name(i.descendants(), "category")
-                .ok_or_else(|| RssParsingError::ItemCategoryNotFound)?;
+                .ok_or(RssParsingError::ItemCategoryNotFound)?;
             let file_size = get_text_in_node_by_name(i.descendants(), "size")
-                .ok_or_else(|| RssParsingError::ItemSizeNotFound)?;
+                .ok_or(RssParsingError::ItemSizeNotFound)?;
             items.push(FeedItem { title, link, guid, pub_date, category, file_size });
         }
-        Ok(SubsPlsChannel {
-            title: content.descendants().find(|n| n.tag_name().name() == "title")
-                .ok_or_else(|| RssParsingError::RssTitleNotFound)?.text()
-                .ok_or_else(|| RssParsingError::RssTitleNotFound)?.to_string(),
-            description: get_text_in_node_by_name(content.descendants(), "description")
-                .ok_or_else(|| RssParsingError::RssDescriptionNotFound)?,
-            items,
-        })
+        Ok(SubsPlsChannel { items })
     }
 }
 
@@ -78,6 +68,43 @@ pub struct FeedItem {
     pub file_size: String,
 }
 
+/// A feed item as it is kept in the database.
+pub struct Release {
+    pub guid: String,
+    pub show_id: String,
+    pub title: String,
+    pub episode: Option<String>,
+    pub link: String,
+    pub file_size: String,
+    pub released_at: DateTime<Utc>,
+}
+
+impl FeedItem {
+    /// The episode number from titles like `[SubsPlease] Kingdom S3 - 14 (1080p) [E0FDE25E].mkv`.
+    /// Batches come as a range like `01-12`.
+    pub fn episode(&self) -> Option<String> {
+        lazy_static::lazy_static! {
+            static ref EPISODE: Regex = Regex::new(r" - (\d+(?:\.\d+)?(?:v\d+)?|\(\d+-\d+\)) \(").unwrap();
+        }
+        let episode = EPISODE.captures_iter(&self.title).last()?.get(1)?.as_str();
+        Some(episode.trim_matches(|c| c == '(' || c == ')').to_string())
+    }
+
+    pub fn to_release(&self, show_id: &str) -> Release {
+        Release {
+            guid: self.guid.to_string(),
+            show_id: show_id.to_string(),
+            title: self.title.to_string(),
+            episode: self.episode(),
+            link: self.link.to_string(),
+            file_size: self.file_size.to_string(),
+            released_at: DateTime::parse_from_rfc2822(&self.pub_date)
+                .map(|d| d.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+        }
+    }
+}
+
 
 pub fn rss_category_to_show_id(rss_category: &str) -> Option<String> {
     lazy_static::lazy_static! {
@@ -166,24 +193,12 @@ fn parse_test() {
             </rss>
         "##;
     let correct_feed = SubsPlsChannel::from_xml(ex_1).unwrap();
-    assert_eq!(correct_feed.title, "SubsPlease RSS");
-    assert_eq!(correct_feed.description, "RSS feed for SubsPlease releases (1080p)");
     assert_eq!(correct_feed.items.len(), 2);
     assert_eq!(correct_feed.items[0].category, "Yami Shibai 9 - 1080");
     assert_eq!(correct_feed.items[1].file_size, "1.09 GiB");
-
-    let ex_2 = r##"
-            <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:subsplease="https://subsplease.org/rss">
-                <channel>
-                    <description>RSS feed for SubsPlease releases (1080p)</description>
-                    <link>https://subsplease.org</link>
-                    <atom:link href="http://subsplease.org/rss" rel="self" type="application/rss+xml"/>
-                </channel>
-            </rss>
-        "##;
-
-    let title_missing = SubsPlsChannel::from_xml(ex_2);
-    assert_eq!(title_missing.err().unwrap(), RssParsingError::RssTitleNotFound);
+    let release = correct_feed.items[1].to_release("kingdom-s3");
+    assert_eq!(release.episode, Some("14".to_string()));
+    assert_eq!(release.released_at.to_rfc3339(), "2021-07-18T18:58:32+00:00");
 
     let ex_3 = r##"
             <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:subsplease="https://subsplease.org/rss">
@@ -206,3 +221,22 @@ fn parse_test() {
     let item_missing_link = SubsPlsChannel::from_xml(ex_3);
     assert_eq!(item_missing_link.err().unwrap(), RssParsingError::ItemLinkNotFound);
 }
+
+
+#[test]
+fn episode_test() {
+    let item = |title: &str| FeedItem {
+        title: title.to_string(),
+        link: String::new(),
+        guid: String::new(),
+        pub_date: String::new(),
+        category: String::new(),
+        file_size: String::new(),
+    };
+    assert_eq!(item("[SubsPlease] Yami Shibai 9 - 02 (1080p) [C68BD8C2].mkv").episode(), Some("02".to_string()));
+    assert_eq!(item("[SubsPlease] Re Zero - Hyouketsu - 12.5 (1080p) [AB12CD34].mkv").episode(), Some("12.5".to_string()));
+    assert_eq!(item("[SubsPlease] One Piece - 1000v2 (1080p) [AB12CD34].mkv").episode(), Some("1000v2".to_string()));
+    assert_eq!(item("[SubsPlease] Kingdom S3 (01-26) (1080p) [Batch]").episode(), None);
+    assert_eq!(item("[SubsPlease] Kingdom S3 - (01-26) (1080p) [Batch]").episode(), Some("01-26".to_string()));
+    assert_eq!(item("[SubsPlease] Some Movie (1080p) [AB12CD34].mkv").episode(), None);
+}
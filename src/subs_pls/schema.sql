@@ -0,0 +1,157 @@
+-- Applied on every start, so every statement has to be idempotent.
+
+create table if not exists users (
+    id bigint primary key
+);
+
+create table if not exists shows (
+    id text primary key,
+    name text not null,
+    image_url text not null,
+    synopsis text not null,
+    is_airing boolean not null,
+    est_week_day integer not null,
+    est_h integer not null,
+    est_m integer not null
+);
+
+create table if not exists user_shows (
+    user_id bigint not null,
+    show_id text not null
+);
+
+create table if not exists program_state (
+    id text primary key,
+    value text not null
+);
+
+insert into program_state (id, value)
+select 'last_rss_guid', '' where not exists (select 1 from program_state where id = 'last_rss_guid');
+
+-- external ids, resolved once per show
+alter table shows add column if not exists mal_id bigint;
+alter table shows add column if not exists anilist_id bigint;
+alter table shows add column if not exists kitsu_id bigint;
+alter table shows add column if not exists ids_resolved boolean not null default false;
+
+-- every episode seen in the rss feed
+create table if not exists releases (
+    guid text primary key,
+    show_id text not null,
+    title text not null,
+    episode text,
+    link text not null,
+    file_size text not null,
+    released_at timestamptz not null default now()
+);
+create index if not exists releases_show_id_released_at on releases (show_id, released_at);
+
+-- when a show was put on a watchlist
+alter table user_shows add column if not exists added_at timestamptz not null default now();
+
+-- secret tokens of the calendar feed urls
+create table if not exists calendar_tokens (
+    user_id bigint primary key,
+    token text not null unique
+);
+
+-- every show seen in the schedule, with the season it first showed up in
+create table if not exists season_shows (
+    show_id text primary key,
+    title text not null,
+    season text not null,
+    first_seen timestamptz not null default now(),
+    announced boolean not null default false
+);
+alter table shows add column if not exists season text;
+alter table users add column if not exists season_digest boolean not null default false;
+
+-- how users want release notifications, and the releases waiting for their digest
+alter table users add column if not exists delivery_mode text not null default 'instant';
+alter table users add column if not exists delivery_hour integer not null default 0;
+alter table users add column if not exists delivery_week_day integer not null default 0;
+create table if not exists pending_notifications (
+    user_id bigint not null,
+    guid text not null,
+    queued_at timestamptz not null default now(),
+    primary key (user_id, guid)
+);
+-- the outcome of a Premiumize transfer that was started while the release was queued
+alter table pending_notifications add column if not exists note text;
+
+-- hours without DMs, in the timezone of the user
+alter table users add column if not exists quiet_start integer;
+alter table users add column if not exists quiet_end integer;
+alter table users add column if not exists quiet_batched boolean not null default true;
+alter table users add column if not exists timezone text;
+
+-- short tokens of the /r/ download redirects
+create table if not exists links (
+    token text primary key,
+    url text not null unique,
+    expires_at timestamptz not null
+);
+
+-- premiumize api keys of users, encrypted with encryption_key
+create table if not exists premiumize_keys (
+    user_id bigint primary key,
+    api_key bytea not null
+);
+
+-- download clients of users, the password is encrypted with encryption_key
+create table if not exists download_clients (
+    user_id bigint primary key,
+    kind text not null,
+    url text not null,
+    username text,
+    password bytea,
+    category text,
+    save_path text
+);
+
+-- webhooks new releases are posted to, signed with secret
+create table if not exists webhooks (
+    user_id bigint primary key,
+    url text not null,
+    secret text not null
+);
+
+-- where users get releases besides Discord, the token of ntfy and gotify is encrypted with
+-- encryption_key. Discord only has a row once it was turned off or on.
+create table if not exists notification_channels (
+    user_id bigint not null,
+    kind text not null,
+    target text not null,
+    token bytea,
+    enabled boolean not null default true,
+    primary key (user_id, kind)
+);
+-- set until the user sent back the code the channel got
+alter table notification_channels add column if not exists confirmation_code text;
+
+-- when users registered, unknown for the ones from before
+alter table users add column if not exists registered_at timestamptz;
+alter table users alter column registered_at set default now();
+
+-- what was sent to whom, kept for a while so users can see it with mydata
+create table if not exists notification_history (
+    user_id bigint not null,
+    guid text not null,
+    channel text not null,
+    sent_at timestamptz not null default now()
+);
+create index if not exists notification_history_user_id on notification_history (user_id);
+
+-- watchlists of users who unregistered, brought back when they register again in time
+create table if not exists unregistered_watchlists (
+    user_id bigint not null,
+    show_id text not null,
+    added_at timestamptz not null,
+    unregistered_at timestamptz not null default now(),
+    primary key (user_id, show_id)
+);
+
+-- a show is on a watchlist once, duplicates from before the key keep their oldest row
+delete from user_shows a using user_shows b
+where a.user_id = b.user_id and a.show_id = b.show_id and (a.added_at, a.ctid) > (b.added_at, b.ctid);
+create unique index if not exists user_shows_user_id_show_id on user_shows (user_id, show_id);
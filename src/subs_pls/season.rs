@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use serenity::builder::CreateEmbed;
+use serenity::http::client::Http;
+use serenity::model::id::UserId;
+
+use crate::config::Config;
+use crate::message_handler::embed::EmbedPages;
+use crate::subs_pls::db;
+use crate::subs_pls::page_parser::fetch_schedule;
+use crate::watchlist_file::show_url;
+
+/// Anime seasons start in January, April, July and October, e.g. "Summer 2021".
+pub fn season_of(date: NaiveDate) -> String {
+    let name = match date.month() {
+        1..=3 => "Winter",
+        4..=6 => "Spring",
+        7..=9 => "Summer",
+        _ => "Fall",
+    };
+    format!("{} {}", name, date.year())
+}
+
+/// Shows tend to premiere a week or two before their season starts.
+const EARLY_PREMIERE_DAYS: i64 = 14;
+
+/// The season a show first seen on `date` belongs to. Shows that show up in the last two
+/// weeks of a season count towards the next one.
+pub fn premiere_season(date: NaiveDate) -> String {
+    season_of(date + Duration::days(EARLY_PREMIERE_DAYS))
+}
+
+/// The season new shows are tagged with today.
+pub fn current_season(config: &Config) -> String {
+    premiere_season(Utc::now().with_timezone(&config.schedule_timezone).date_naive())
+}
+
+/// Remembers shows that are in the schedule for the first time, tagged with the current
+/// season. The very first run only fills the table, so it doesn't announce a whole season.
+pub async fn detect_new_shows(config: &Config) {
+    let schedule = match fetch_schedule(config).await {
+        Some(schedule) => schedule,
+        None => return println!("Error fetching schedule for new shows"),
+    };
+    let seen: HashSet<String> = match db::get_seen_show_ids().await {
+        Ok(ids) => ids.into_iter().collect(),
+        Err(e) => return println!("DB Error fetching seen shows: {}", e),
+    };
+    let new_shows: Vec<(String, String)> = schedule.schedule.into_values().flatten()
+        .filter(|show| !seen.contains(&show.page))
+        .map(|show| (show.page, show.title))
+        .collect();
+    if new_shows.is_empty() { return; }
+    // all at once, so a failure can't leave the table half filled and the rest announced
+    if let Err(e) = db::insert_season_shows(&new_shows, &current_season(config), seen.is_empty()).await {
+        println!("Error saving new shows: {}", e);
+    }
+}
+
+/// Sends everyone who opted in a list of the shows that are new since the last digest.
+pub async fn send_digests(config: &Config) {
+    let (shows, user_ids) = match (db::get_unannounced_shows().await, db::get_season_digest_user_ids().await) {
+        (Ok(shows), Ok(user_ids)) => (shows, user_ids),
+        (Err(e), _) | (_, Err(e)) => return println!("DB Error preparing new show digest: {}", e),
+    };
+    if shows.is_empty() { return; }
+    let embeds = digest_embed(&shows).build();
+    let http = Http::new_with_token(&config.discord_token);
+    for user_id in user_ids {
+        if let Err(e) = send_digest(&http, user_id, &embeds).await {
+            println!("Error sending new show digest to {}: {}", user_id, e);
+        }
+    }
+    let ids: Vec<String> = shows.into_iter().map(|(id, _)| id).collect();
+    if let Err(e) = db::set_shows_announced(&ids).await {
+        println!("Error marking shows as announced: {}", e);
+    }
+}
+
+async fn send_digest(http: &Http, user_id: i64, embeds: &[CreateEmbed]) -> Result<(), serenity::Error> {
+    let user = UserId::from(user_id as u64).to_user(http).await?;
+    for embed in embeds {
+        user.dm(http, |m| m.set_embed(embed.clone())).await?;
+    }
+    Ok(())
+}
+
+fn digest_embed(shows: &[(String, String)]) -> EmbedPages {
+    let mut embed = EmbedPages::default();
+    embed.title(format!("New on subsplease this week ({})", shows.len()))
+        .description("Stop these with \"season digest off\".");
+    for (id, title) in shows {
+        embed.field(title, format!("[subsplease]({})", show_url(id)), true);
+    }
+    embed
+}
+
+
+#[test]
+fn test_season_of() {
+    assert_eq!(season_of(NaiveDate::from_ymd_opt(2021, 1, 1).unwrap()), "Winter 2021");
+    assert_eq!(season_of(NaiveDate::from_ymd_opt(2021, 6, 30).unwrap()), "Spring 2021");
+    assert_eq!(season_of(NaiveDate::from_ymd_opt(2021, 7, 1).unwrap()), "Summer 2021");
+    assert_eq!(season_of(NaiveDate::from_ymd_opt(2021, 12, 31).unwrap()), "Fall 2021");
+}
+
+#[test]
+fn test_premiere_season() {
+    // a late March premiere belongs to spring
+    assert_eq!(premiere_season(NaiveDate::from_ymd_opt(2021, 3, 27).unwrap()), "Spring 2021");
+    assert_eq!(premiere_season(NaiveDate::from_ymd_opt(2021, 3, 10).unwrap()), "Winter 2021");
+    assert_eq!(premiere_season(NaiveDate::from_ymd_opt(2021, 12, 26).unwrap()), "Winter 2022");
+}
+
+#[test]
+fn test_digest_embed() {
+    let shows: Vec<(String, String)> = (0..60).map(|i| (format!("show-{}", i), format!("Show {}", i))).collect();
+    let embeds = digest_embed(&shows).build();
+    assert_eq!(embeds.len(), 3);
+    assert_eq!(embeds[0].0.get("title").unwrap(), "New on subsplease this week (60)");
+    let fields = embeds[2].0.get("fields").unwrap().as_array().unwrap();
+    assert_eq!(fields.len(), 10);
+    assert_eq!(fields[9]["name"], "Show 59");
+    assert_eq!(fields[9]["value"], "[subsplease](https://subsplease.org/shows/show-59/)");
+}
@@ -1,12 +1,18 @@
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::shutdown::Shutdown;
 use crate::subs_pls::db;
-use crate::subs_pls::page_parser::scrape_show;
+use crate::subs_pls::page_parser::{scrape_show, ExternalIds};
+use crate::trackers::resolve_external_ids;
 
-pub async fn update_shows() {
+/// Rescrapes every saved show. Stops early between two shows if the bot shuts down.
+pub async fn update_shows(config: &Config, shutdown: &Shutdown) {
     let res = db::get_all_show_ids().await;
     match res {
         Ok(ids) => {
             for id in ids {
-                let show = scrape_show(&id).await;
+                let show = scrape_show(config, &id).await;
                 match show {
                     None => {
                         println!("Error updating show {}", id);
@@ -20,9 +26,46 @@ pub async fn update_shows() {
                         }
                     }
                 }
-                std::thread::sleep(std::time::Duration::from_secs(10))
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(10)) => {}
+                    _ = shutdown.requested() => {
+                        println!("Stopped updating shows for shutdown");
+                        break;
+                    }
+                }
+            }
+        }
+        Err(e) => println!("DB Error updating shows: {}", e)
+    }
+    resolve_missing_external_ids(config, shutdown).await;
+}
+
+/// Retries the id lookup for shows where it didn't find anything before. Ids someone set
+/// by hand are kept.
+async fn resolve_missing_external_ids(config: &Config, shutdown: &Shutdown) {
+    let shows = match db::get_shows_without_external_ids().await {
+        Ok(shows) => shows,
+        Err(e) => {
+            println!("DB Error fetching shows without external ids: {}", e);
+            return;
+        }
+    };
+    for show in shows {
+        let found = resolve_external_ids(&config.trackers, &show.name).await;
+        if found.resolved {
+            let ids = ExternalIds {
+                mal: show.external_ids.mal.or(found.mal),
+                anilist: show.external_ids.anilist.or(found.anilist),
+                kitsu: show.external_ids.kitsu.or(found.kitsu),
+                resolved: true,
+            };
+            if let Err(e) = db::update_external_ids(&show.id, &ids).await {
+                println!("Error saving external ids of {}: {}", show.id, e)
             }
         }
-        Err(e) => println!("DB Error updating shows: {}", e.to_string())
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            _ = shutdown.requested() => break
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,66 @@
+//! Local stand-in for the external http apis the bot talks to.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::{Body, Request, Response, Server};
+use hyper::header::HeaderMap;
+use hyper::service::{make_service_fn, service_fn};
+
+#[derive(Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path including the query string.
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+pub struct StubServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl StubServer {
+    /// Serves `respond` on a random local port until the test ends.
+    pub async fn start<F>(respond: F) -> StubServer
+        where F: Fn(&RecordedRequest) -> Response<Body> + Send + Sync + 'static {
+        let respond = Arc::new(respond);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let (respond, recorded) = (respond.clone(), recorded.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let (respond, recorded) = (respond.clone(), recorded.clone());
+                    async move {
+                        let (parts, body) = req.into_parts();
+                        let body = hyper::body::to_bytes(body).await.unwrap_or_default().to_vec();
+                        let request = RecordedRequest {
+                            method: parts.method.to_string(),
+                            path: parts.uri.path_and_query().map(|p| p.to_string()).unwrap_or_default(),
+                            headers: parts.headers,
+                            body,
+                        };
+                        let res = respond(&request);
+                        recorded.lock().unwrap().push(request);
+                        Ok::<_, Infallible>(res)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        StubServer { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub fn reply(status: u16, body: &str) -> Response<Body> {
+    Response::builder().status(status).body(Body::from(body.to_string())).unwrap()
+}
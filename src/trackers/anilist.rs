@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::config::TrackerConfig;
+use super::{ListEntry, TrackerError, CLIENT};
+
+/// AniList allows 90 requests a minute. Every show added gets looked up, so an import
+/// of a long list would run into the limit otherwise.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(700);
+
+lazy_static::lazy_static! {
+    static ref NEXT_REQUEST: Mutex<Instant> = Mutex::new(Instant::now());
+}
+
+const LIST_QUERY: &str = "query ($user: String) {
+  MediaListCollection(userName: $user, type: ANIME, status_in: [CURRENT, PLANNING]) {
+    lists { entries { media { title { romaji english } synonyms } } }
+  }
+}";
+
+const SEARCH_QUERY: &str = "query ($search: String) {
+  Page(perPage: 5) { media(search: $search, type: ANIME) { id idMal title { romaji english } synonyms } }
+}";
+
+#[derive(Deserialize)]
+struct Response {
+    data: Option<Data>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    data: Option<SearchData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SearchData {
+    page: Option<SearchPage>,
+}
+
+#[derive(Deserialize)]
+struct SearchPage {
+    media: Vec<SearchMedia>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchMedia {
+    id: i64,
+    id_mal: Option<i64>,
+    title: Title,
+    #[serde(default)]
+    synonyms: Vec<String>,
+}
+
+/// An anime found by a title search.
+pub struct SearchHit {
+    pub anilist_id: i64,
+    pub mal_id: Option<i64>,
+    pub titles: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Data {
+    media_list_collection: Option<Collection>,
+}
+
+#[derive(Deserialize)]
+struct Collection {
+    lists: Vec<List>,
+}
+
+#[derive(Deserialize)]
+struct List {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    media: Media,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    title: Title,
+    #[serde(default)]
+    synonyms: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Title {
+    romaji: Option<String>,
+    english: Option<String>,
+}
+
+/// Waits until the next request keeps within the rate limit. The lock is held while
+/// waiting, so requests go out one after another.
+async fn pace() {
+    let mut next = NEXT_REQUEST.lock().await;
+    tokio::time::sleep_until(*next).await;
+    *next = Instant::now() + REQUEST_INTERVAL;
+}
+
+/// Fetches the "watching" and "planning" entries of a public AniList list.
+pub async fn fetch_list(config: &TrackerConfig, user_name: &str) -> Result<Vec<ListEntry>, TrackerError> {
+    pace().await;
+    let res = CLIENT
+        .post(&config.anilist_api_url)
+        .json(&json!({ "query": LIST_QUERY, "variables": { "user": user_name } }))
+        .send().await
+        .map_err(|e| TrackerError::Request(e.to_string()))?;
+    match res.status().as_u16() {
+        200 => {}
+        404 => return Err(TrackerError::UserNotFound),
+        code => return Err(TrackerError::Request(format!("AniList answered with {}", code))),
+    }
+    let response: Response = res.json().await
+        .map_err(|e| TrackerError::InvalidResponse(e.to_string()))?;
+    let collection = response.data
+        .and_then(|d| d.media_list_collection)
+        .ok_or(TrackerError::UserNotFound)?;
+    Ok(collection.lists.into_iter()
+        .flat_map(|l| l.entries)
+        .map(|e| {
+            let titles = e.media.title.romaji.into_iter()
+                .chain(e.media.title.english)
+                .chain(e.media.synonyms)
+                .collect();
+            ListEntry { titles }
+        })
+        .collect())
+}
+
+/// Searches AniList for anime by title, best results first.
+pub async fn search(config: &TrackerConfig, title: &str) -> Result<Vec<SearchHit>, TrackerError> {
+    pace().await;
+    let res = CLIENT
+        .post(&config.anilist_api_url)
+        .json(&json!({ "query": SEARCH_QUERY, "variables": { "search": title } }))
+        .send().await
+        .map_err(|e| TrackerError::Request(e.to_string()))?;
+    if !res.status().is_success() {
+        return Err(TrackerError::Request(format!("AniList answered with {}", res.status())));
+    }
+    let response: SearchResponse = res.json().await
+        .map_err(|e| TrackerError::InvalidResponse(e.to_string()))?;
+    let page = response.data.and_then(|d| d.page)
+        .ok_or_else(|| TrackerError::InvalidResponse("search returned no page".to_string()))?;
+    Ok(page.media.into_iter()
+        .map(|m| SearchHit {
+            anilist_id: m.id,
+            mal_id: m.id_mal,
+            titles: m.title.romaji.into_iter().chain(m.title.english).chain(m.synonyms).collect(),
+        })
+        .collect())
+}
+
+
+#[tokio::test]
+async fn test_fetch_list() {
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| {
+        let body: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        if body["variables"]["user"] == "nobody" {
+            return reply(404, r#"{"errors": [{"message": "User not found", "status": 404}], "data": {"MediaListCollection": null}}"#);
+        }
+        reply(200, r#"{"data": {"MediaListCollection": {"lists": [
+            {"entries": [{"media": {"title": {"romaji": "Kingdom 3rd Season", "english": null}, "synonyms": []}}]},
+            {"entries": [{"media": {"title": {"romaji": "One Piece", "english": "ONE PIECE"}, "synonyms": ["OP"]}}]}
+        ]}}}"#)
+    }).await;
+    let config = TrackerConfig { mal_api_url: server.url.clone(), mal_client_id: None, anilist_api_url: server.url.clone(),
+        kitsu_api_url: server.url.clone() };
+
+    let entries = fetch_list(&config, "someone").await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].titles, vec!["Kingdom 3rd Season"]);
+    assert_eq!(entries[1].titles, vec!["One Piece", "ONE PIECE", "OP"]);
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert!(String::from_utf8(request.body.clone()).unwrap().contains("status_in: [CURRENT, PLANNING]"));
+    assert_eq!(fetch_list(&config, "nobody").await.err(), Some(TrackerError::UserNotFound));
+}
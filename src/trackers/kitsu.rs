@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+use crate::config::TrackerConfig;
+use super::{api_url, TrackerError, CLIENT};
+
+#[derive(Deserialize)]
+struct Mappings {
+    data: Vec<Mapping>,
+}
+
+#[derive(Deserialize)]
+struct Mapping {
+    relationships: Relationships,
+}
+
+#[derive(Deserialize)]
+struct Relationships {
+    item: Relationship,
+}
+
+#[derive(Deserialize)]
+struct Relationship {
+    data: Option<ResourceId>,
+}
+
+#[derive(Deserialize)]
+struct ResourceId {
+    id: String,
+}
+
+/// Looks up the Kitsu anime mapped to a MyAnimeList id.
+pub async fn id_for_mal_id(config: &TrackerConfig, mal_id: i64) -> Result<Option<i64>, TrackerError> {
+    let url = api_url(&config.kitsu_api_url, &["mappings"])?;
+    let res = CLIENT
+        .get(url)
+        .query(&[("filter[externalSite]", "myanimelist/anime".to_string()),
+                 ("filter[externalId]", mal_id.to_string())])
+        .header("Accept", "application/vnd.api+json")
+        .send().await
+        .map_err(|e| TrackerError::Request(e.to_string()))?;
+    if !res.status().is_success() {
+        return Err(TrackerError::Request(format!("Kitsu answered with {}", res.status())));
+    }
+    let mappings: Mappings = res.json().await
+        .map_err(|e| TrackerError::InvalidResponse(e.to_string()))?;
+    Ok(mappings.data.into_iter()
+        .filter_map(|m| m.relationships.item.data)
+        .find_map(|item| item.id.parse().ok()))
+}
@@ -0,0 +1,102 @@
+use serde::Deserialize;
+
+use crate::config::TrackerConfig;
+use super::{api_url, ListEntry, TrackerError, CLIENT};
+
+const LIST_STATUSES: [&str; 2] = ["watching", "plan_to_watch"];
+
+#[derive(Deserialize)]
+struct AnimeList {
+    data: Vec<ListNode>,
+}
+
+#[derive(Deserialize)]
+struct ListNode {
+    node: Anime,
+}
+
+#[derive(Deserialize)]
+struct Anime {
+    title: String,
+    #[serde(default)]
+    alternative_titles: AlternativeTitles,
+}
+
+#[derive(Default, Deserialize)]
+struct AlternativeTitles {
+    #[serde(default)]
+    synonyms: Vec<String>,
+    #[serde(default)]
+    en: String,
+}
+
+/// Fetches the "watching" and "plan to watch" entries of a public MyAnimeList list.
+pub async fn fetch_list(config: &TrackerConfig, user_name: &str) -> Result<Vec<ListEntry>, TrackerError> {
+    let client_id = config.mal_client_id.as_ref().ok_or(TrackerError::NotConfigured)?;
+    let url = api_url(&config.mal_api_url, &["users", user_name, "animelist"])?;
+    let client = &*CLIENT;
+    let mut entries = Vec::new();
+    for status in LIST_STATUSES.iter() {
+        let res = client.get(url.clone())
+            .query(&[("status", *status), ("limit", "1000"), ("fields", "alternative_titles")])
+            .header("X-MAL-CLIENT-ID", client_id)
+            .send().await
+            .map_err(|e| TrackerError::Request(e.to_string()))?;
+        match res.status().as_u16() {
+            200 => {}
+            403 | 404 => return Err(TrackerError::UserNotFound),
+            code => return Err(TrackerError::Request(format!("MyAnimeList answered with {}", code))),
+        }
+        let list: AnimeList = res.json().await
+            .map_err(|e| TrackerError::InvalidResponse(e.to_string()))?;
+        entries.extend(list.data.into_iter().map(|n| {
+            let mut titles = vec![n.node.title];
+            if !n.node.alternative_titles.en.is_empty() {
+                titles.push(n.node.alternative_titles.en);
+            }
+            titles.extend(n.node.alternative_titles.synonyms);
+            ListEntry { titles }
+        }));
+    }
+    Ok(entries)
+}
+
+
+#[cfg(test)]
+fn test_config(url: &str, client_id: Option<&str>) -> TrackerConfig {
+    TrackerConfig {
+        mal_api_url: format!("{}/v2", url),
+        mal_client_id: client_id.map(|c| c.to_string()),
+        anilist_api_url: url.to_string(),
+        kitsu_api_url: url.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_list() {
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| {
+        if req.path.starts_with("/v2/users/nobody/") { return reply(404, "{\"error\":\"not_found\"}"); }
+        if req.path.contains("status=watching") {
+            reply(200, r#"{"data": [{"node": {"id": 40028, "title": "Shingeki no Kyojin: The Final Season",
+                "alternative_titles": {"synonyms": ["AoT"], "en": "Attack on Titan Final Season", "ja": ""}}}],
+                "paging": {}}"#)
+        } else {
+            reply(200, r#"{"data": [{"node": {"id": 21, "title": "One Piece"}}], "paging": {}}"#)
+        }
+    }).await;
+
+    let entries = fetch_list(&test_config(&server.url, Some("client")), "some user").await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].titles, vec!["Shingeki no Kyojin: The Final Season", "Attack on Titan Final Season", "AoT"]);
+    assert_eq!(entries[1].titles, vec!["One Piece"]);
+    let requests = server.requests();
+    assert!(requests[0].path.starts_with("/v2/users/some%20user/animelist?status=watching&limit=1000"));
+    assert_eq!(requests[0].headers["X-MAL-CLIENT-ID"], "client");
+    assert!(requests[1].path.contains("status=plan_to_watch"));
+
+    assert_eq!(fetch_list(&test_config(&server.url, Some("client")), "nobody").await.err(),
+               Some(TrackerError::UserNotFound));
+    assert_eq!(fetch_list(&test_config(&server.url, None), "some user").await.err(),
+               Some(TrackerError::NotConfigured));
+}
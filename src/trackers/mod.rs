@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::config::TrackerConfig;
+use crate::subs_pls::page_parser::ExternalIds;
+
+pub mod anilist;
+pub mod kitsu;
+pub mod mal;
+
+/// Imports and show updates wait on the list sites, which shouldn't hang them.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+lazy_static::lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+}
+
+/// An anime on a user's list of one of the tracking sites. The main title comes first.
+pub struct ListEntry {
+    pub titles: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TrackerError {
+    NotConfigured,
+    UserNotFound,
+    Request(String),
+    InvalidResponse(String),
+}
+
+/// A show subsplease releases, which list entries get matched against.
+pub struct Candidate {
+    pub title: String,
+    pub show_id: String,
+}
+
+/// Minimum similarity of two normalized titles to count as the same show.
+const MATCH_THRESHOLD: f64 = 0.88;
+
+/// Lowercases, strips punctuation and writes season numbers the way subsplease does,
+/// so "Kingdom 3rd Season" and "Kingdom S3" end up equal.
+pub fn normalize_title(title: &str) -> String {
+    lazy_static::lazy_static! {
+            static ref NON_ALNUM: Regex = Regex::new("[^a-z0-9]+").unwrap();
+            static ref ORDINAL_SEASON: Regex = Regex::new(r"\b(\d+)(?:st|nd|rd|th) season\b").unwrap();
+            static ref SEASON_NUMBER: Regex = Regex::new(r"\bseason (\d+)\b").unwrap();
+        }
+    let lower = NON_ALNUM.replace_all(&title.to_lowercase(), " ").trim().to_string();
+    let seasons = ORDINAL_SEASON.replace_all(&lower, "s$1");
+    SEASON_NUMBER.replace_all(&seasons, "s$1").to_string()
+}
+
+/// Similarity of the closest of `titles` to `other`, from 0 to 1.
+fn similarity(titles: &[String], other: &str) -> f64 {
+    let other = normalize_title(other);
+    titles.iter()
+        .map(|t| normalize_title(t))
+        .filter(|t| !t.is_empty())
+        .map(|t| strsim::normalized_levenshtein(&t, &other))
+        .fold(0.0, f64::max)
+}
+
+/// Finds the subsplease show that fits one of the titles best, if any fits well enough.
+pub fn best_match<'a>(titles: &[String], candidates: &'a [Candidate]) -> Option<&'a Candidate> {
+    let mut best: Option<(f64, &Candidate)> = None;
+    for candidate in candidates {
+        let score = similarity(titles, &candidate.title);
+        if score >= MATCH_THRESHOLD && best.is_none_or(|(s, _)| score > s) {
+            best = Some((score, candidate));
+        }
+    }
+    best.map(|(_, c)| c)
+}
+
+/// Looks the show up on AniList, which also knows the MyAnimeList id, and maps that to
+/// Kitsu. Only search hits whose title fits the name are taken.
+pub async fn resolve_external_ids(config: &TrackerConfig, name: &str) -> ExternalIds {
+    let hits = match anilist::search(config, name).await {
+        Ok(hits) => hits,
+        Err(e) => {
+            println!("Error looking up ids of {}: {:?}", name, e);
+            return ExternalIds::default();
+        }
+    };
+    let hit = match hits.into_iter().find(|h| similarity(&h.titles, name) >= MATCH_THRESHOLD) {
+        Some(hit) => hit,
+        None => return ExternalIds::default(),
+    };
+    let kitsu = match hit.mal_id {
+        Some(mal_id) => kitsu::id_for_mal_id(config, mal_id).await
+            .unwrap_or_else(|e| {
+                println!("Error looking up kitsu id of {}: {:?}", name, e);
+                None
+            }),
+        None => None
+    };
+    ExternalIds { mal: hit.mal_id, anilist: Some(hit.anilist_id), kitsu, resolved: true }
+}
+
+/// Builds `base` with the segments appended, percent encoding them.
+fn api_url(base: &str, segments: &[&str]) -> Result<reqwest::Url, TrackerError> {
+    let mut url = reqwest::Url::parse(base).map_err(|e| TrackerError::Request(e.to_string()))?;
+    url.path_segments_mut()
+        .map_err(|_| TrackerError::Request(format!("{} can't be a base url", base)))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+
+#[test]
+fn test_normalize_title() {
+    assert_eq!(normalize_title("Re:Zero kara Hajimeru Isekai Seikatsu"), "re zero kara hajimeru isekai seikatsu");
+    assert_eq!(normalize_title("Kingdom 3rd Season"), "kingdom s3");
+    assert_eq!(normalize_title("Dr. STONE: Season 2"), "dr stone s2");
+}
+
+#[test]
+fn test_best_match() {
+    let candidates = vec![
+        Candidate { title: "Kingdom S3".to_string(), show_id: "kingdom-s3".to_string() },
+        Candidate { title: "Re Zero kara Hajimeru Isekai Seikatsu".to_string(),
+            show_id: "re-zero-kara-hajimeru-isekai-seikatsu".to_string() },
+        Candidate { title: "Boruto - Naruto Next Generations".to_string(), show_id: "boruto".to_string() },
+    ];
+    let titles = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    assert_eq!(best_match(&titles(&["Kingdom 3rd Season"]), &candidates).unwrap().show_id, "kingdom-s3");
+    assert_eq!(best_match(&titles(&["Re:ZERO -Starting Life in Another World-", "Re:Zero kara Hajimeru Isekai Seikatsu"]),
+                          &candidates).unwrap().show_id, "re-zero-kara-hajimeru-isekai-seikatsu");
+    assert_eq!(best_match(&titles(&["Boruto: Naruto Next Generations"]), &candidates).unwrap().show_id, "boruto");
+    assert!(best_match(&titles(&["Kingdom"]), &candidates).is_none());
+    assert!(best_match(&titles(&["Naruto"]), &candidates).is_none());
+}
+
+#[tokio::test]
+async fn test_resolve_external_ids() {
+    use crate::test_server::{reply, StubServer};
+    let server = StubServer::start(|req| {
+        if req.path.starts_with("/kitsu/mappings") {
+            assert!(req.path.contains("filter%5BexternalId%5D=40028"));
+            return reply(200, r#"{"data": [{"id": "1", "type": "mappings",
+                "relationships": {"item": {"data": {"type": "anime", "id": "42422"}}}}]}"#);
+        }
+        reply(200, r#"{"data": {"Page": {"media": [
+            {"id": 1, "idMal": 1, "title": {"romaji": "Shingeki no Kyojin", "english": "Attack on Titan"}, "synonyms": []},
+            {"id": 110277, "idMal": 40028, "title": {"romaji": "Shingeki no Kyojin: The Final Season",
+                "english": "Attack on Titan Final Season"}, "synonyms": []}
+        ]}}}"#)
+    }).await;
+    let config = TrackerConfig {
+        mal_api_url: server.url.clone(),
+        mal_client_id: None,
+        anilist_api_url: server.url.clone(),
+        kitsu_api_url: format!("{}/kitsu", server.url),
+    };
+    let ids = resolve_external_ids(&config, "Shingeki no Kyojin - The Final Season").await;
+    assert_eq!(ids, ExternalIds { mal: Some(40028), anilist: Some(110277), kitsu: Some(42422), resolved: true });
+    let ids = resolve_external_ids(&config, "Something Else Entirely").await;
+    assert_eq!(ids, ExternalIds::default());
+}
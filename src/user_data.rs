@@ -0,0 +1,121 @@
+//! Everything the bot keeps about a user, sent to them as JSON by `mydata`. Secrets like
+//! api keys and passwords are left out, only whether one is saved.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct UserData {
+    /// A string, since Discord ids don't fit into the numbers of most JSON parsers.
+    pub user_id: String,
+    /// Unknown for users from before it was recorded.
+    pub registered_at: Option<String>,
+    pub watchlist: Vec<WatchedShow>,
+    pub preferences: Preferences,
+    pub notification_channels: Vec<ChannelData>,
+    pub integrations: Integrations,
+    pub pending_notifications: Vec<ReleaseData>,
+    pub notification_history: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize)]
+pub struct WatchedShow {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub added_at: String,
+}
+
+#[derive(Serialize)]
+pub struct Preferences {
+    pub delivery: String,
+    pub quiet_hours: Option<String>,
+    pub timezone: Option<String>,
+    pub season_digest: bool,
+}
+
+#[derive(Serialize)]
+pub struct ChannelData {
+    pub kind: String,
+    pub target: String,
+    pub enabled: bool,
+    pub confirmed: bool,
+    pub token_saved: bool,
+}
+
+#[derive(Serialize)]
+pub struct Integrations {
+    pub premiumize_key_saved: bool,
+    pub download_client: Option<DownloadClientData>,
+    pub webhook_url: Option<String>,
+    pub calendar_link: bool,
+}
+
+#[derive(Serialize)]
+pub struct DownloadClientData {
+    pub kind: String,
+    pub url: String,
+    pub username: Option<String>,
+    pub category: Option<String>,
+    pub save_path: Option<String>,
+    pub password_saved: bool,
+}
+
+/// A release waiting for the next digest or the end of the quiet hours.
+#[derive(Serialize)]
+pub struct ReleaseData {
+    pub show: String,
+    pub title: String,
+    pub released_at: String,
+    pub note: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    /// None when the release is gone from the db.
+    pub title: Option<String>,
+    pub guid: String,
+    pub channel: String,
+    pub sent_at: String,
+}
+
+impl UserData {
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap_or_default()
+    }
+}
+
+
+#[test]
+fn test_user_data_json() {
+    let data = UserData {
+        user_id: "123456789012345678".to_string(),
+        registered_at: None,
+        watchlist: vec![WatchedShow {
+            id: "one-piece".to_string(),
+            name: "One Piece".to_string(),
+            url: "https://subsplease.org/shows/one-piece/".to_string(),
+            added_at: "2021-11-21T02:15:00+00:00".to_string(),
+        }],
+        preferences: Preferences {
+            delivery: "hourly".to_string(),
+            quiet_hours: None,
+            timezone: Some("Europe/Berlin".to_string()),
+            season_digest: true,
+        },
+        notification_channels: Vec::new(),
+        integrations: Integrations {
+            premiumize_key_saved: true,
+            download_client: None,
+            webhook_url: None,
+            calendar_link: false,
+        },
+        pending_notifications: Vec::new(),
+        notification_history: Vec::new(),
+    };
+    let json: serde_json::Value = serde_json::from_slice(&data.to_json()).unwrap();
+    assert_eq!(json["user_id"], "123456789012345678");
+    assert_eq!(json["registered_at"], serde_json::Value::Null);
+    assert_eq!(json["watchlist"][0]["name"], "One Piece");
+    assert_eq!(json["preferences"]["season_digest"], true);
+    assert_eq!(json["integrations"]["premiumize_key_saved"], true);
+}
@@ -1,5 +1,26 @@
+use crate::config::Config;
+use crate::downloads::{self, ClientSettings, DownloadError, DownloadOptions};
+use crate::ical;
+use crate::notifiers::{self, Channel, ChannelKind, Notification, NotifierError};
+use crate::outbound::{self, OutboundError};
+use crate::premiumize::{self, PremiumizeError};
+use crate::secrets;
+use crate::schedule::Schedule;
 use crate::subs_pls::db;
-use crate::subs_pls::page_parser::{Show, AddFailure, add_show, is_valid_url, AirTime};
+use crate::subs_pls::season;
+use crate::subs_pls::digest::{DeliveryMode, DeliverySettings, QuietHours};
+use crate::subs_pls::page_parser::{Show, AddFailure, add_show, fetch_schedule, is_valid_url, scrape_show,
+                                     show_id_from_url, AirTime};
+use crate::subs_pls::release_parser::Release;
+use crate::trackers::{self, Candidate, TrackerError};
+use crate::user_data::{ChannelData, DownloadClientData, HistoryEntry, Integrations, Preferences, ReleaseData, UserData,
+                       WatchedShow};
+use crate::watchlist_file::show_url;
+use crate::webhooks::{self, WebhookError};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serenity::http::client::Http;
+use rand::Rng;
 use std::collections::HashSet;
 
 pub async fn is_user_registered(user_id: i64) -> Result<bool, ()> {
@@ -7,141 +28,649 @@ pub async fn is_user_registered(user_id: i64) -> Result<bool, ()> {
     match res {
         Ok(b) => Ok(b),
         Err(e) => {
-            println!("Error checking user registration: {}", e.to_string());
+            println!("Error checking user registration: {}", e);
             Err(())
         }
     }
 }
 
-pub async fn register_user(user_id: i64) -> Result<(), ()> {
-    let res = db::insert_user(user_id).await;
+/// Returns the number of shows brought back from a watchlist kept after unregistering.
+pub async fn register_user(config: &Config, user_id: i64) -> Result<usize, ()> {
+    let res = db::insert_user(user_id, Utc::now() - grace_period(config)).await;
     match res {
-        Ok(()) => Ok(()),
+        Ok(restored) => Ok(restored),
         Err(e) => {
-            println!("Error inserting user: {}", e.to_string());
+            println!("Error inserting user: {}", e);
             Err(())
         }
     }
 }
 
-pub async fn unregister_user(user_id: i64) -> Result<(), ()> {
-    let res = db::remove_user(user_id).await;
+pub async fn unregister_user(config: &Config, user_id: i64) -> Result<(), ()> {
+    let res = db::remove_user(user_id, !config.unregister_grace.is_zero()).await;
     match res {
         Ok(()) => Ok(()),
         Err(e) => {
-            println!("Error Removing User: {}", e.to_string());
+            println!("Error Removing User: {}", e);
             Err(())
         }
     }
 }
 
-pub async fn add_user_show(user_id: i64, identifier: &str) -> Result<Show, AddFailure> {
-    add_show(user_id, identifier).await
+/// Deletes the watchlists of users who unregistered longer ago than the grace period.
+pub async fn purge_unregistered(config: &Config) {
+    if let Err(e) = db::delete_unregistered_watchlists(Utc::now() - grace_period(config)).await {
+        println!("Error deleting watchlists of unregistered users: {}", e);
+    }
 }
 
-pub enum RemoveFailure {
-    InvalidIdentifier,
+fn grace_period(config: &Config) -> chrono::Duration {
+    chrono::Duration::from_std(config.unregister_grace).unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+pub async fn add_user_show(config: &Config, user_id: i64, identifier: &str) -> Result<Show, AddFailure> {
+    add_show(config, user_id, identifier, true).await
+}
+
+/// Shows one import adds at most, as each new one is scraped from subsplease.
+pub const MAX_IMPORT_SHOWS: usize = 200;
+
+/// Adds every identifier on its own, so one bad line doesn't stop the rest. Only the first
+/// `MAX_IMPORT_SHOWS` are added.
+pub async fn import_user_shows(config: &Config, user_id: i64, identifiers: &[String])
+                               -> Vec<(String, Result<Show, AddFailure>)> {
+    let mut results = Vec::with_capacity(identifiers.len().min(MAX_IMPORT_SHOWS));
+    for identifier in identifiers.iter().take(MAX_IMPORT_SHOWS) {
+        results.push((identifier.to_string(), add_show(config, user_id, identifier, false).await));
+    }
+    results
+}
+
+pub enum Tracker {
+    MyAnimeList,
+    AniList,
+}
+
+pub struct TrackerImport {
+    /// Title on the list site and the result of adding the matching show.
+    pub added: Vec<(String, Result<Show, AddFailure>)>,
+    pub unmatched: Vec<String>,
+}
+
+/// Matches a user's list on an anime list site against the shows subsplease releases and
+/// adds everything that was found.
+pub async fn import_from_tracker(config: &Config, user_id: i64, tracker: Tracker, user_name: &str)
+                                 -> Result<TrackerImport, TrackerError> {
+    let entries = match tracker {
+        Tracker::MyAnimeList => trackers::mal::fetch_list(&config.trackers, user_name).await?,
+        Tracker::AniList => trackers::anilist::fetch_list(&config.trackers, user_name).await?,
+    };
+    let mut candidates: Vec<Candidate> = db::get_all_show_names().await
+        .map_err(|e| TrackerError::Request(e.to_string()))?
+        .into_iter()
+        .map(|(show_id, title)| Candidate { title, show_id })
+        .collect();
+    if let Some(schedule) = fetch_schedule(config).await {
+        candidates.extend(schedule.schedule.into_values()
+            .flatten()
+            .map(|s| Candidate { title: s.title, show_id: s.page }));
+    }
+
+    let mut import = TrackerImport { added: Vec::new(), unmatched: Vec::new() };
+    let mut matched_ids = HashSet::new();
+    for entry in entries {
+        let title = entry.titles.first().cloned().unwrap_or_default();
+        match trackers::best_match(&entry.titles, &candidates) {
+            Some(c) if matched_ids.insert(c.show_id.to_string()) => {
+                let res = add_show(config, user_id, &show_url(&c.show_id), false).await;
+                import.added.push((title, res));
+            }
+            Some(_) => {}
+            None => import.unmatched.push(title),
+        }
+    }
+    Ok(import)
+}
+
+/// Everything stored about a user, for `mydata`.
+pub async fn get_user_data(user_id: i64) -> Result<UserData, ()> {
+    get_user_data_from_db(user_id).await.map_err(|e| println!("Error collecting data of {}: {}", user_id, e))
+}
+
+async fn get_user_data_from_db(user_id: i64) -> Result<UserData, tokio_postgres::Error> {
+    let (registered_at, season_digest) = db::get_user_profile(user_id).await?;
+    let mut watchlist = db::get_watchlist(user_id).await?;
+    watchlist.sort_by_key(|(_, added_at)| *added_at);
+    let settings = db::get_delivery_settings(user_id).await?;
+    let channels = db::get_notification_channels(user_id).await?;
+    let download_client = db::get_download_client(user_id).await?;
+    Ok(UserData {
+        user_id: user_id.to_string(),
+        registered_at: registered_at.map(|t| t.to_rfc3339()),
+        watchlist: watchlist.into_iter().map(|(show, added_at)| WatchedShow {
+            url: show_url(&show.id),
+            id: show.id,
+            name: show.name,
+            added_at: added_at.to_rfc3339(),
+        }).collect(),
+        preferences: preferences(&settings, season_digest),
+        notification_channels: channels.into_iter().map(|(channel, token)| ChannelData {
+            kind: channel.kind.id().to_string(),
+            target: channel.target,
+            enabled: channel.enabled,
+            confirmed: channel.confirmed,
+            token_saved: token.is_some(),
+        }).collect(),
+        integrations: Integrations {
+            premiumize_key_saved: db::has_premiumize_key(user_id).await?,
+            download_client: download_client.map(|(client, password)| DownloadClientData {
+                kind: client.kind.id().to_string(),
+                url: client.url,
+                username: client.username,
+                category: client.options.category,
+                save_path: client.options.save_path,
+                password_saved: password.is_some(),
+            }),
+            webhook_url: db::get_webhook(user_id).await?.map(|(url, _)| url),
+            calendar_link: db::get_calendar_token(user_id).await?.is_some(),
+        },
+        pending_notifications: db::get_pending_notifications(user_id, Utc::now()).await?.into_iter()
+            .map(|(show, release, note)| ReleaseData {
+                show,
+                title: release.title,
+                released_at: release.released_at.to_rfc3339(),
+                note,
+            }).collect(),
+        notification_history: db::get_notification_history(user_id).await?.into_iter()
+            .map(|(guid, title, channel, sent_at)| HistoryEntry { title, guid, channel, sent_at: sent_at.to_rfc3339() })
+            .collect(),
+    })
+}
+
+fn preferences(settings: &DeliverySettings, season_digest: bool) -> Preferences {
+    Preferences {
+        delivery: settings.mode.to_string(),
+        quiet_hours: settings.quiet_hours.map(|q| q.to_string()),
+        timezone: settings.timezone.map(|tz| tz.name().to_string()),
+        season_digest,
+    }
+}
+
+pub async fn get_user_shows(user_id: i64) -> Result<Vec<Show>, ()> {
+    db::get_shows_for_user(user_id).await.map_err(|e| println!("Error fetching watchlist: {}", e))
+}
+
+/// The watchlist and the preferences that go into an export.
+pub async fn get_export(user_id: i64) -> Result<(Vec<Show>, Preferences), ()> {
+    get_export_from_db(user_id).await.map_err(|e| println!("Error fetching export of {}: {}", user_id, e))
+}
+
+async fn get_export_from_db(user_id: i64) -> Result<(Vec<Show>, Preferences), tokio_postgres::Error> {
+    let (_, season_digest) = db::get_user_profile(user_id).await?;
+    let settings = db::get_delivery_settings(user_id).await?;
+    Ok((db::get_shows_for_user(user_id).await?, preferences(&settings, season_digest)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListSort {
+    Name,
+    AirDay,
+    /// Newest first.
+    Added,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListFilter {
+    All,
+    Airing,
+    NonAiring,
+}
+
+pub struct WatchlistEntry {
+    pub show: Show,
+    pub added_at: DateTime<Utc>,
+}
+
+pub async fn get_watchlist(user_id: i64, sort: ListSort, filter: ListFilter) -> Result<Vec<WatchlistEntry>, ()> {
+    let entries = db::get_watchlist(user_id).await
+        .map_err(|e| println!("Error fetching watchlist: {}", e))?
+        .into_iter()
+        .map(|(show, added_at)| WatchlistEntry { show, added_at })
+        .collect();
+    Ok(sort_watchlist(entries, sort, filter))
+}
+
+/// Shows that aren't airing have no air day and go last when sorting by it.
+fn sort_watchlist(entries: Vec<WatchlistEntry>, sort: ListSort, filter: ListFilter) -> Vec<WatchlistEntry> {
+    let mut entries: Vec<WatchlistEntry> = entries.into_iter()
+        .filter(|e| match filter {
+            ListFilter::All => true,
+            ListFilter::Airing => e.show.air_time.is_airing,
+            ListFilter::NonAiring => !e.show.air_time.is_airing,
+        })
+        .collect();
+    entries.sort_by_key(|e| e.show.name.to_lowercase());
+    match sort {
+        ListSort::Name => {}
+        ListSort::AirDay => entries.sort_by_key(|e| {
+            let a = &e.show.air_time;
+            (!a.is_airing, a.est_week_day, a.est_h, a.est_m)
+        }),
+        ListSort::Added => entries.sort_by_key(|e| std::cmp::Reverse(e.added_at)),
+    }
+    entries
+}
+
+#[derive(Clone, Copy)]
+pub enum ListSite {
+    MyAnimeList,
+    AniList,
+    Kitsu,
+}
+
+pub enum LinkFailure {
     ShowNotFound,
     DBError,
 }
 
-pub async fn remove_non_airing(user_id: i64) -> Result<Vec<Show>, RemoveFailure> {
-    let user_shows = db::get_shows_for_user(user_id)
-        .await.map_err(|_| RemoveFailure::DBError)?;
-    let mut removed_shows = Vec::new();
-    for show in user_shows.iter() {
-        if !show.air_time.is_airing {
-            remove_user_show(user_id, &format!("https://subsplease.org/shows/{}/", show.id)).await?;
-            removed_shows.push(show.clone());
-        }
+/// Sets the id of a saved show on a list site by hand, for when the lookup picked the
+/// wrong entry or none at all.
+pub async fn set_external_id(identifier: &str, site: ListSite, id: i64) -> Result<Show, LinkFailure> {
+    let res = if is_valid_url(identifier) {
+        let show_id = show_id_from_url(identifier).ok_or(LinkFailure::ShowNotFound)?;
+        db::get_show_from_show_id(show_id).await
+    } else {
+        db::get_show_from_name(identifier).await
+    };
+    let mut show = res.map_err(|_| LinkFailure::ShowNotFound)?;
+    match site {
+        ListSite::MyAnimeList => show.external_ids.mal = Some(id),
+        ListSite::AniList => show.external_ids.anilist = Some(id),
+        ListSite::Kitsu => show.external_ids.kitsu = Some(id),
     }
-    Ok(removed_shows)
-}
-
-pub struct ShowTable {
-    pub release_times: Vec<String>,
-    //size m
-    pub days: Vec<String>,
-    // size n
-    pub shows: Vec<Vec<String>>,
-    // size nxm
-    pub non_airing: Vec<String>,
-}
-
-impl ShowTable {
-    pub fn get_printable_table(&self) -> Result<Vec<(String, String)>, ()> {
-        let mut res = Vec::with_capacity(self.days.len() + 1);
-        for (i, day) in self.days.iter().enumerate() {
-            let shows_on_day = self.shows.get(i).ok_or_else(|| ())?;
-            let timeslot_strings = shows_on_day.iter()
-                .zip(&self.release_times)
-                .filter(|(name, _)| name != &&"".to_string())
-                .map(|(name, time)|
-                    format!("{} - {}", time, name))
-                .fold("".to_string(), |x, y| format!("{}\n{}", x, y));
-            res.push((day.to_string(), timeslot_strings))
+    let ids = &show.external_ids;
+    show.external_ids.resolved = ids.mal.is_some() && ids.anilist.is_some() && ids.kitsu.is_some();
+    db::update_external_ids(&show.id, &show.external_ids).await.map_err(|_| LinkFailure::DBError)?;
+    Ok(show)
+}
+
+pub struct ShowInfo {
+    pub show: Show,
+    pub watchers: i64,
+    pub latest_release: Option<Release>,
+    pub on_watchlist: bool,
+}
+
+pub enum InfoFailure {
+    ShowNotFound,
+    DBError,
+}
+
+/// Looks a show up by url or name. Urls of shows nobody added yet are scraped without
+/// saving them, names are matched loosely against the saved shows.
+pub async fn show_info(config: &Config, user_id: i64, identifier: &str) -> Result<ShowInfo, InfoFailure> {
+    let show = if is_valid_url(identifier) {
+        let show_id = show_id_from_url(identifier).ok_or(InfoFailure::ShowNotFound)?;
+        if db::is_show_saved(show_id).await.map_err(|_| InfoFailure::DBError)? {
+            db::get_show_from_show_id(show_id).await.map_err(|_| InfoFailure::DBError)?
+        } else {
+            scrape_show(config, show_id).await.ok_or(InfoFailure::ShowNotFound)?
         }
-        if !self.non_airing.is_empty() {
-            let mut non_airing = String::new();
-            let stop = self.non_airing.len() - 1;
-            for (i, na) in self.non_airing.iter().enumerate() {
-                non_airing.push_str(&*na.to_string());
-
-                if i < stop {
-                    non_airing.push(',');
-                    non_airing.push(' ');
-                }
+    } else {
+        match db::get_show_from_name(identifier).await {
+            Ok(show) => show,
+            Err(_) => {
+                let candidates: Vec<Candidate> = db::get_all_show_names().await
+                    .map_err(|_| InfoFailure::DBError)?
+                    .into_iter()
+                    .map(|(show_id, title)| Candidate { title, show_id })
+                    .collect();
+                let show_id = trackers::best_match(&[identifier.to_string()], &candidates)
+                    .ok_or(InfoFailure::ShowNotFound)?.show_id.to_string();
+                db::get_show_from_show_id(&show_id).await.map_err(|_| InfoFailure::DBError)?
             }
-            res.push(("Not currently airing:".to_string(), non_airing));
         }
-        Ok(res)
+    };
+    let watchers = db::count_watchers(&show.id).await.map_err(|_| InfoFailure::DBError)?;
+    let latest_release = db::get_latest_release(&show.id).await.map_err(|_| InfoFailure::DBError)?;
+    let on_watchlist = db::does_user_show_exist(user_id, &show.id).await.map_err(|_| InfoFailure::DBError)?;
+    Ok(ShowInfo { show, watchers, latest_release, on_watchlist })
+}
+
+/// The watchlist of a user as an iCalendar file.
+pub async fn get_calendar(config: &Config, user_id: i64) -> Result<String, ()> {
+    let shows = get_user_shows(user_id).await?;
+    Ok(ical::calendar(&shows, config.schedule_timezone, Utc::now()))
+}
+
+/// The calendar of whoever the token of a feed url belongs to.
+pub async fn get_calendar_for_token(config: &Config, token: &str) -> Result<Option<String>, ()> {
+    let user_id = db::get_user_for_calendar_token(token).await
+        .map_err(|e| println!("Error looking up calendar token: {}", e))?;
+    match user_id {
+        Some(user_id) => get_calendar(config, user_id).await.map(Some),
+        None => Ok(None),
     }
 }
 
-pub async fn generate_schedule(user_id: i64) -> Result<ShowTable, ()> {
-    let user_shows = db::get_shows_for_user(user_id)
-        .await.map_err(|_| ())?;
-    let airing_shows: Vec<&Show> = user_shows.iter().filter(|&s| s.air_time.is_airing).collect();
-    let non_airing_shows: Vec<&Show> = user_shows.iter().filter(|&s| !s.air_time.is_airing).collect();
+pub enum CalendarFailure {
+    NotConfigured,
+    DBError,
+}
+
+/// Secret url of the calendar feed of a user. A new token is made on first use and when
+/// `reset` is set.
+pub async fn get_calendar_url(config: &Config, user_id: i64, reset: bool) -> Result<String, CalendarFailure> {
+    let public_url = config.http.public_url.as_ref().ok_or(CalendarFailure::NotConfigured)?;
+    let token = match db::get_calendar_token(user_id).await.map_err(|_| CalendarFailure::DBError)? {
+        Some(token) if !reset => token,
+        _ => {
+            let token: String = rand::thread_rng()
+                .sample_iter(&rand::distributions::Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+            db::set_calendar_token(user_id, &token).await.map_err(|_| CalendarFailure::DBError)?;
+            token
+        }
+    };
+    Ok(format!("{}/ical/{}.ics", public_url, token))
+}
+
+pub enum WebhookFailure {
+    NoWebhook,
+    Failed(WebhookError),
+    NotAllowed(OutboundError),
+    DBError,
+}
+
+/// Saves the url of the webhook of a user and returns its signing secret. The secret stays
+/// when only the url changes, unless `reset` is set.
+pub async fn set_webhook(user_id: i64, url: Option<&str>, reset: bool) -> Result<(String, String), WebhookFailure> {
+    if let Some(url) = url {
+        outbound::check_url(url).await.map_err(WebhookFailure::NotAllowed)?;
+    }
+    let old = db::get_webhook(user_id).await.map_err(|_| WebhookFailure::DBError)?;
+    let (url, secret) = match (url, old) {
+        (Some(url), Some((_, secret))) if !reset => (url.to_string(), secret),
+        (Some(url), _) => (url.to_string(), generate_webhook_secret()),
+        (None, Some((url, _))) => (url, generate_webhook_secret()),
+        (None, None) => return Err(WebhookFailure::NoWebhook),
+    };
+    db::set_webhook(user_id, &url, &secret).await.map_err(|_| WebhookFailure::DBError)?;
+    Ok((url, secret))
+}
 
-    let air_times: HashSet<(i32, i32)> = airing_shows
-        .iter()
-        .map(|s| (s.air_time.est_h, s.air_time.est_m))
+fn generate_webhook_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Url and secret of the webhook of a user.
+pub async fn get_webhook(user_id: i64) -> Result<Option<(String, String)>, ()> {
+    db::get_webhook(user_id).await.map_err(|e| println!("Error fetching webhook: {}", e))
+}
+
+pub async fn remove_webhook(user_id: i64) -> Result<(), ()> {
+    db::delete_webhook(user_id).await.map_err(|e| println!("Error deleting webhook: {}", e))
+}
+
+/// Posts a made up release to the webhook once, without retries, to see whether it works.
+pub async fn test_webhook(user_id: i64) -> Result<(), WebhookFailure> {
+    let (url, secret) = db::get_webhook(user_id).await.map_err(|_| WebhookFailure::DBError)?
+        .ok_or(WebhookFailure::NoWebhook)?;
+    webhooks::send(&url, &secret, "test", &webhooks::test_payload()).await.map_err(WebhookFailure::Failed)
+}
+
+pub enum ChannelFailure {
+    /// The bot has no encryption key to store the token with.
+    NotConfigured,
+    NotLinked,
+    /// The channel can't be turned on before the code it got was sent back.
+    NotConfirmed,
+    WrongCode,
+    Failed(NotifierError),
+    DBError,
+}
+
+/// The channels of a user, without tokens. Discord is always there.
+pub async fn get_channels(user_id: i64) -> Result<Vec<Channel>, ()> {
+    match db::get_notification_channels(user_id).await {
+        Ok(channels) => Ok(notifiers::with_discord(user_id, channels.into_iter().map(|(c, _)| c).collect())),
+        Err(e) => {
+            println!("Error fetching notification channels: {}", e);
+            Err(())
+        }
+    }
+}
+
+/// Sends a confirmation code to the channel and stores it, off, when that worked, with the
+/// token encrypted. It replaces a channel of the same kind. It's turned on by
+/// `confirm_channel` once the user sends the code back.
+pub async fn link_channel(config: &Config, user_id: i64, channel: Channel) -> Result<(), ChannelFailure> {
+    let token = match (&channel.token, &config.encryption_key) {
+        (Some(token), Some(key)) => Some(secrets::encrypt(key, token)),
+        (Some(_), None) => return Err(ChannelFailure::NotConfigured),
+        (None, _) => None,
+    };
+    let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+    let http = Http::new_with_token(&config.discord_token);
+    notifiers::notifier(config, &http, &channel).map_err(ChannelFailure::Failed)?
+        .notify(&Notification::confirmation(channel.kind, &code)).await.map_err(ChannelFailure::Failed)?;
+    let channel = Channel { enabled: false, confirmed: false, ..channel };
+    db::set_notification_channel(user_id, &channel, token.as_deref(), Some(&code)).await
+        .map_err(|_| ChannelFailure::DBError)
+}
+
+/// Turns the channel on when `code` is the one `link_channel` sent it, and says hi there.
+pub async fn confirm_channel(config: &Config, user_id: i64, kind: ChannelKind, code: &str)
+                             -> Result<(), ChannelFailure> {
+    match db::confirm_notification_channel(user_id, kind, code).await {
+        Ok(true) => {}
+        Ok(false) => return Err(ChannelFailure::WrongCode),
+        Err(_) => return Err(ChannelFailure::DBError),
+    }
+    let channels = db::get_notification_channels(user_id).await.map_err(|_| ChannelFailure::DBError)?;
+    if let Some((channel, token)) = channels.iter().find(|(c, _)| c.kind == kind) {
+        let http = Http::new_with_token(&config.discord_token);
+        let sent = match notifiers::notifier(config, &http, &notifiers::decrypted(config, channel, token)) {
+            Ok(notifier) => notifier.notify(&Notification::welcome()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            println!("Error saying hi to the {} of {}: {}", kind.name(), user_id, e);
+        }
+    }
+    Ok(())
+}
+
+pub async fn set_channel_enabled(user_id: i64, kind: ChannelKind, enabled: bool) -> Result<(), ChannelFailure> {
+    if kind == ChannelKind::Discord {
+        let channel = Channel { enabled, ..Channel::discord(user_id) };
+        return db::set_notification_channel(user_id, &channel, None, None).await.map_err(|_| ChannelFailure::DBError);
+    }
+    if enabled {
+        let channels = db::get_notification_channels(user_id).await.map_err(|_| ChannelFailure::DBError)?;
+        if channels.iter().any(|(c, _)| c.kind == kind && !c.confirmed) {
+            return Err(ChannelFailure::NotConfirmed);
+        }
+    }
+    match db::set_notification_channel_enabled(user_id, kind, enabled).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ChannelFailure::NotLinked),
+        Err(_) => Err(ChannelFailure::DBError),
+    }
+}
+
+pub async fn remove_channel(user_id: i64, kind: ChannelKind) -> Result<(), ChannelFailure> {
+    match db::delete_notification_channel(user_id, kind).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ChannelFailure::NotLinked),
+        Err(_) => Err(ChannelFailure::DBError),
+    }
+}
+
+pub enum RemoveFailure {
+    InvalidIdentifier,
+    ShowNotFound,
+    DBError,
+}
+
+pub async fn remove_non_airing(user_id: i64) -> Result<Vec<Show>, RemoveFailure> {
+    db::delete_non_airing_user_shows(user_id)
+        .await.map_err(|_| RemoveFailure::DBError)
+}
+
+pub struct AiringShow {
+    pub title: String,
+    pub show_id: String,
+    pub time: String,
+    pub on_watchlist: bool,
+}
+
+pub enum AiringFailure {
+    ScheduleUnavailable,
+    DBError,
+}
+
+/// Everything subsplease releases on a week day, 0 being Monday, sorted by time.
+pub async fn get_airing(config: &Config, user_id: i64, week_day: usize) -> Result<Vec<AiringShow>, AiringFailure> {
+    let mut schedule = fetch_schedule(config).await.ok_or(AiringFailure::ScheduleUnavailable)?;
+    let watched: HashSet<String> = db::get_shows_for_user(user_id).await
+        .map_err(|_| AiringFailure::DBError)?
+        .into_iter()
+        .map(|s| s.id)
+        .collect();
+    let day = AirTime::weekdays()[week_day % 7];
+    let mut shows: Vec<AiringShow> = schedule.schedule.remove(day).unwrap_or_default()
+        .into_iter()
+        .map(|s| AiringShow { on_watchlist: watched.contains(&s.page), title: s.title, show_id: s.page, time: s.time })
         .collect();
+    shows.sort_by(|a, b| (&a.time, &a.title).cmp(&(&b.time, &b.title)));
+    Ok(shows)
+}
+
+/// Shows that first appeared in the current season, and whether each is on the watchlist.
+pub async fn get_season(config: &Config, user_id: i64) -> Result<(String, Vec<(String, String, bool)>), ()> {
+    let season = season::current_season(config);
+    let shows = db::get_season_shows(&season).await
+        .map_err(|e| println!("Error fetching season: {}", e))?;
+    let watched: HashSet<String> = get_user_shows(user_id).await?.into_iter().map(|s| s.id).collect();
+    let shows = shows.into_iter()
+        .map(|(id, title)| {
+            let on_watchlist = watched.contains(&id);
+            (id, title, on_watchlist)
+        })
+        .collect();
+    Ok((season, shows))
+}
+
+pub async fn set_season_digest(user_id: i64, enabled: bool) -> Result<(), ()> {
+    db::set_season_digest(user_id, enabled).await.map_err(|e| println!("Error saving digest setting: {}", e))
+}
 
+pub async fn get_delivery_settings(user_id: i64) -> Result<DeliverySettings, ()> {
+    db::get_delivery_settings(user_id).await.map_err(|e| println!("Error fetching delivery settings: {}", e))
+}
+
+pub async fn set_delivery_mode(user_id: i64, mode: DeliveryMode) -> Result<(), ()> {
+    db::set_delivery_mode(user_id, mode).await.map_err(|e| println!("Error saving delivery mode: {}", e))
+}
+
+pub async fn set_quiet_hours(user_id: i64, quiet_hours: Option<QuietHours>) -> Result<(), ()> {
+    db::set_quiet_hours(user_id, quiet_hours).await.map_err(|e| println!("Error saving quiet hours: {}", e))
+}
+
+pub async fn set_timezone(user_id: i64, timezone: Tz) -> Result<(), ()> {
+    db::set_user_timezone(user_id, timezone.name()).await.map_err(|e| println!("Error saving timezone: {}", e))
+}
+
+pub enum PremiumizeFailure {
+    /// The bot has no encryption key to store api keys with.
+    NotConfigured,
+    InvalidKey(String),
+    Unreachable,
+    DBError,
+}
 
-    let mut week_days = Vec::new();
-    for day in 0..=6 {
-        let mut day_vec = Vec::new();
-        for &(h, m) in air_times.iter() {
-            let show_names =  airing_shows
-                .iter()
-                .filter(|&&s|
-                    s.air_time.est_week_day == day &&
-                        s.air_time.est_h == h &&
-                        s.air_time.est_m == m)
-                .map(|s| s.name.to_string())
-                .reduce(|a, b| format!("{}, {}", a, b));
-            match show_names {
-                Some(s) => day_vec.push(s),
-                None => day_vec.push("".to_string())
-            };
+/// Checks the api key with Premiumize and stores it encrypted.
+pub async fn set_premiumize_key(config: &Config, user_id: i64, api_key: &str) -> Result<(), PremiumizeFailure> {
+    let encryption_key = config.encryption_key.as_ref().ok_or(PremiumizeFailure::NotConfigured)?;
+    premiumize::check_api_key(&config.premiumize, api_key).await.map_err(|e| match e {
+        PremiumizeError::Rejected(reason) => PremiumizeFailure::InvalidKey(reason),
+        e => {
+            println!("Error checking premiumize key: {:?}", e);
+            PremiumizeFailure::Unreachable
         }
-        week_days.push(day_vec);
+    })?;
+    db::set_premiumize_key(user_id, &secrets::encrypt(encryption_key, api_key)).await
+        .map_err(|_| PremiumizeFailure::DBError)
+}
+
+pub async fn has_premiumize_key(user_id: i64) -> Result<bool, ()> {
+    db::has_premiumize_key(user_id).await.map_err(|e| println!("Error fetching premiumize key: {}", e))
+}
+
+pub async fn remove_premiumize_key(user_id: i64) -> Result<(), ()> {
+    db::delete_premiumize_key(user_id).await.map_err(|e| println!("Error deleting premiumize key: {}", e))
+}
+
+pub enum ClientFailure {
+    /// The bot has no encryption key to store passwords with.
+    NotConfigured,
+    NoClient,
+    Client(DownloadError),
+    DBError,
+}
+
+/// Checks that the client is reachable and takes the credentials, then stores it with the
+/// password encrypted. The category and save path of an older client are kept.
+pub async fn set_download_client(config: &Config, user_id: i64, mut settings: ClientSettings) -> Result<(), ClientFailure> {
+    let password = match (&settings.password, &config.encryption_key) {
+        (Some(password), Some(key)) => Some(secrets::encrypt(key, password)),
+        (Some(_), None) => return Err(ClientFailure::NotConfigured),
+        (None, _) => None,
+    };
+    if let Some((old, _)) = db::get_download_client(user_id).await.map_err(|_| ClientFailure::DBError)? {
+        settings.options = old.options;
     }
+    downloads::sink(settings.clone()).check().await.map_err(ClientFailure::Client)?;
+    db::set_download_client(user_id, &settings, password.as_deref()).await.map_err(|_| ClientFailure::DBError)
+}
 
+/// The download client of a user, without password.
+pub async fn get_download_client(user_id: i64) -> Result<Option<ClientSettings>, ()> {
+    db::get_download_client(user_id).await
+        .map(|client| client.map(|(settings, _)| settings))
+        .map_err(|e| println!("Error fetching download client: {}", e))
+}
 
-    Ok(ShowTable {
-        release_times: air_times.iter().map(|&(h, m)| format!("{:02}:{:02}", h, m)).collect(),
-        days: AirTime::weekdays().iter().map(|s| s.to_string()).collect(),
-        shows: week_days,
-        non_airing: non_airing_shows.iter().map(|s| s.name.to_owned()).collect(),
-    })
+pub async fn set_download_options(user_id: i64, change: impl FnOnce(&mut DownloadOptions))
+                                  -> Result<DownloadOptions, ClientFailure> {
+    let (settings, _) = db::get_download_client(user_id).await.map_err(|_| ClientFailure::DBError)?
+        .ok_or(ClientFailure::NoClient)?;
+    let mut options = settings.options;
+    change(&mut options);
+    db::set_download_options(user_id, &options).await.map_err(|_| ClientFailure::DBError)?;
+    Ok(options)
+}
+
+pub async fn remove_download_client(user_id: i64) -> Result<(), ()> {
+    db::delete_download_client(user_id).await.map_err(|e| println!("Error deleting download client: {}", e))
+}
+
+pub async fn generate_schedule(user_id: i64) -> Result<Schedule, ()> {
+    let user_shows = db::get_shows_for_user(user_id)
+        .await.map_err(|_| ())?;
+    Ok(Schedule::from_shows(&user_shows))
 }
 
 pub async fn remove_user_show(user_id: i64, identifier: &str) -> Result<(), RemoveFailure> {
     if is_valid_url(identifier) {
-        let show_id = &identifier[29..identifier.len() - 1];
+        let show_id = show_id_from_url(identifier).ok_or(RemoveFailure::ShowNotFound)?;
         if db::does_user_show_exist(user_id, show_id).await.map_err(|_| RemoveFailure::DBError)? {
             db::delete_user_show(user_id, show_id)
                 .await.map_err(|_| RemoveFailure::DBError)?;
@@ -164,3 +693,25 @@ pub async fn remove_user_show(user_id: i64, identifier: &str) -> Result<(), Remo
     }
 }
 
+
+
+#[test]
+fn test_sort_watchlist() {
+    use chrono::TimeZone;
+    use crate::subs_pls::page_parser::test_show;
+    let entry = |name: &str, day: i32, h: i32, added: i64| WatchlistEntry {
+        show: test_show(&name.to_lowercase(), name,
+                        AirTime { is_airing: day >= 0, est_week_day: day, est_h: h, est_m: 0 }),
+        added_at: Utc.timestamp_opt(added, 0).unwrap(),
+    };
+    let entries = || vec![entry("kingdom", 6, 9, 3), entry("Boruto", 6, 3, 1), entry("Aria", -1, -1, 2),
+                          entry("One Piece", 0, 10, 4)];
+    let names = |entries: Vec<WatchlistEntry>| entries.into_iter().map(|e| e.show.name).collect::<Vec<String>>();
+    assert_eq!(names(sort_watchlist(entries(), ListSort::Name, ListFilter::All)),
+               vec!["Aria", "Boruto", "kingdom", "One Piece"]);
+    assert_eq!(names(sort_watchlist(entries(), ListSort::AirDay, ListFilter::All)),
+               vec!["One Piece", "Boruto", "kingdom", "Aria"]);
+    assert_eq!(names(sort_watchlist(entries(), ListSort::Added, ListFilter::Airing)),
+               vec!["One Piece", "kingdom", "Boruto"]);
+    assert_eq!(names(sort_watchlist(entries(), ListSort::Name, ListFilter::NonAiring)), vec!["Aria"]);
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use crate::subs_pls::page_parser::{is_valid_url, Show};
+use crate::user_data::Preferences;
+
+pub const SHOW_URL_PREFIX: &str = "https://subsplease.org/shows/";
+
+#[derive(Serialize, Deserialize)]
+struct ExportedShow {
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    url: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedWatchlist {
+    shows: Vec<ExportedShow>,
+}
+
+/// The json export. The preferences are for reference, imports only read the shows.
+#[derive(Serialize)]
+struct ExportedData<'a> {
+    shows: Vec<ExportedShow>,
+    preferences: &'a Preferences,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    InvalidJson(String),
+    InvalidCsv(String),
+    NotText,
+}
+
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "watchlist.json",
+            ExportFormat::Csv => "watchlist.csv",
+        }
+    }
+}
+
+pub fn show_url(show_id: &str) -> String {
+    format!("{}{}/", SHOW_URL_PREFIX, show_id)
+}
+
+fn to_exported(shows: &[Show]) -> Vec<ExportedShow> {
+    shows.iter()
+        .map(|s| ExportedShow { id: s.id.to_string(), name: s.name.to_string(), url: show_url(&s.id) })
+        .collect()
+}
+
+/// Csv files only have room for the shows.
+pub fn export(shows: &[Show], preferences: &Preferences, format: ExportFormat) -> Vec<u8> {
+    match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(&ExportedData { shows: to_exported(shows), preferences })
+            .unwrap_or_default(),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for show in to_exported(shows) {
+                writer.serialize(show).ok();
+            }
+            writer.into_inner().unwrap_or_default()
+        }
+    }
+}
+
+/// Turns an uploaded file into show urls. Exports of this bot (json or csv) are read by
+/// show id, anything else is treated as text with one identifier per line.
+pub fn parse_import(file_name: &str, data: &[u8]) -> Result<Vec<String>, ImportError> {
+    let lower_name = file_name.to_ascii_lowercase();
+    if lower_name.ends_with(".json") {
+        let watchlist: ExportedWatchlist = serde_json::from_slice(data)
+            .map_err(|e| ImportError::InvalidJson(e.to_string()))?;
+        Ok(watchlist.shows.iter().map(|s| show_url(&s.id)).collect())
+    } else if lower_name.ends_with(".csv") {
+        let mut reader = csv::Reader::from_reader(data);
+        let mut urls = Vec::new();
+        for record in reader.deserialize::<ExportedShow>() {
+            let show = record.map_err(|e| ImportError::InvalidCsv(e.to_string()))?;
+            urls.push(show_url(&show.id));
+        }
+        Ok(urls)
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| ImportError::NotText)?;
+        Ok(parse_identifier_lines(text))
+    }
+}
+
+/// Splits a message or text file into identifiers. Lines made up only of show urls may
+/// hold several of them.
+pub fn parse_identifier_lines(text: &str) -> Vec<String> {
+    let mut identifiers = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.iter().all(|p| is_valid_url(p)) {
+            identifiers.extend(parts.iter().map(|p| p.to_string()));
+        } else {
+            identifiers.push(line.to_string());
+        }
+    }
+    identifiers
+}
+
+
+#[test]
+fn test_export_import_round_trip() {
+    use crate::subs_pls::page_parser::{test_show, AirTime};
+    let show = |id: &str, name: &str|
+        test_show(id, name, AirTime { is_airing: false, est_week_day: -1, est_h: -1, est_m: -1 });
+    let shows = vec![show("one-piece", "One Piece"), show("kingdom-s3", "Kingdom, Season 3")];
+    let urls = vec!["https://subsplease.org/shows/one-piece/".to_string(),
+                    "https://subsplease.org/shows/kingdom-s3/".to_string()];
+    let preferences = Preferences {
+        delivery: "daily at 18:00".to_string(),
+        quiet_hours: Some("23:00 to 07:00".to_string()),
+        timezone: Some("Europe/Berlin".to_string()),
+        season_digest: true,
+    };
+
+    let csv = export(&shows, &preferences, ExportFormat::Csv);
+    assert!(String::from_utf8(csv.clone()).unwrap().starts_with("id,name,url\none-piece,One Piece,"));
+    assert_eq!(parse_import("watchlist.csv", &csv).unwrap(), urls);
+    let json = export(&shows, &preferences, ExportFormat::Json);
+    assert_eq!(parse_import("Watchlist.JSON", &json).unwrap(), urls);
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(value["preferences"]["delivery"], "daily at 18:00");
+    assert_eq!(value["preferences"]["season_digest"], true);
+}
+
+#[test]
+fn test_parse_import() {
+    assert_eq!(parse_import("list.txt", b"https://subsplease.org/shows/one-piece/ https://subsplease.org/shows/boruto/\n\n  One Piece \n").unwrap(),
+               vec!["https://subsplease.org/shows/one-piece/", "https://subsplease.org/shows/boruto/", "One Piece"]);
+    assert!(matches!(parse_import("watchlist.json", b"{\"shows\": 3}"), Err(ImportError::InvalidJson(_))));
+    assert!(matches!(parse_import("watchlist.csv", b"name\nOne Piece"), Err(ImportError::InvalidCsv(_))));
+    assert_eq!(parse_import("list.txt", &[0xff, 0xfe]), Err(ImportError::NotText));
+}
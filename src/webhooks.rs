@@ -0,0 +1,219 @@
+//! Webhooks users can have new releases posted to. The body is signed with a secret only the
+//! user knows, so their automation can tell the requests come from the bot.
+
+use std::fmt;
+use std::time::Duration;
+
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::outbound::{self, OutboundError};
+use crate::subs_pls::page_parser::{AirTime, ExternalIds, Show};
+use crate::subs_pls::release_parser::FeedItem;
+use crate::watchlist_file::show_url;
+
+/// `sha256=` and the hex HMAC-SHA256 of the body under the secret of the webhook.
+pub const SIGNATURE_HEADER: &str = "X-Yukino-Signature";
+/// `release` or `test`.
+pub const EVENT_HEADER: &str = "X-Yukino-Event";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The pauses before trying a failed delivery again, so a short outage doesn't lose releases.
+pub const RETRY_DELAYS: [Duration; 3] = [Duration::from_secs(10), Duration::from_secs(60), Duration::from_secs(300)];
+
+#[derive(Debug, PartialEq)]
+pub enum WebhookError {
+    /// The webhook answered with this status code.
+    Status(u16),
+    Request(String),
+    /// The url leads somewhere the bot doesn't post to.
+    NotAllowed(OutboundError),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookError::Status(code) => write!(f, "it answered with {}", code),
+            WebhookError::Request(e) => write!(f, "couldn't reach it ({})", e),
+            WebhookError::NotAllowed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl WebhookError {
+    /// Timeouts, server errors and rate limits might go away, anything else won't.
+    fn is_temporary(&self) -> bool {
+        match self {
+            WebhookError::Status(code) => *code >= 500 || *code == 429,
+            WebhookError::Request(_) => true,
+            WebhookError::NotAllowed(_) => false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'a str,
+    show: ShowPayload<'a>,
+    release: ReleasePayload<'a>,
+}
+
+#[derive(Serialize)]
+struct ShowPayload<'a> {
+    id: &'a str,
+    name: &'a str,
+    url: String,
+    image_url: &'a str,
+    synopsis: &'a str,
+    season: Option<&'a str>,
+    is_airing: bool,
+    mal_id: Option<i64>,
+    anilist_id: Option<i64>,
+    kitsu_id: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ReleasePayload<'a> {
+    title: &'a str,
+    episode: Option<String>,
+    link: &'a str,
+    guid: &'a str,
+    pub_date: &'a str,
+    category: &'a str,
+    file_size: &'a str,
+}
+
+/// The JSON body posted for a release.
+pub fn payload(event: &str, show: &Show, item: &FeedItem) -> String {
+    let payload = Payload {
+        event,
+        show: ShowPayload {
+            id: &show.id,
+            name: &show.name,
+            url: show_url(&show.id),
+            image_url: &show.image_url,
+            synopsis: &show.synopsis,
+            season: show.season.as_deref(),
+            is_airing: show.air_time.is_airing,
+            mal_id: show.external_ids.mal,
+            anilist_id: show.external_ids.anilist,
+            kitsu_id: show.external_ids.kitsu,
+        },
+        release: ReleasePayload {
+            title: &item.title,
+            episode: item.episode(),
+            link: &item.link,
+            guid: &item.guid,
+            pub_date: &item.pub_date,
+            category: &item.category,
+            file_size: &item.file_size,
+        },
+    };
+    serde_json::to_string(&payload).unwrap_or_default()
+}
+
+/// A made up release for `webhook test`, in the same shape as the real ones.
+pub fn test_payload() -> String {
+    let show = Show {
+        id: "one-piece".to_string(),
+        name: "One Piece".to_string(),
+        image_url: "https://subsplease.org/wp-content/uploads/2019/10/one-piece.jpg".to_string(),
+        synopsis: "Gol D. Roger was known as the Pirate King.".to_string(),
+        air_time: AirTime { is_airing: true, est_week_day: 6, est_h: 2, est_m: 15 },
+        external_ids: ExternalIds { mal: Some(21), anilist: Some(21), kitsu: Some(12), resolved: true },
+        season: Some("Fall 1999".to_string()),
+    };
+    let item = FeedItem {
+        title: "[SubsPlease] One Piece - 1000 (1080p) [F9A3D1B8].mkv".to_string(),
+        link: "magnet:?xt=urn:btih:0000000000000000000000000000000000000000".to_string(),
+        guid: "TEST".to_string(),
+        pub_date: "Sun, 21 Nov 2021 02:15:00 +0000".to_string(),
+        category: "One Piece - 1080".to_string(),
+        file_size: "1.4 GiB".to_string(),
+    };
+    payload("test", &show, &item)
+}
+
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(body.as_bytes());
+    let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", signature)
+}
+
+/// Posts the body once. Any 2xx counts as delivered. The url is checked every time, as the
+/// address its host points to can change.
+pub async fn send(url: &str, secret: &str, event: &str, body: &str) -> Result<(), WebhookError> {
+    outbound::check_url(url).await.map_err(WebhookError::NotAllowed)?;
+    let res = outbound::client(REQUEST_TIMEOUT)
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, event)
+        .header(SIGNATURE_HEADER, sign(secret, body))
+        .body(body.to_string())
+        .send().await
+        .map_err(|e| WebhookError::Request(e.to_string()))?;
+    match res.status().as_u16() {
+        200..=299 => Ok(()),
+        code => Err(WebhookError::Status(code)),
+    }
+}
+
+/// Like `send`, but tries again after each of `delays` while the error might go away.
+pub async fn send_with_retries(url: &str, secret: &str, event: &str, body: &str,
+                               delays: &[Duration]) -> Result<(), WebhookError> {
+    let mut result = send(url, secret, event, body).await;
+    for delay in delays {
+        match &result {
+            Err(e) if e.is_temporary() => {}
+            _ => break,
+        }
+        tokio::time::sleep(*delay).await;
+        result = send(url, secret, event, body).await;
+    }
+    result
+}
+
+
+#[test]
+fn test_sign() {
+    // RFC 4231 test case 2
+    assert_eq!(sign("Jefe", "what do ya want for nothing?"),
+               "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+}
+
+#[test]
+fn test_payload_json() {
+    let body: serde_json::Value = serde_json::from_str(&test_payload()).unwrap();
+    assert_eq!(body["event"], "test");
+    assert_eq!(body["show"]["url"], "https://subsplease.org/shows/one-piece/");
+    assert_eq!(body["show"]["mal_id"], 21);
+    assert_eq!(body["release"]["episode"], "1000");
+}
+
+#[tokio::test]
+async fn test_send_with_retries() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::test_server::{reply, StubServer};
+    let calls = std::sync::Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let server = StubServer::start(move |req| match req.path.as_str() {
+        "/flaky" if counter.fetch_add(1, Ordering::SeqCst) < 2 => reply(503, "busy"),
+        "/flaky" => reply(204, ""),
+        _ => reply(404, "not found"),
+    }).await;
+
+    let body = test_payload();
+    let delays = [Duration::ZERO; 3];
+    let url = format!("{}/flaky", server.url);
+    assert_eq!(send_with_retries(&url, "secret", "test", &body, &delays).await, Ok(()));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[2].headers[SIGNATURE_HEADER], sign("secret", &body).as_str());
+    assert_eq!(requests[2].headers[EVENT_HEADER], "test");
+    assert_eq!(String::from_utf8_lossy(&requests[2].body), body);
+
+    let missing = format!("{}/missing", server.url);
+    assert_eq!(send_with_retries(&missing, "secret", "test", &body, &delays).await, Err(WebhookError::Status(404)));
+    assert_eq!(server.requests().len(), 4);
+}